@@ -0,0 +1,216 @@
+use crate::class::{decrement, increment, CharClass, ClassRange};
+use crate::look::Context;
+use crate::thompson::NFA;
+
+use std::collections::HashMap;
+
+pub type ClassId = usize;
+
+// With assertions all characters of a class must also have the same Context.
+const CONTEXT_RANGES: [ClassRange; 5] = [
+    ClassRange::new('\n', '\n'),
+    ClassRange::new('0', '9'),
+    ClassRange::new('A', 'Z'),
+    ClassRange::new('_', '_'),
+    ClassRange::new('a', 'z')
+];
+
+// Partition of the characters an NFA uses into equivalence classes. Two characters
+// are in the same class when every edge of the NFA either takes both or none of them,
+// so an automaton only needs one transition per class instead of one per character.
+//
+// Characters are first split into elementary intervals, the maximal ranges that no
+// edge splits, and intervals with the same edges are then merged into one class.
+// "[a-z]+|[0-9]x" has classes [0-9], [a-wyz] and [x]. Characters that no edge
+// takes belong to no class.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Alphabet {
+    // sorted elementary intervals and the class of each of them
+    intervals: Vec<ClassRange>,
+    ids: Vec<ClassId>,
+    classes: Vec<CharClass>
+}
+
+impl Alphabet {
+    pub fn from_nfa(nfa: &NFA) -> Alphabet {
+        let symbols = (0..nfa.len())
+            .flat_map(|state| nfa.transitions(state))
+            .filter_map(|transition| transition.symbol().class());
+        Alphabet::from_classes(symbols, nfa.has_looks())
+    }
+
+    // partition of the characters of classes, has_looks also splits them by Context
+    pub fn from_classes<'c>(classes: impl IntoIterator<Item = &'c CharClass>, has_looks: bool) -> Alphabet {
+        let mut symbols: Vec<&CharClass> = classes.into_iter().collect();
+        symbols.sort();
+        symbols.dedup();
+
+        let ranges = symbols
+            .iter()
+            .flat_map(|class| class.ranges())
+            .copied()
+            .collect::<Vec<_>>();
+        let contexts = if has_looks { CONTEXT_RANGES.as_slice() } else { &[] };
+        let intervals = elementary_intervals(&ranges, contexts);
+
+        // intervals are in the same class when they are taken by the same edges
+        let mut alphabet = Alphabet::default();
+        let mut signatures: HashMap<(Vec<usize>, Context), ClassId> = HashMap::new();
+        for interval in intervals {
+            let edges = symbols
+                .iter()
+                .enumerate()
+                .filter(|(_, class)| class.contains(interval.start()))
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            let context = if has_looks { Context::of(Some(interval.start())) } else { Context::Edge };
+
+            let next_id = alphabet.classes.len();
+            let id = *signatures.entry((edges, context)).or_insert(next_id);
+            if id == next_id {
+                alphabet.classes.push(CharClass::empty());
+            }
+            alphabet.classes[id].push(interval.start(), interval.end());
+            alphabet.intervals.push(interval);
+            alphabet.ids.push(id);
+        }
+        alphabet
+    }
+
+    // number of classes, they are numbered 0..len()
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    // class of char, None when no edge takes it
+    pub fn class_of(&self, char: char) -> Option<ClassId> {
+        let index = self.intervals
+            .binary_search_by(|interval| {
+                if interval.end() < char {
+                    std::cmp::Ordering::Less
+                } else if interval.start() > char {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .ok()?;
+        Some(self.ids[index])
+    }
+
+    pub fn class(&self, id: ClassId) -> &CharClass {
+        &self.classes[id]
+    }
+
+    // classes in the order of their ids
+    pub fn classes(&self) -> impl Iterator<Item = &CharClass> {
+        self.classes.iter()
+    }
+
+    // some character of the class, all of them behave the same
+    pub fn representative(&self, id: ClassId) -> char {
+        self.classes[id].ranges()[0].start()
+    }
+
+    // character of the class to show to people, a visible ASCII one where the class has it
+    pub fn readable(&self, id: ClassId) -> char {
+        let class = &self.classes[id];
+        ('!'..='~')
+            .chain([' '])
+            .find(|char| class.contains(*char))
+            .unwrap_or_else(|| self.representative(id))
+    }
+
+    // sorted elementary intervals together with their class
+    pub fn intervals(&self) -> impl Iterator<Item = (ClassRange, ClassId)> + '_ {
+        self.intervals.iter().copied().zip(self.ids.iter().copied())
+    }
+}
+
+// splits characters of ranges into maximal intervals that every range either contains
+// completely or not at all, boundaries also splits them but does not add characters.
+fn elementary_intervals(ranges: &[ClassRange], boundaries: &[ClassRange]) -> Vec<ClassRange> {
+    // an interval starts at every range start and right after every range end
+    let mut starts = ranges
+        .iter()
+        .chain(boundaries)
+        .flat_map(|range| [Some(range.start()), increment(range.end())])
+        .flatten()
+        .collect::<Vec<_>>();
+    starts.sort_unstable();
+    starts.dedup();
+
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|start| decrement(*start))
+        .chain([Some(char::MAX)]);
+    starts
+        .iter()
+        .zip(ends)
+        .filter_map(|(start, end)| end.map(|end| ClassRange::new(*start, end)))
+        .filter(|interval| ranges.iter().any(|range| range.contains(interval.start())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::class::{CharClass, ClassRange};
+    use crate::thompson::NFA;
+
+    use super::Alphabet;
+
+    fn alphabet(pattern: &str) -> Alphabet {
+        let Ok(nfa) = NFA::from_pattern(pattern) else {
+            panic!("failed to compile {}", pattern)
+        };
+        Alphabet::from_nfa(&nfa)
+    }
+
+    fn class(ranges: &[(char, char)]) -> CharClass {
+        CharClass::new(ranges.iter().map(|(start, end)| ClassRange::new(*start, *end)))
+    }
+
+    #[test]
+    fn intervals_with_same_edges_share_class() {
+        let alphabet = alphabet("[a-z]+|[0-9a-f]x");
+        let intervals = alphabet.intervals().map(|(interval, _)| interval).collect::<Vec<_>>();
+        assert_eq!(intervals, vec![
+            ClassRange::new('0', '9'),
+            ClassRange::new('a', 'f'),
+            ClassRange::new('g', 'w'),
+            ClassRange::new('x', 'x'),
+            ClassRange::new('y', 'z'),
+        ]);
+        assert_eq!(alphabet.len(), 4);
+        assert_ne!(alphabet.class_of('0'), alphabet.class_of('c'));
+        assert_eq!(alphabet.class_of('g'), alphabet.class_of('z'));
+        assert_ne!(alphabet.class_of('x'), alphabet.class_of('y'));
+        assert_eq!(alphabet.class_of('!'), None);
+
+        let Some(id) = alphabet.class_of('h') else {
+            panic!("h is in a class")
+        };
+        assert_eq!(alphabet.class(id), &class(&[('g', 'w'), ('y', 'z')]));
+    }
+
+    #[test]
+    fn large_classes_stay_small() {
+        let alphabet = alphabet("[^\"]*\"|\\w");
+        assert_eq!(alphabet.len(), 3);
+        assert!(alphabet.class_of('ö').is_some());
+        assert_eq!(alphabet.class_of('ö'), alphabet.class_of('\u{10FFFF}'));
+    }
+
+    #[test]
+    fn assertions_split_classes_by_context() {
+        assert_eq!(alphabet(".+").len(), 1);
+        let alphabet = alphabet(".+\\b");
+        assert_eq!(alphabet.len(), 2);
+        assert_ne!(alphabet.class_of('a'), alphabet.class_of(' '));
+    }
+}
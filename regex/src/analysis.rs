@@ -0,0 +1,145 @@
+use crate::ast::Expr;
+use crate::dfa::DFA;
+
+// Relations between the languages of expressions, the sets of strings they match as
+// a whole like DFA::is_match does. Both expressions are compiled to minimized DFAs and
+// the relation is decided on their product: left is a subset of right exactly when
+// the DFA of left minus right accepts nothing. When a relation does not hold, the
+// shortest string that shows it comes back as the error, e.g. for checking that a
+// rewrite of an expression did not change what it matches:
+//
+//     assert_eq!(equivalent(&expr, &simplify(&expr)), Ok(()));
+//
+// Expressions can use the extended syntax. Assertions are resolved the same way
+// as in a DFA, "^a$" and "a" have the same language.
+
+// expression the counterexample is matched by, the other one does not match it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Side {
+    Left,
+    Right
+}
+
+// string that one expression matches and the other one does not
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Counterexample {
+    input: String,
+    matched_by: Side
+}
+
+impl Counterexample {
+    fn new(input: String, matched_by: Side) -> Counterexample {
+        Counterexample {
+            input,
+            matched_by
+        }
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn matched_by(&self) -> Side {
+        self.matched_by
+    }
+}
+
+// Ok when both match the same strings, otherwise the shortest string matched by only one
+// of them, the left one when both differences have a string of that length
+pub fn equivalent(left: &Expr, right: &Expr) -> Result<(), Counterexample> {
+    let left = DFA::from_expr(left);
+    let right = DFA::from_expr(right);
+    let only_left = left.difference(&right).shortest_string();
+    let only_right = right.difference(&left).shortest_string();
+    match (only_left, only_right) {
+        (None, None) => Ok(()),
+        (Some(input), None) => Err(Counterexample::new(input, Side::Left)),
+        (None, Some(input)) => Err(Counterexample::new(input, Side::Right)),
+        (Some(left), Some(right)) if right.chars().count() < left.chars().count() => {
+            Err(Counterexample::new(right, Side::Right))
+        },
+        (Some(left), Some(_)) => Err(Counterexample::new(left, Side::Left))
+    }
+}
+
+// Ok when right matches every string left matches, otherwise the shortest string only left matches
+pub fn is_subset(left: &Expr, right: &Expr) -> Result<(), Counterexample> {
+    let difference = DFA::from_expr(left).difference(&DFA::from_expr(right));
+    match difference.shortest_string() {
+        None => Ok(()),
+        Some(input) => Err(Counterexample::new(input, Side::Left))
+    }
+}
+
+// Ok when expr matches nothing, otherwise the shortest string it matches
+pub fn is_empty(expr: &Expr) -> Result<(), Counterexample> {
+    match DFA::from_expr(expr).shortest_string() {
+        None => Ok(()),
+        Some(input) => Err(Counterexample::new(input, Side::Left))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::simplify::simplify;
+    use crate::ast::Expr;
+    use crate::parser::{parse, parse_with, Config};
+
+    use super::{equivalent, is_empty, is_subset, Counterexample, Side};
+
+    fn expr(pattern: &str) -> Expr {
+        match parse_with(pattern, &Config::new().extended(true)) {
+            Ok(expr) => expr,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    fn counterexample(input: &str, matched_by: Side) -> Result<(), Counterexample> {
+        Err(Counterexample::new(input.to_string(), matched_by))
+    }
+
+    #[test]
+    fn equivalence() {
+        assert_eq!(equivalent(&expr("(a|b)*"), &expr("(a*b*)*")), Ok(()));
+        assert_eq!(equivalent(&expr("a(b|c)"), &expr("ab|ac")), Ok(()));
+        assert_eq!(equivalent(&expr("~(~a&~b)"), &expr("a|b")), Ok(()));
+        assert_eq!(equivalent(&expr("a*"), &expr("a+")), counterexample("", Side::Left));
+        assert_eq!(equivalent(&expr("[a-c]{2}"), &expr("[a-c]{2,3}")), counterexample("aaa", Side::Right));
+        assert_eq!(equivalent(&expr("ab|b"), &expr("a|b")), counterexample("a", Side::Right));
+    }
+
+    #[test]
+    fn inclusion() {
+        assert_eq!(is_subset(&expr("if|else"), &expr("[a-z]+")), Ok(()));
+        assert_eq!(is_subset(&expr("[a-z]+"), &expr("\\w+")), Ok(()));
+        assert_eq!(is_subset(&expr("\\w+"), &expr("[a-z]+")), counterexample("0", Side::Left));
+        assert_eq!(is_subset(&expr("[0-9]+(\\.[0-9]*)?"), &expr("[0-9]+\\.?[0-9]+")), counterexample("0", Side::Left));
+    }
+
+    #[test]
+    fn emptiness() {
+        assert_eq!(is_empty(&expr("[a-z]+&[0-9]+")), Ok(()));
+        assert_eq!(is_empty(&expr("a~(?s:.*)")), Ok(()));
+        assert_eq!(is_empty(&expr("a~(.*)")), counterexample("a\n", Side::Left));
+        assert_eq!(is_empty(&expr("(ab|cd)&~(ab)")), counterexample("cd", Side::Left));
+        assert_eq!(is_empty(&expr("a*")), counterexample("", Side::Left));
+    }
+
+    #[test]
+    fn checks_rewrites() {
+        for pattern in ["(a|b)(a|b)*c?", "if|iff|in|int", "[a-c]|[d-f]|x", "(?:)|a{2,}b{0,3}"] {
+            let Ok(expr) = parse(pattern) else {
+                panic!("failed to parse {}", pattern)
+            };
+            assert_eq!(equivalent(&expr, &simplify(&expr)), Ok(()), "{}", pattern);
+        }
+
+        // a rewrite that turns an optional into its operand loses the empty string
+        let optional = expr("a?");
+        let Expr::ZeroOrOne(ref operand) = optional else {
+            panic!("a? is optional")
+        };
+        let wrong = operand.expr().clone();
+        assert_eq!(equivalent(&optional, &wrong), counterexample("", Side::Left));
+    }
+}
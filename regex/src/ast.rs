@@ -1,26 +1,56 @@
 use std::collections::VecDeque;
 
-use crate::thompson::NFA;
+use crate::alphabet::ClassId;
+use crate::class::{escape_invisible, CharClass};
+use crate::dfa::{self, DFA};
+use crate::look::Look;
+use crate::utils::graphviz;
+use crate::thompson::{Nfa, StateId, Symbol, NFA};
+
+pub mod fold;
+mod printer;
+pub mod simplify;
 
 const CAPACITY: usize = 1000;
 
-#[derive(Clone)]
+#[derive(Clone, Eq, PartialEq)]
 pub enum Expr {
     Empty, 
     Literal(Box<Literal>),
+    Class(Box<CharClass>),
+    Assertion(Box<Assertion>),
     Repetition(Box<KleeneStar>),
+    ZeroOrOne(Box<ZeroOrOne>),
+    OneOrMany(Box<OneOrMany>),
+    Bounded(Box<BoundedRepetition>),
     Alternation(Box<Alternation>),
     Concatenation(Box<Concatenation>),
+    Intersection(Box<Intersection>),
+    Complement(Box<Complement>),
     Group(Box<Group>),
     Escape(Box<Escape>),
     MetaChar(Box<MetaChar>)
 }
 
 impl Expr {
-
+    // subexpressions in order, empty for leaves
+    pub fn children(&self) -> &[Expr] {
+        match self {
+            Expr::Repetition(rep) => std::slice::from_ref(&rep.expr),
+            Expr::ZeroOrOne(rep) => std::slice::from_ref(&rep.expr),
+            Expr::OneOrMany(rep) => std::slice::from_ref(&rep.expr),
+            Expr::Bounded(rep) => std::slice::from_ref(&rep.expr),
+            Expr::Alternation(alt) => &alt.exprs,
+            Expr::Concatenation(concat) => &concat.exprs,
+            Expr::Intersection(and) => &and.exprs,
+            Expr::Complement(not) => std::slice::from_ref(&not.expr),
+            Expr::Group(group) => std::slice::from_ref(&group.expr),
+            _ => &[]
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Eq, PartialEq)]
 pub struct Literal {
     literal: char
 }
@@ -31,8 +61,30 @@ impl Literal {
             literal 
         }
     }
+
+    pub fn literal(&self) -> char {
+        self.literal
+    }
+}
+// zero width assertion such as ^ or \b
+#[derive(Clone, Eq, PartialEq)]
+pub struct Assertion {
+    look: Look
 }
-#[derive(Clone)]
+
+impl Assertion {
+    pub fn new(look: Look) -> Assertion {
+        Assertion { 
+            look 
+        }
+    }
+
+    pub fn look(&self) -> Look {
+        self.look
+    }
+}
+
+#[derive(Clone, Eq, PartialEq)]
 pub struct KleeneStar {
     expr: Expr
 }
@@ -43,88 +95,366 @@ impl KleeneStar {
             expr    
         }
     }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+// e? 
+#[derive(Clone, Eq, PartialEq)]
+pub struct ZeroOrOne {
+    expr: Expr
+}
+
+impl ZeroOrOne {
+    pub fn new(expr: Expr) -> ZeroOrOne {
+        ZeroOrOne { 
+            expr    
+        }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+// e+
+#[derive(Clone, Eq, PartialEq)]
+pub struct OneOrMany {
+    expr: Expr
+}
+
+impl OneOrMany {
+    pub fn new(expr: Expr) -> OneOrMany {
+        OneOrMany { 
+            expr    
+        }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+// e{min}, e{min,} and e{min,max}
+#[derive(Clone, Eq, PartialEq)]
+pub struct BoundedRepetition {
+    expr: Expr,
+    min: u32,
+    max: Option<u32>
+}
+
+impl BoundedRepetition {
+    pub fn new(expr: Expr, min: u32, max: Option<u32>) -> BoundedRepetition {
+        BoundedRepetition { 
+            expr,
+            min,
+            max
+        }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn min(&self) -> u32 {
+        self.min
+    }
+
+    // None when repetition is unbounded
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
 }
 
-#[derive(Clone)]
+// e1|e2|...|en, alternatives in order of their priority. The parser only builds
+// nodes with two of them, simplification flattens nested ones.
+#[derive(Clone, Eq, PartialEq)]
 pub struct Alternation {
-    left_expr: Expr,
-    right_expr: Expr
+    exprs: Vec<Expr>
 }
 
 impl Alternation {
     pub fn new(left: Expr, right: Expr) -> Alternation {
-        Alternation { 
-            left_expr: left, 
-            right_expr: right
+        Alternation::n_ary(vec![left, right])
+    }
+
+    pub fn n_ary(exprs: Vec<Expr>) -> Alternation {
+        Alternation {
+            exprs
         }
     }
+
+    pub fn exprs(&self) -> &[Expr] {
+        &self.exprs
+    }
+
+    // first alternative
+    pub fn left(&self) -> &Expr {
+        &self.exprs[0]
+    }
+
+    // last alternative
+    pub fn right(&self) -> &Expr {
+        &self.exprs[self.exprs.len() - 1]
+    }
 }
 
-#[derive(Clone)]
+// e1e2...en, like Alternation the parser only builds nodes with two operands
+#[derive(Clone, Eq, PartialEq)]
 pub struct Concatenation {
-    left_expr: Expr,
-    right_expr: Expr
+    exprs: Vec<Expr>
 }
 
 impl Concatenation {
     pub fn new(left: Expr, right: Expr) -> Concatenation {
-        Concatenation { 
-            left_expr: left, 
-            right_expr: right
+        Concatenation::n_ary(vec![left, right])
+    }
+
+    pub fn n_ary(exprs: Vec<Expr>) -> Concatenation {
+        Concatenation {
+            exprs
         }
     }
+
+    pub fn exprs(&self) -> &[Expr] {
+        &self.exprs
+    }
+
+    // first operand
+    pub fn left(&self) -> &Expr {
+        &self.exprs[0]
+    }
+
+    // last operand
+    pub fn right(&self) -> &Expr {
+        &self.exprs[self.exprs.len() - 1]
+    }
 }
 
+// e1&e2&...&en, matches the strings every operand matches. Only the extended
+// syntax has it, like Complement its operands can not have assertions.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Intersection {
+    exprs: Vec<Expr>
+}
 
-#[derive(Clone)]
-pub struct Group {
+impl Intersection {
+    pub fn new(left: Expr, right: Expr) -> Intersection {
+        Intersection::n_ary(vec![left, right])
+    }
+
+    pub fn n_ary(exprs: Vec<Expr>) -> Intersection {
+        Intersection {
+            exprs
+        }
+    }
+
+    pub fn exprs(&self) -> &[Expr] {
+        &self.exprs
+    }
+}
+
+// ~e, matches every string e does not match
+#[derive(Clone, Eq, PartialEq)]
+pub struct Complement {
     expr: Expr
 }
 
+impl Complement {
+    pub fn new(expr: Expr) -> Complement {
+        Complement {
+            expr
+        }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+
+// (e), capturing groups have an index and optionally a name, "(?:e)" has neither
+#[derive(Clone, Eq, PartialEq)]
+pub struct Group {
+    expr: Expr,
+    index: Option<usize>,
+    name: Option<String>
+}
+
 impl Group {
     pub fn new(expr: Expr) -> Group {
         Group {
-            expr
+            expr,
+            index: None,
+            name: None
         }
     }
+
+    pub fn capturing(expr: Expr, index: usize, name: Option<String>) -> Group {
+        Group {
+            expr,
+            index: Some(index),
+            name
+        }
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 }
 
-#[derive(Clone)]
+// shorthand class such as \d or \W, escape is the letter after the backslash
+#[derive(Clone, Eq, PartialEq)]
 pub struct Escape {
-    expr: Expr
+    escape: char,
+    class: CharClass
 }
 
 impl Escape {
-    pub fn new(expr: Expr) -> Escape {
+    pub fn new(escape: char, class: CharClass) -> Escape {
         Escape { 
-            expr    
+            escape,
+            class
         }
     }
+
+    pub fn escape(&self) -> char {
+        self.escape
+    }
+
+    pub fn class(&self) -> &CharClass {
+        &self.class
+    }
 }
+
+// metacharacter that stands for a set of characters, i.e. "." whose class
+// depends on whether it matches a new line
+#[derive(Clone, Eq, PartialEq)]
+pub struct MetaChar {
+    meta: char,
+    class: CharClass
+}
+
+impl MetaChar {
+    pub fn new(meta: char, class: CharClass) -> MetaChar {
+        MetaChar { 
+            meta,
+            class
+        }
+    }
+
+    pub fn meta(&self) -> char {
+        self.meta
+    }
+
+    pub fn class(&self) -> &CharClass {
+        &self.class
+    }
+}
+
 pub trait Visitor {
-    fn start(&self);
+    fn start(&mut self);
     fn visit_pre(&mut self, ast: &Expr);
     fn visit_in(&mut self, ast: &Expr);
     fn visit_post(&mut self, ast: &Expr);
-    fn finish(&self, ast: &Expr);
+    fn finish(&mut self, ast: &Expr);
 }
-type Nfa = (usize, usize);
 
+// Builds Thompson NFA bottom up, every visited node pops fragments of its 
+// children from args and pushes its own fragment. 
 pub struct ThompsonVisitor {
     args: VecDeque<Nfa>,
+    // first state of every counted repetition that is being built, 
+    // its fragment has to be copied once it is finished. & and ~ are
+    // built as DFAs, fragments of their operands are dropped from there.
+    marks: Vec<StateId>,
     nfa: NFA,
 }
 
 impl Visitor for ThompsonVisitor {
-    fn start(&self) {    
+    fn start(&mut self) {    
+        self.args.clear();
+        self.marks.clear();
+        self.nfa.clear();
     }
     fn visit_pre(&mut self, ast: &Expr) {
+        if let Expr::Bounded(_) | Expr::Intersection(_) | Expr::Complement(_) = ast {
+            self.marks.push(self.nfa.len());
+        }
     }
     fn visit_in(&mut self, ast: &Expr) {
     }
     fn visit_post(&mut self, ast: &Expr) {  
+        let nfa = match ast {
+            Expr::Empty => self.nfa.empty(),
+            Expr::Literal(literal) => self.nfa.symbol(Symbol::Class(CharClass::single(literal.literal))),
+            Expr::Class(class) => self.nfa.symbol(Symbol::Class(class.as_ref().clone())),
+            Expr::Assertion(assertion) => self.nfa.symbol(Symbol::Look(assertion.look)),
+            Expr::Repetition(_) => {
+                let nfa = self.pop();
+                self.nfa.repetition(nfa)
+            },
+            Expr::ZeroOrOne(_) => {
+                let nfa = self.pop();
+                self.nfa.zero_or_one(nfa)
+            },
+            Expr::OneOrMany(_) => {
+                let nfa = self.pop();
+                self.nfa.one_or_many(nfa)
+            },
+            Expr::Bounded(rep) => {
+                let nfa = self.pop();
+                let first = self.marks.pop().unwrap_or_default();
+                self.nfa.bounded(nfa, first, rep.min, rep.max)
+            },
+            Expr::Alternation(alt) => {
+                let alternatives = self.pop_n(alt.exprs.len());
+                self.nfa.alternation(&alternatives)
+            },
+            Expr::Concatenation(concat) => {
+                let operands = self.pop_n(concat.exprs.len());
+                operands
+                    .into_iter()
+                    .reduce(|left, right| self.nfa.concatenation(left, right))
+                    .unwrap_or_else(|| self.nfa.empty())
+            },
+            Expr::Intersection(and) => {
+                let operands = self.pop_n(and.exprs.len());
+                self.set_operation(&operands, |dfas| {
+                    dfas.into_iter()
+                        .reduce(|left, right| left.intersection(&right))
+                        .expect("operands of intersection")
+                })
+            },
+            Expr::Complement(_) => {
+                let operand = self.pop();
+                self.set_operation(&[operand], |dfas| dfas[0].complement())
+            },
+            Expr::Group(group) => {
+                let nfa = self.pop();
+                match group.index {
+                    Some(index) => self.nfa.capture(nfa, index, group.name.clone()),
+                    None => nfa
+                }
+            },
+            Expr::Escape(escape) => self.nfa.symbol(Symbol::Class(escape.class.clone())),
+            Expr::MetaChar(meta) => self.nfa.symbol(Symbol::Class(meta.class.clone()))
+        };
+        self.args.push_back(nfa);
     }
-    fn finish(&self, ast: &Expr) {
+    fn finish(&mut self, ast: &Expr) {
+        let nfa = self.pop();
+        self.nfa.set_fragment(nfa);
     }
 }
 
@@ -132,85 +462,156 @@ impl ThompsonVisitor {
     pub fn new() -> ThompsonVisitor {
       ThompsonVisitor {
         args: VecDeque::with_capacity(2),
+        marks: Vec::new(),
         nfa: NFA::new()
       }  
     }
 
-    // iterative (pre, post and in order) traversal of a tree using visiter pattern;
+    pub fn into_nfa(self) -> NFA {
+        self.nfa
+    }
+
+    // every visited node leaves exactly one fragment behind, so children are always there
+    fn pop(&mut self) -> Nfa {
+        self.args.pop_back().expect("fragment of a child expression")
+    }
+
+    // Fragment of the DFA of & or ~ in place of the ones of its operands. Each operand
+    // is turned into a DFA from the fragment that is already built for it, so nested
+    // operations are compiled only once.
+    fn set_operation(
+        &mut self,
+        operands: &[Nfa],
+        combine: impl FnOnce(Vec<DFA<dfa::StateId, ClassId>>) -> DFA<dfa::StateId, ClassId>
+    ) -> Nfa {
+        let first = self.marks.pop().unwrap_or_default();
+        let dfas = operands
+            .iter()
+            .map(|operand| DFA::from_nfa(&self.nfa.fragment(*operand)).minimize())
+            .collect();
+        let dfa = combine(dfas);
+        self.nfa.truncate(first);
+        self.nfa.dfa(&dfa)
+    }
+
+    // fragments of the last n children in their order
+    fn pop_n(&mut self, n: usize) -> Vec<Nfa> {
+        let at = self.args.len().checked_sub(n).expect("fragments of child expressions");
+        self.args.split_off(at).into()
+    }
+
     pub fn visit(&mut self, ast: &Expr) {
-        self.start();
-        let mut active = VecDeque::<&Expr>::with_capacity(CAPACITY);
-        let mut frames = VecDeque::<(&Expr, Option<&Expr>)>::with_capacity(CAPACITY);
-        active.push_back(ast);
-        loop {
-            while let Some(ast_node) = active.pop_back() {
-                self.visit_pre(ast_node);
-                match ast_node {
-                    Expr::Alternation(alt) => {
-                        active.push_back(&alt.left_expr);
-                        frames.push_back((ast_node, Some(&alt.right_expr)));
-                    },
-                    Expr::Concatenation(concat) => {
-                        active.push_back(&concat.left_expr);
-                        frames.push_back((ast_node, Some(&concat.right_expr)));
-                    },
-                    Expr::Repetition(rep) => {
-                        active.push_back(&rep.expr);
-                        frames.push_back((ast_node, None));
-                    },
-                    Expr::Group(group) => {
-                        active.push_back(&group.expr);
-                        frames.push_back((ast_node, None));
-                    },
-                    _ => {
-                        frames.push_back((ast_node, None))
-                    }
-                }
-            };
-            
-
-            while let Some(frame) = frames.pop_back() {
-                match frame {
-                    (node@Expr::Alternation(_), Some(next)) => {
-                        self.visit_in(node);
-                        frames.push_back((node, None));
-                        active.push_back(&next);
-
-                        break;
-                    },
-                    (node@Expr::Concatenation(_), Some(next)) => {
-                        self.visit_in(node);
-                        frames.push_back((node, None));
-                        active.push_back(&next);
-
-                        break;
-                    }
-                    _ => {
-                        self.visit_post(frame.0);
-                    }
+        walk(ast, self);
+    }
+}
+
+impl Default for ThompsonVisitor {
+    fn default() -> Self {
+        ThompsonVisitor::new()
+    }
+}
+
+// iterative (pre, post and in order) traversal of a tree using visiter pattern;
+// visit_in is called between every two children of a node
+pub fn walk<V: Visitor>(ast: &Expr, visitor: &mut V) {
+    visitor.start();
+    // node together with the index of its child that is visited next
+    let mut frames = Vec::<(&Expr, usize)>::with_capacity(CAPACITY);
+    visitor.visit_pre(ast);
+    frames.push((ast, 0));
+    while let Some((node, index)) = frames.pop() {
+        match node.children().get(index) {
+            Some(child) => {
+                if index > 0 {
+                    visitor.visit_in(node);
                 }
-            }
+                frames.push((node, index + 1));
+                visitor.visit_pre(child);
+                frames.push((child, 0));
+            },
+            None => visitor.visit_post(node)
+        }
+    }
+    visitor.finish(ast);
+}
 
-            if active.is_empty() && frames.is_empty() {
-                self.finish(ast);
-                break;
-            }
-        }    
+impl Expr {
+    // DOT description of the expression tree
+    pub fn to_graphviz_string(&self) -> String {
+        let mut graphviz = GraphvizVisitor::new();
+        walk(self, &mut graphviz);
+        graphviz.graph
     }
+}
 
+// draws every node as a box labelled with its operator or character
+struct GraphvizVisitor {
+    graph: String,
+    parents: Vec<usize>,
+    next_id: usize
 }
 
-// frame consists of a current ast node and next node that needs to be traversed by ast. 
-struct Frame<'a> {
-    // reference to current ast
-    ast_node: &'a Expr,
+impl GraphvizVisitor {
+    fn new() -> GraphvizVisitor {
+        GraphvizVisitor {
+            graph: String::new(),
+            parents: Vec::new(),
+            next_id: 0
+        }
+    }
 
-    // and next link to traverse in the frame
-    next: Option<&'a Expr>
+    fn label(ast: &Expr) -> String {
+        match ast {
+            Expr::Empty => "ε".to_string(),
+            Expr::Literal(literal) => escape_invisible(literal.literal),
+            Expr::Class(class) => class.to_string(),
+            Expr::Assertion(assertion) => assertion.look.to_string(),
+            Expr::Repetition(_) => "*".to_string(),
+            Expr::ZeroOrOne(_) => "?".to_string(),
+            Expr::OneOrMany(_) => "+".to_string(),
+            Expr::Bounded(rep) => match rep.max {
+                Some(max) if max == rep.min => format!("{{{}}}", rep.min),
+                Some(max) => format!("{{{},{}}}", rep.min, max),
+                None => format!("{{{},}}", rep.min)
+            },
+            Expr::Alternation(_) => "|".to_string(),
+            Expr::Concatenation(_) => "·".to_string(),
+            Expr::Intersection(_) => "&".to_string(),
+            Expr::Complement(_) => "~".to_string(),
+            Expr::Group(group) => match (group.index, &group.name) {
+                (Some(_), Some(name)) => format!("(?<{}>)", name),
+                (Some(index), None) => format!("({})", index),
+                (None, _) => "(?:)".to_string()
+            },
+            Expr::Escape(escape) => format!("\\{}", escape.escape),
+            Expr::MetaChar(meta) => meta.meta.to_string()
+        }
+    }
 }
 
-impl Expr {
-    
+impl Visitor for GraphvizVisitor {
+    fn start(&mut self) {
+        graphviz::header(&mut self.graph, "expression_tree");
+        self.graph.push_str("node [shape = box];\n");
+    }
+    fn visit_pre(&mut self, ast: &Expr) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let label = graphviz::escape(&GraphvizVisitor::label(ast));
+        self.graph.push_str(&format!("{} [label = \"{}\"];\n", id, label));
+        if let Some(parent) = self.parents.last() {
+            self.graph.push_str(&format!("{} -> {};\n", parent, id));
+        }
+        self.parents.push(id);
+    }
+    fn visit_in(&mut self, ast: &Expr) {
+    }
+    fn visit_post(&mut self, ast: &Expr) {
+        self.parents.pop();
+    }
+    fn finish(&mut self, ast: &Expr) {
+        self.graph.push_str("}\n");
+    }
 }
 
 
@@ -224,9 +625,43 @@ mod tests {
     #[test]
     fn wlak_expr_tree() {
         let pattern = "a|b|c";
-        let expr = parse(pattern);
+        let Ok(expr) = parse(pattern) else {
+            panic!("failed to parse {}", pattern)
+        };
         let mut thompson = ThompsonVisitor::new();
         thompson.visit(&expr);
 
+        let nfa = thompson.into_nfa();
+        assert_eq!(nfa.len(), 10);
+        assert_eq!((nfa.start(), nfa.accept()), (8, 9));
+    }
+
+    #[test]
+    fn graphviz() {
+        let Ok(expr) = parse("(a|\\\\)b{2,}") else {
+            panic!("failed to parse")
+        };
+        let expected = "\
+digraph expression_tree {
+fontname=\"Helvetica,Arial,sans-serif\"
+node [fontname=\"Helvetica,Arial,sans-serif\"]
+edge [fontname=\"Helvetica,Arial,sans-serif\"]
+node [shape = box];
+0 [label = \"·\"];
+1 [label = \"(1)\"];
+0 -> 1;
+2 [label = \"|\"];
+1 -> 2;
+3 [label = \"a\"];
+2 -> 3;
+4 [label = \"\\\\\"];
+2 -> 4;
+5 [label = \"{2,}\"];
+0 -> 5;
+6 [label = \"b\"];
+5 -> 6;
+}
+";
+        assert_eq!(expr.to_graphviz_string(), expected);
     }
 }
\ No newline at end of file
@@ -0,0 +1,265 @@
+use super::{
+    walk, Alternation, BoundedRepetition, Complement, Concatenation, Expr, Group, Intersection, KleeneStar,
+    OneOrMany, Visitor, ZeroOrOne
+};
+
+// Computes one value per node out of the values of its children, which are
+// passed in the order of Expr::children. fold drives it without recursion, so
+// deep expressions do not overflow the stack.
+pub trait Fold {
+    type Output;
+
+    fn fold(&mut self, expr: &Expr, children: Vec<Self::Output>) -> Self::Output;
+}
+
+// Rewrites the tree bottom up, rewrite gets every node with its children
+// already rewritten and returns the node that replaces it.
+pub trait Rewrite {
+    fn rewrite(&mut self, expr: Expr) -> Expr;
+}
+
+impl<F: FnMut(Expr) -> Expr> Rewrite for F {
+    fn rewrite(&mut self, expr: Expr) -> Expr {
+        self(expr)
+    }
+}
+
+pub fn fold<F: Fold>(expr: &Expr, folder: &mut F) -> F::Output {
+    let mut visitor = FoldVisitor {
+        folder,
+        values: Vec::new()
+    };
+    walk(expr, &mut visitor);
+    visitor.values.pop().expect("value of the expression")
+}
+
+pub fn rewrite<R: Rewrite>(expr: &Expr, rewriter: &mut R) -> Expr {
+    fold(expr, &mut Rebuild(rewriter))
+}
+
+// Like ThompsonVisitor, every node pops the values of its children and pushes its own.
+struct FoldVisitor<'f, F: Fold> {
+    folder: &'f mut F,
+    values: Vec<F::Output>
+}
+
+impl<F: Fold> Visitor for FoldVisitor<'_, F> {
+    fn start(&mut self) {
+        self.values.clear();
+    }
+    fn visit_pre(&mut self, ast: &Expr) {
+    }
+    fn visit_in(&mut self, ast: &Expr) {
+    }
+    fn visit_post(&mut self, ast: &Expr) {
+        let at = self.values.len() - ast.children().len();
+        let children = self.values.split_off(at);
+        let value = self.folder.fold(ast, children);
+        self.values.push(value);
+    }
+    fn finish(&mut self, ast: &Expr) {
+    }
+}
+
+struct Rebuild<'r, R: Rewrite>(&'r mut R);
+
+impl<R: Rewrite> Fold for Rebuild<'_, R> {
+    type Output = Expr;
+
+    fn fold(&mut self, expr: &Expr, children: Vec<Expr>) -> Expr {
+        self.0.rewrite(expr.with_children(children))
+    }
+}
+
+impl Expr {
+    // copy of the node with children in place of its own, leaves are copied as they are
+    pub fn with_children(&self, children: Vec<Expr>) -> Expr {
+        let mut children = children.into_iter();
+        let mut child = || children.next().unwrap_or(Expr::Empty);
+        match self {
+            Expr::Repetition(_) => Expr::Repetition(Box::new(KleeneStar::new(child()))),
+            Expr::ZeroOrOne(_) => Expr::ZeroOrOne(Box::new(ZeroOrOne::new(child()))),
+            Expr::OneOrMany(_) => Expr::OneOrMany(Box::new(OneOrMany::new(child()))),
+            Expr::Bounded(rep) => Expr::Bounded(Box::new(BoundedRepetition::new(child(), rep.min, rep.max))),
+            Expr::Group(group) => Expr::Group(Box::new(Group {
+                expr: child(),
+                index: group.index,
+                name: group.name.clone()
+            })),
+            Expr::Alternation(_) => Expr::Alternation(Box::new(Alternation::n_ary(children.collect()))),
+            Expr::Concatenation(_) => Expr::Concatenation(Box::new(Concatenation::n_ary(children.collect()))),
+            Expr::Intersection(_) => Expr::Intersection(Box::new(Intersection::n_ary(children.collect()))),
+            Expr::Complement(_) => Expr::Complement(Box::new(Complement::new(child()))),
+            leaf => leaf.clone()
+        }
+    }
+
+    // number of leaves (characters and classes) expression has once all
+    // counted repetitions are expanded, this is roughly the number of NFA states it needs.
+    pub fn size(&self) -> usize {
+        fold(self, &mut Size)
+    }
+
+    // true if the expression matches the empty string, assertions are taken as
+    // satisfied as they do not consume anything
+    pub fn nullable(&self) -> bool {
+        fold(self, &mut Nullable)
+    }
+
+    // string every match starts with
+    pub fn literal_prefix(&self) -> String {
+        fold(self, &mut LiteralPrefix).prefix
+    }
+}
+
+struct Size;
+
+impl Fold for Size {
+    type Output = usize;
+
+    fn fold(&mut self, expr: &Expr, children: Vec<usize>) -> usize {
+        let size = children.iter().fold(0usize, |size, child| size.saturating_add(*child));
+        match expr {
+            Expr::Empty => 0,
+            Expr::Literal(_) | Expr::Class(_) | Expr::Assertion(_) | Expr::Escape(_) | Expr::MetaChar(_) => 1,
+            Expr::Bounded(rep) => {
+                // e{m,} is expanded into m copies of e followed by e*
+                let copies = rep.max.unwrap_or(rep.min.saturating_add(1)) as usize;
+                size.saturating_mul(copies.max(1))
+            },
+            _ => size
+        }
+    }
+}
+
+struct Nullable;
+
+impl Fold for Nullable {
+    type Output = bool;
+
+    fn fold(&mut self, expr: &Expr, children: Vec<bool>) -> bool {
+        match expr {
+            Expr::Empty | Expr::Assertion(_) | Expr::Repetition(_) | Expr::ZeroOrOne(_) => true,
+            Expr::Literal(_) | Expr::Class(_) | Expr::Escape(_) | Expr::MetaChar(_) => false,
+            Expr::Bounded(rep) if rep.min == 0 => true,
+            Expr::Alternation(_) => children.contains(&true),
+            Expr::Complement(_) => children.contains(&false),
+            _ => !children.contains(&false)
+        }
+    }
+}
+
+struct LiteralPrefix;
+
+// exact when the expression matches nothing but prefix
+struct Prefix {
+    prefix: String,
+    exact: bool
+}
+
+impl Prefix {
+    fn new(prefix: String, exact: bool) -> Prefix {
+        Prefix { prefix, exact }
+    }
+}
+
+impl Fold for LiteralPrefix {
+    type Output = Prefix;
+
+    fn fold(&mut self, expr: &Expr, children: Vec<Prefix>) -> Prefix {
+        let mut children = children.into_iter();
+        match expr {
+            Expr::Empty | Expr::Assertion(_) => Prefix::new(String::new(), true),
+            Expr::Literal(literal) => Prefix::new(literal.literal.to_string(), true),
+            Expr::Class(_) | Expr::Escape(_) | Expr::MetaChar(_) => Prefix::new(String::new(), false),
+            Expr::Repetition(_) | Expr::ZeroOrOne(_) => Prefix::new(String::new(), false),
+            Expr::Intersection(_) | Expr::Complement(_) => Prefix::new(String::new(), false),
+            Expr::OneOrMany(_) => children.next().map_or(Prefix::new(String::new(), false), |child| Prefix::new(child.prefix, false)),
+            Expr::Bounded(rep) => match children.next() {
+                Some(child) if child.exact => Prefix::new(child.prefix.repeat(rep.min as usize), rep.max == Some(rep.min)),
+                Some(child) if rep.min > 0 => Prefix::new(child.prefix, false),
+                _ => Prefix::new(String::new(), false)
+            },
+            Expr::Group(_) => children.next().unwrap_or(Prefix::new(String::new(), true)),
+            Expr::Concatenation(_) => children.fold(Prefix::new(String::new(), true), |prefix, child| match prefix.exact {
+                true => Prefix::new(prefix.prefix + &child.prefix, child.exact),
+                false => prefix
+            }),
+            Expr::Alternation(_) => children
+                .reduce(|left, right| {
+                    let common = left.prefix
+                        .chars()
+                        .zip(right.prefix.chars())
+                        .take_while(|(left, right)| left == right)
+                        .map(|(char, _)| char)
+                        .collect::<String>();
+                    let exact = left.exact && right.exact && left.prefix == right.prefix;
+                    Prefix::new(common, exact)
+                })
+                .unwrap_or(Prefix::new(String::new(), true))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+    use crate::ast::{Expr, KleeneStar, Literal};
+
+    use super::{fold, rewrite, Fold};
+
+    fn expr(pattern: &str) -> Expr {
+        match parse(pattern) {
+            Ok(expr) => expr,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    // depth of the tree
+    struct Depth;
+
+    impl Fold for Depth {
+        type Output = usize;
+
+        fn fold(&mut self, expr: &Expr, children: Vec<usize>) -> usize {
+            children.into_iter().max().unwrap_or(0) + 1
+        }
+    }
+
+    #[test]
+    fn folds_from_children() {
+        assert_eq!(fold(&expr("a"), &mut Depth), 1);
+        assert_eq!(fold(&expr("(a|b)*c"), &mut Depth), 5);
+        assert_eq!(expr("a{3}(bc|d)*").size(), 6);
+        assert!(expr("a*(b?|^)").nullable());
+        assert!(!expr("a*(b|c)d{0,2}").nullable());
+    }
+
+    #[test]
+    fn literal_prefixes() {
+        assert_eq!(expr("abc").literal_prefix(), "abc");
+        assert_eq!(expr("ab(?:cd|ce)f").literal_prefix(), "abc");
+        assert_eq!(expr("(?:ab){2}c+d").literal_prefix(), "ababc");
+        assert_eq!(expr("a[bc]d").literal_prefix(), "a");
+        assert_eq!(expr("^ab|ac").literal_prefix(), "a");
+        assert_eq!(expr("a?b").literal_prefix(), "");
+    }
+
+    #[test]
+    fn rewrites_bottom_up() {
+        // uppercase every literal
+        let mut upper = |expr: Expr| match expr {
+            Expr::Literal(literal) => Expr::Literal(Box::new(Literal::new(literal.literal.to_ascii_uppercase()))),
+            expr => expr
+        };
+        assert_eq!(rewrite(&expr("a(b|c)*d{2}"), &mut upper).to_string(), "A(B|C)*D{2}");
+    }
+
+    #[test]
+    fn deep_expressions_do_not_overflow() {
+        let deep = (0..10_000).fold(expr("a"), |expr, _| Expr::Repetition(Box::new(KleeneStar::new(expr))));
+        assert_eq!(fold(&deep, &mut Depth), 10_001);
+        assert_eq!(deep.size(), 1);
+        assert!(deep.nullable());
+    }
+}
@@ -0,0 +1,534 @@
+use std::fmt::{self, Debug, Display};
+
+use crate::class::CharClass;
+use crate::look::Look;
+use crate::parser::{META, SET_META};
+
+use super::{walk, Expr, Visitor};
+
+// Flags that change what the printed text of a node means. Case insensitivity
+// and verbose mode are already resolved in the tree, literals are folded into
+// classes and ignored white space is gone.
+const MULTI_LINE: usize = 0;
+const DOT_ALL: usize = 1;
+const FLAG_LETTERS: [char; 2] = ['m', 's'];
+
+type Flags = [bool; 2];
+
+// Prints the expression as a pattern. Parentheses are only added where precedence
+// requires them and they are non-capturing, so parsing the printed pattern gives
+// back the same tree for every tree the parser produces, e.g. "(a|b)c*" stays
+// "(a|b)c*" and Concatenation(a, Alternation(b, c)) becomes "a(?:b|c)".
+//
+// Anchors and dots depend on the m and s flags. Flag groups such as "(?m)" parse
+// into Empty, so Empty is printed as a flag group that switches the flags to
+// what the nodes after it need, "a(?m)^" prints as it was written. Non-capturing
+// groups switch flags for their content the same way, e.g. "(?s:.)".
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = PatternVisitor::new();
+        walk(self, &mut printer);
+        f.write_str(&printer.pattern)
+    }
+}
+
+// S-expression form of the tree, e.g. "(concat (capture 1 (alt 'a' 'b')) (star 'c'))"
+// for "(a|b)c*", which shows the structure that Display hides.
+impl Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = SexpVisitor::new();
+        walk(self, &mut printer);
+        f.write_str(&printer.sexp)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    Alternation,
+    Concatenation,
+    Intersection,
+    Complement,
+    Quantifier,
+    Group,
+    Atom,
+    Empty
+}
+
+impl Kind {
+    fn of(expr: &Expr) -> Kind {
+        match expr {
+            Expr::Empty => Kind::Empty,
+            Expr::Alternation(_) => Kind::Alternation,
+            Expr::Concatenation(_) => Kind::Concatenation,
+            Expr::Intersection(_) => Kind::Intersection,
+            Expr::Complement(_) => Kind::Complement,
+            Expr::Repetition(_) | Expr::ZeroOrOne(_) | Expr::OneOrMany(_) | Expr::Bounded(_) => Kind::Quantifier,
+            Expr::Group(_) => Kind::Group,
+            Expr::Literal(_) | Expr::Class(_) | Expr::Assertion(_) | Expr::Escape(_) | Expr::MetaChar(_) => Kind::Atom
+        }
+    }
+}
+
+// Piece of the printed pattern. Flags can only be resolved once the whole tree
+// was visited, because a flag group has to know what comes after it.
+enum Piece {
+    Text(String),
+    // opening parenthesis, flags switched inside of a group are restored at its end
+    Open(String),
+    // "(?:" that can also switch flags for its content, e.g. "(?m:"
+    OpenNonCapturing,
+    Close,
+    Empty,
+    // text that has the right meaning only while flag has value, fallback works regardless
+    Flagged {
+        flag: usize,
+        value: bool,
+        text: &'static str,
+        fallback: &'static str
+    }
+}
+
+// node whose children are being printed
+struct Node {
+    kind: Kind,
+    children: usize,
+    parenthesized: bool
+}
+
+struct PatternVisitor {
+    pieces: Vec<Piece>,
+    nodes: Vec<Node>,
+    pattern: String
+}
+
+impl PatternVisitor {
+    fn new() -> PatternVisitor {
+        PatternVisitor {
+            pieces: Vec::new(),
+            nodes: Vec::new(),
+            pattern: String::new()
+        }
+    }
+
+    // The binary operators are left associative and bind looser than quantifiers, alternation
+    // looser than intersection and intersection looser than concatenation. Only the first
+    // operand can be of the same kind as its parent without parentheses, "ab(?:cd)" is
+    // Concatenation(a, b, Concatenation(c, d)). Complement takes a single atom with its
+    // quantifiers, "~ab" is Concatenation(Complement(a), b).
+    fn needs_parens(parent: Option<&Node>, kind: Kind) -> bool {
+        let Some(parent) = parent else {
+            return false;
+        };
+        match (parent.kind, kind) {
+            (Kind::Concatenation, Kind::Alternation | Kind::Intersection) => true,
+            (Kind::Intersection, Kind::Alternation) => true,
+            (Kind::Concatenation, Kind::Concatenation)
+            | (Kind::Intersection, Kind::Intersection)
+            | (Kind::Alternation, Kind::Alternation) => parent.children > 0,
+            (Kind::Quantifier, Kind::Alternation | Kind::Concatenation | Kind::Intersection | Kind::Complement) => true,
+            (Kind::Complement, Kind::Alternation | Kind::Concatenation | Kind::Intersection) => true,
+            _ => false
+        }
+    }
+
+    fn atom(ast: &Expr) -> Piece {
+        match ast {
+            Expr::Literal(literal) => Piece::Text(escape_literal(literal.literal)),
+            Expr::Class(class) => Piece::Text(class.to_string()),
+            Expr::Assertion(assertion) => match assertion.look {
+                Look::StartText => flagged(MULTI_LINE, false, "^", "\\A"),
+                Look::EndText => flagged(MULTI_LINE, false, "$", "\\z"),
+                Look::StartLine => flagged(MULTI_LINE, true, "^", "(?m:^)"),
+                Look::EndLine => flagged(MULTI_LINE, true, "$", "(?m:$)"),
+                Look::WordBoundary => Piece::Text("\\b".to_string()),
+                Look::NotWordBoundary => Piece::Text("\\B".to_string())
+            },
+            Expr::Escape(escape) => Piece::Text(format!("\\{}", escape.escape)),
+            Expr::MetaChar(meta) if meta.class == CharClass::any() => flagged(DOT_ALL, true, ".", "(?s:.)"),
+            Expr::MetaChar(meta) if meta.class == CharClass::single('\n').negate() => {
+                flagged(DOT_ALL, false, ".", "(?-s:.)")
+            },
+            Expr::MetaChar(meta) => Piece::Text(meta.class.to_string()),
+            _ => Piece::Text(String::new())
+        }
+    }
+
+    // flags that the nodes following an Empty need, up to the next Empty or the end of the group
+    fn wanted_flags(rest: &[Piece], current: Flags) -> Flags {
+        let mut wanted = [None; 2];
+        let mut depth = 0;
+        // depth of a nested group whose flags an Empty inside of it already switched
+        let mut switched: Option<usize> = None;
+        for piece in rest {
+            match piece {
+                Piece::Open(_) | Piece::OpenNonCapturing => depth += 1,
+                Piece::Close if depth == 0 => break,
+                Piece::Close => {
+                    if switched == Some(depth) {
+                        switched = None;
+                    }
+                    depth -= 1;
+                },
+                Piece::Empty if depth == 0 => break,
+                Piece::Empty => {
+                    switched.get_or_insert(depth);
+                },
+                Piece::Flagged { flag, value, .. } if switched.is_none() => {
+                    wanted[*flag].get_or_insert(*value);
+                },
+                _ => {}
+            }
+        }
+        [MULTI_LINE, DOT_ALL].map(|flag| wanted[flag].unwrap_or(current[flag]))
+    }
+
+    fn resolve(pieces: &[Piece]) -> String {
+        let mut pattern = String::new();
+        let mut scopes: Vec<Flags> = vec![[false; 2]];
+        for (index, piece) in pieces.iter().enumerate() {
+            let current = *scopes.last().unwrap_or(&[false; 2]);
+            match piece {
+                Piece::Text(text) => pattern.push_str(text),
+                Piece::Open(text) => {
+                    pattern.push_str(text);
+                    scopes.push(current);
+                },
+                Piece::OpenNonCapturing => {
+                    let wanted = PatternVisitor::wanted_flags(&pieces[index + 1..], current);
+                    pattern.push_str(&format!("(?{}:", flag_letters(current, wanted)));
+                    scopes.push(wanted);
+                },
+                Piece::Close => {
+                    pattern.push(')');
+                    scopes.pop();
+                },
+                Piece::Empty => {
+                    let wanted = PatternVisitor::wanted_flags(&pieces[index + 1..], current);
+                    pattern.push_str(&flag_group(current, wanted));
+                    if let Some(flags) = scopes.last_mut() {
+                        *flags = wanted;
+                    }
+                },
+                Piece::Flagged { flag, value, text, fallback } => {
+                    pattern.push_str(if current[*flag] == *value { text } else { fallback });
+                }
+            }
+        }
+        pattern
+    }
+}
+
+impl Visitor for PatternVisitor {
+    fn start(&mut self) {
+        self.pieces.clear();
+        self.nodes.clear();
+        self.pattern.clear();
+    }
+    fn visit_pre(&mut self, ast: &Expr) {
+        let kind = Kind::of(ast);
+        let parent = self.nodes.last();
+        let parenthesized = PatternVisitor::needs_parens(parent, kind);
+        if parenthesized {
+            self.pieces.push(Piece::OpenNonCapturing);
+        }
+        match ast {
+            // on its own Empty is just nothing, elsewhere nothing would disappear
+            Expr::Empty => match parent.map(|parent| parent.kind) {
+                None | Some(Kind::Group) => {},
+                Some(_) => self.pieces.push(Piece::Empty)
+            },
+            Expr::Group(group) => {
+                let open = match (group.index, &group.name) {
+                    (Some(_), Some(name)) => Piece::Open(format!("(?<{}>", name)),
+                    (Some(_), None) => Piece::Open("(".to_string()),
+                    (None, _) => Piece::OpenNonCapturing
+                };
+                self.pieces.push(open);
+            },
+            Expr::Complement(_) => self.pieces.push(Piece::Text("~".to_string())),
+            _ if kind == Kind::Atom => self.pieces.push(PatternVisitor::atom(ast)),
+            _ => {}
+        }
+        self.nodes.push(Node {
+            kind,
+            children: 0,
+            parenthesized
+        });
+    }
+    fn visit_in(&mut self, ast: &Expr) {
+        match ast {
+            Expr::Alternation(_) => self.pieces.push(Piece::Text("|".to_string())),
+            Expr::Intersection(_) => self.pieces.push(Piece::Text("&".to_string())),
+            _ => {}
+        }
+    }
+    fn visit_post(&mut self, ast: &Expr) {
+        let suffix = match ast {
+            Expr::Repetition(_) => Some("*".to_string()),
+            Expr::ZeroOrOne(_) => Some("?".to_string()),
+            Expr::OneOrMany(_) => Some("+".to_string()),
+            Expr::Bounded(rep) => Some(match rep.max {
+                Some(max) if max == rep.min => format!("{{{}}}", rep.min),
+                Some(max) => format!("{{{},{}}}", rep.min, max),
+                None => format!("{{{},}}", rep.min)
+            }),
+            _ => None
+        };
+        if let Some(suffix) = suffix {
+            self.pieces.push(Piece::Text(suffix));
+        }
+        if let Expr::Group(_) = ast {
+            self.pieces.push(Piece::Close);
+        }
+
+        if self.nodes.pop().is_some_and(|node| node.parenthesized) {
+            self.pieces.push(Piece::Close);
+        }
+        if let Some(parent) = self.nodes.last_mut() {
+            parent.children += 1;
+        }
+    }
+    fn finish(&mut self, ast: &Expr) {
+        self.pattern = PatternVisitor::resolve(&self.pieces);
+    }
+}
+
+fn flagged(flag: usize, value: bool, text: &'static str, fallback: &'static str) -> Piece {
+    Piece::Flagged {
+        flag,
+        value,
+        text,
+        fallback
+    }
+}
+
+// "(?m-s)" switching current flags to wanted ones. When nothing changes it still
+// has to be there, then it repeats the m flag.
+fn flag_group(current: Flags, wanted: Flags) -> String {
+    match flag_letters(current, wanted) {
+        letters if !letters.is_empty() => format!("(?{})", letters),
+        _ if wanted[MULTI_LINE] => "(?m)".to_string(),
+        _ => "(?-m)".to_string()
+    }
+}
+
+// letters of flags that change between current and wanted, e.g. "m-s"
+fn flag_letters(current: Flags, wanted: Flags) -> String {
+    let letters = |value: bool| {
+        [MULTI_LINE, DOT_ALL]
+            .into_iter()
+            .filter(|flag| wanted[*flag] == value && current[*flag] != value)
+            .map(|flag| FLAG_LETTERS[flag])
+            .collect::<String>()
+    };
+    match (letters(true), letters(false)) {
+        (on, off) if off.is_empty() => on,
+        (on, off) => format!("{}-{}", on, off)
+    }
+}
+
+// metacharacters and characters that can not be seen are escaped
+fn escape_literal(char: char) -> String {
+    match char {
+        char if META.contains(&char) || SET_META.contains(&char) => format!("\\{}", char),
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        char if char.is_control() || (char.is_whitespace() && char != ' ') => format!("\\u{{{:x}}}", char as u32),
+        char => char.to_string()
+    }
+}
+
+struct SexpVisitor {
+    sexp: String,
+    depth: usize
+}
+
+impl SexpVisitor {
+    fn new() -> SexpVisitor {
+        SexpVisitor {
+            sexp: String::new(),
+            depth: 0
+        }
+    }
+
+    // head of the list of a node with children, None for leaves
+    fn head(ast: &Expr) -> Option<String> {
+        let head = match ast {
+            Expr::Repetition(_) => "star".to_string(),
+            Expr::ZeroOrOne(_) => "opt".to_string(),
+            Expr::OneOrMany(_) => "plus".to_string(),
+            Expr::Bounded(rep) => match rep.max {
+                Some(max) => format!("repeat {} {}", rep.min, max),
+                None => format!("repeat {} inf", rep.min)
+            },
+            Expr::Alternation(_) => "alt".to_string(),
+            Expr::Concatenation(_) => "concat".to_string(),
+            Expr::Intersection(_) => "and".to_string(),
+            Expr::Complement(_) => "not".to_string(),
+            Expr::Group(group) => match (group.index, &group.name) {
+                (Some(index), Some(name)) => format!("capture {} {}", index, name),
+                (Some(index), None) => format!("capture {}", index),
+                (None, _) => "group".to_string()
+            },
+            _ => return None
+        };
+        Some(head)
+    }
+
+    fn leaf(ast: &Expr) -> String {
+        match ast {
+            Expr::Empty => "empty".to_string(),
+            Expr::Literal(literal) => format!("{:?}", literal.literal),
+            Expr::Class(class) => class.to_string(),
+            Expr::Assertion(assertion) => match assertion.look {
+                Look::StartText => "start_text",
+                Look::EndText => "end_text",
+                Look::StartLine => "start_line",
+                Look::EndLine => "end_line",
+                Look::WordBoundary => "word_boundary",
+                Look::NotWordBoundary => "not_word_boundary"
+            }.to_string(),
+            Expr::Escape(escape) => format!("\\{}", escape.escape),
+            Expr::MetaChar(meta) if meta.class == CharClass::any() => "any".to_string(),
+            Expr::MetaChar(meta) => "dot".to_string(),
+            _ => String::new()
+        }
+    }
+}
+
+impl Visitor for SexpVisitor {
+    fn start(&mut self) {
+        self.sexp.clear();
+        self.depth = 0;
+    }
+    fn visit_pre(&mut self, ast: &Expr) {
+        if self.depth > 0 {
+            self.sexp.push(' ');
+        }
+        match SexpVisitor::head(ast) {
+            Some(head) => {
+                self.sexp.push('(');
+                self.sexp.push_str(&head);
+                self.depth += 1;
+            },
+            None => self.sexp.push_str(&SexpVisitor::leaf(ast))
+        }
+    }
+    fn visit_in(&mut self, ast: &Expr) {
+    }
+    fn visit_post(&mut self, ast: &Expr) {
+        if SexpVisitor::head(ast).is_some() {
+            self.sexp.push(')');
+            self.depth -= 1;
+        }
+    }
+    fn finish(&mut self, ast: &Expr) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Alternation, Complement, Concatenation, Expr, Intersection, KleeneStar, Literal};
+    use crate::parser::{parse, parse_with, Config};
+
+    fn expr(pattern: &str) -> Expr {
+        match parse(pattern) {
+            Ok(expr) => expr,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    fn literal(char: char) -> Expr {
+        Expr::Literal(Box::new(Literal::new(char)))
+    }
+
+    fn concat(left: Expr, right: Expr) -> Expr {
+        Expr::Concatenation(Box::new(Concatenation::new(left, right)))
+    }
+
+    fn alt(left: Expr, right: Expr) -> Expr {
+        Expr::Alternation(Box::new(Alternation::new(left, right)))
+    }
+
+    #[test]
+    fn prints_patterns_as_written() {
+        let patterns = [
+            "a|bc*",
+            "(a|b)+c{2,3}d{2,}e{4}",
+            "(?:ab)?",
+            "(?<year>\\d{4})-\\w\\S",
+            "^\\bx\\B$",
+            "[a-z]\\.\\*\\\\",
+            "a**",
+            "()",
+            "",
+            "\\n\\t",
+            "a(?m)^b$(?-m)$",
+            "(?s).(?-s).",
+            "(?m:^a)$"
+        ];
+        for pattern in patterns {
+            assert_eq!(expr(pattern).to_string(), pattern);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let patterns = [
+            "(?i)ab|[a-c]",
+            "\\A\\z",
+            "(?x) a b # comment",
+            "x(?m:^)y",
+            "(?s:.(?-s:.))",
+            "(?m)(a(?-m)^)^",
+            "a(?i)",
+            "(?s)a|.",
+            "\\u{1F600}\\x7f",
+            "[^\\n]",
+            "(?m)*",
+            "[^\\x00-\\u{10FFFF}]"
+        ];
+        for pattern in patterns {
+            let expr = expr(pattern);
+            let printed = expr.to_string();
+            assert_eq!(parse(&printed).ok(), Some(expr), "{} printed as {}", pattern, printed);
+        }
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        let right_nested = concat(literal('a'), concat(literal('b'), literal('c')));
+        assert_eq!(right_nested.to_string(), "a(?:bc)");
+        let left_nested = alt(alt(literal('a'), literal('b')), literal('c'));
+        assert_eq!(left_nested.to_string(), "a|b|c");
+        let alternative = concat(literal('a'), alt(literal('b'), literal('c')));
+        assert_eq!(alternative.to_string(), "a(?:b|c)");
+        let star = Expr::Repetition(Box::new(KleeneStar::new(concat(literal('a'), literal('b')))));
+        assert_eq!(star.to_string(), "(?:ab)*");
+    }
+
+    #[test]
+    fn s_expression() {
+        assert_eq!(format!("{:?}", expr("(a|\\\\)b{2,}")), "(concat (capture 1 (alt 'a' '\\\\')) (repeat 2 inf 'b'))");
+        assert_eq!(format!("{:?}", expr("(?<x>^.)?[0-9]|\\d")), "(alt (concat (opt (capture 1 x (concat start_text dot))) [0-9]) \\d)");
+        assert_eq!(format!("{:?}", expr("(?:)")), "(group empty)");
+    }
+    #[test]
+    fn set_operations() {
+        let config = Config::new().extended(true);
+        let patterns = ["a|b&cd", "(?:a|b)&c", "a(?:b&c)", "a&(?:b&c)", "~a*b", "~(?:ab)", "(?:~a)*", "~~a", "a\\&\\~"];
+        for pattern in patterns {
+            let Ok(expr) = parse_with(pattern, &config) else {
+                panic!("failed to parse {}", pattern)
+            };
+            assert_eq!(expr.to_string(), pattern);
+            assert_eq!(parse_with(&expr.to_string(), &config).ok(), Some(expr));
+        }
+
+        let and = Expr::Intersection(Box::new(Intersection::new(alt(literal('a'), literal('b')), literal('~'))));
+        assert_eq!(and.to_string(), "(?:a|b)&\\~");
+        let not = Expr::Complement(Box::new(Complement::new(concat(literal('a'), literal('b')))));
+        assert_eq!(format!("{:?}", not), "(not (concat 'a' 'b'))");
+    }
+}
@@ -0,0 +1,336 @@
+use crate::class::CharClass;
+
+use super::fold::rewrite;
+use super::{Alternation, Concatenation, Expr, KleeneStar};
+
+// Rewrites of the expression tree that keep the leftmost-first matches of every
+// pattern, captures included, but make the tree and therefore the NFA smaller.
+// Each pass rewrites every node after its children, so they see children that
+// are already rewritten.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Pass {
+    // e+ becomes ee* and e? becomes e|, for engines that only know the core operators.
+    // It duplicates e, so it is not part of the default pipeline.
+    Desugar,
+    // nested concatenations and alternations become a single n-ary node,
+    // non-capturing groups are only there for precedence and are removed
+    Flatten,
+    // Empty is the identity of concatenation and repeating it changes nothing
+    DropEmpty,
+    // abc|abd becomes ab(c|d), only neighbouring alternatives are factored because
+    // the order of alternatives matters. Alternatives repeating an earlier one are dropped.
+    // Only heads that match exactly one character are factored, a shared a* or (a)
+    // could match differently in each alternative.
+    FactorPrefixes,
+    // a|b|[0-9] becomes [ab0-9] as long as the characters are neighbouring alternatives
+    MergeClasses
+}
+
+impl Pass {
+    // passes that make the tree smaller, in the order they work best in
+    pub const DEFAULT: [Pass; 4] = [Pass::Flatten, Pass::DropEmpty, Pass::FactorPrefixes, Pass::MergeClasses];
+
+    pub fn run(&self, expr: &Expr) -> Expr {
+        let mut pass: fn(Expr) -> Expr = match self {
+            Pass::Desugar => desugar,
+            Pass::Flatten => flatten,
+            Pass::DropEmpty => drop_empty,
+            Pass::FactorPrefixes => factor_prefixes,
+            Pass::MergeClasses => merge_classes
+        };
+        rewrite(expr, &mut pass)
+    }
+}
+
+// passes that run one after another
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Pipeline {
+    passes: Vec<Pass>
+}
+
+impl Pipeline {
+    pub fn new(passes: &[Pass]) -> Pipeline {
+        Pipeline {
+            passes: passes.to_vec()
+        }
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub fn run(&self, expr: &Expr) -> Expr {
+        let mut passes = self.passes.iter();
+        let Some(first) = passes.next() else {
+            return expr.clone();
+        };
+        passes.fold(first.run(expr), |expr, pass| pass.run(&expr))
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::new(&Pass::DEFAULT)
+    }
+}
+
+// runs the default pipeline
+pub fn simplify(expr: &Expr) -> Expr {
+    Pipeline::default().run(expr)
+}
+
+fn desugar(expr: Expr) -> Expr {
+    match expr {
+        Expr::OneOrMany(rep) => {
+            let star = Expr::Repetition(Box::new(KleeneStar::new(rep.expr.clone())));
+            concatenation(vec![rep.expr, star])
+        },
+        Expr::ZeroOrOne(rep) => alternation(vec![rep.expr, Expr::Empty]),
+        expr => expr
+    }
+}
+
+fn flatten(expr: Expr) -> Expr {
+    match expr {
+        Expr::Group(group) if group.index.is_none() => group.expr,
+        Expr::Concatenation(concat) => concatenation(concat.exprs),
+        Expr::Alternation(alt) => alternation(alt.exprs),
+        expr => expr
+    }
+}
+
+fn drop_empty(expr: Expr) -> Expr {
+    match expr {
+        Expr::Concatenation(concat) => {
+            let exprs = concat.exprs
+                .into_iter()
+                .filter(|expr| !matches!(expr, Expr::Empty))
+                .collect();
+            concatenation(exprs)
+        },
+        Expr::Repetition(rep) if matches!(rep.expr, Expr::Empty) => Expr::Empty,
+        Expr::ZeroOrOne(rep) if matches!(rep.expr, Expr::Empty) => Expr::Empty,
+        Expr::OneOrMany(rep) if matches!(rep.expr, Expr::Empty) => Expr::Empty,
+        Expr::Bounded(rep) if matches!(rep.expr, Expr::Empty) => Expr::Empty,
+        expr => expr
+    }
+}
+
+fn factor_prefixes(expr: Expr) -> Expr {
+    let Expr::Alternation(alt) = expr else {
+        return expr;
+    };
+
+    // an alternative that repeats an earlier one can never match first
+    let mut alternatives: Vec<Expr> = Vec::with_capacity(alt.exprs.len());
+    for expr in alt.exprs {
+        if !alternatives.contains(&expr) {
+            alternatives.push(expr);
+        }
+    }
+
+    let mut factored: Vec<Expr> = Vec::with_capacity(alternatives.len());
+    let mut alternatives = alternatives.into_iter().peekable();
+    while let Some(first) = alternatives.next() {
+        // "a*ab|a*" is not "a*(?:ab|)", the star would take the a that ab needs
+        if single_char_class(head_of(&first)).is_none() {
+            factored.push(first);
+            continue;
+        }
+        let (head, rest) = split_head(first);
+        let mut rests = vec![rest];
+        while let Some(next) = alternatives.next_if(|next| head_of(next) == &head) {
+            rests.push(split_head(next).1);
+        }
+        match rests.len() {
+            1 => factored.push(concatenation(vec![head, rests.remove(0)])),
+            _ => {
+                let rest = factor_prefixes(alternation(rests));
+                factored.push(concatenation(vec![head, rest]));
+            }
+        }
+    }
+    alternation(factored)
+}
+
+fn merge_classes(expr: Expr) -> Expr {
+    let Expr::Alternation(alt) = expr else {
+        return expr;
+    };
+
+    let mut merged: Vec<Expr> = Vec::with_capacity(alt.exprs.len());
+    // class of the run of single character alternatives at the end of merged
+    let mut run: Option<(CharClass, usize)> = None;
+    for expr in alt.exprs {
+        let Some(class) = single_char_class(&expr) else {
+            run = None;
+            merged.push(expr);
+            continue;
+        };
+        match run.as_mut() {
+            Some((chars, index)) => {
+                chars.union(&class);
+                merged[*index] = Expr::Class(Box::new(chars.clone()));
+            },
+            None => {
+                run = Some((class, merged.len()));
+                merged.push(expr);
+            }
+        }
+    }
+    alternation(merged)
+}
+
+// first operand of a concatenation and the concatenation of the others,
+// anything else is its own head followed by nothing
+fn split_head(expr: Expr) -> (Expr, Expr) {
+    match expr {
+        Expr::Concatenation(concat) => {
+            let mut exprs = concat.exprs.into_iter();
+            let head = exprs.next().unwrap_or(Expr::Empty);
+            (head, concatenation(exprs.collect()))
+        },
+        expr => (expr, Expr::Empty)
+    }
+}
+
+fn head_of(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Concatenation(concat) => concat.exprs.first().unwrap_or(&Expr::Empty),
+        expr => expr
+    }
+}
+
+// characters of an expression that matches exactly one character
+fn single_char_class(expr: &Expr) -> Option<CharClass> {
+    match expr {
+        Expr::Literal(literal) => Some(CharClass::single(literal.literal)),
+        Expr::Class(class) => Some(class.as_ref().clone()),
+        Expr::Escape(escape) => Some(escape.class.clone()),
+        Expr::MetaChar(meta) => Some(meta.class.clone()),
+        _ => None
+    }
+}
+
+// Concatenation of exprs with nested concatenations spliced in, Empty operands
+// are left to DropEmpty. A single operand is returned as it is.
+fn concatenation(exprs: Vec<Expr>) -> Expr {
+    let mut operands = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        match expr {
+            Expr::Concatenation(concat) => operands.extend(concat.exprs),
+            Expr::Empty if !operands.is_empty() => {},
+            expr => operands.push(expr)
+        }
+    }
+    if operands.len() > 1 {
+        operands.retain(|expr| !matches!(expr, Expr::Empty));
+    }
+    match operands.len() {
+        0 => Expr::Empty,
+        1 => operands.remove(0),
+        _ => Expr::Concatenation(Box::new(Concatenation::n_ary(operands)))
+    }
+}
+
+// alternation of exprs with nested alternations spliced in
+fn alternation(exprs: Vec<Expr>) -> Expr {
+    let mut alternatives = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        match expr {
+            Expr::Alternation(alt) => alternatives.extend(alt.exprs),
+            expr => alternatives.push(expr)
+        }
+    }
+    match alternatives.len() {
+        0 => Expr::Empty,
+        1 => alternatives.remove(0),
+        _ => Expr::Alternation(Box::new(Alternation::n_ary(alternatives)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+    use crate::ast::Expr;
+    use crate::simulation::Simulation;
+    use crate::thompson::NFA;
+
+    use super::{simplify, Pass, Pipeline};
+
+    fn expr(pattern: &str) -> Expr {
+        match parse(pattern) {
+            Ok(expr) => expr,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    fn run(passes: &[Pass], pattern: &str) -> String {
+        Pipeline::new(passes).run(&expr(pattern)).to_string()
+    }
+
+    #[test]
+    fn desugar() {
+        assert_eq!(run(&[Pass::Desugar], "a+"), "aa*");
+        assert_eq!(run(&[Pass::Desugar], "(ab)?c"), "(?:(ab)|(?-m))c");
+    }
+
+    #[test]
+    fn flatten() {
+        let flat = Pass::Flatten.run(&expr("a|b|(?:c|d)"));
+        let Expr::Alternation(alt) = &flat else {
+            panic!("expected alternation, got {:?}", flat)
+        };
+        assert_eq!(alt.exprs().len(), 4);
+        assert_eq!(format!("{:?}", Pass::Flatten.run(&expr("ab(cd)e"))), "(concat 'a' 'b' (capture 1 (concat 'c' 'd')) 'e')");
+    }
+
+    #[test]
+    fn drop_empty() {
+        assert_eq!(run(&[Pass::Flatten, Pass::DropEmpty], "a(?i)b(?:)*"), "a[Bb]");
+        assert_eq!(run(&[Pass::Flatten, Pass::DropEmpty], "(?i)"), "");
+    }
+
+    #[test]
+    fn factor_prefixes() {
+        assert_eq!(simplify(&expr("abc|abd")).to_string(), "ab[c-d]");
+        assert_eq!(simplify(&expr("ab|abc|x")).to_string(), "ab(?:(?-m)|c)|x");
+        // only neighbours share a prefix, "ab" has to stay in front of "c"
+        assert_eq!(simplify(&expr("ab|c|ad")).to_string(), "ab|c|ad");
+        assert_eq!(simplify(&expr("if|int|in|for")).to_string(), "i(?:f|n(?:t|(?-m)))|for");
+        assert_eq!(simplify(&expr("a|b|a")).to_string(), "[a-b]");
+        // heads that are not a single character stay where they are
+        assert_eq!(simplify(&expr("a*ab|a*")).to_string(), "a*ab|a*");
+        assert_eq!(simplify(&expr("(a)b|(a)c")).to_string(), "(a)b|(a)c");
+    }
+
+    #[test]
+    fn merge_classes() {
+        assert_eq!(run(&Pass::DEFAULT, "a|b|\\d|xy|c|[e-f]"), "[0-9a-b]|xy|[ce-f]");
+        assert_eq!(run(&Pass::DEFAULT, "(a|b)"), "([a-b])");
+    }
+
+    #[test]
+    fn keywords_need_fewer_states() {
+        let keywords = ["break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for"];
+        let expr = expr(&keywords.join("|"));
+        let simplified = simplify(&expr);
+        assert_eq!(simplified.to_string(), "break|c(?:on(?:st|tinue)|rate)|e(?:lse|num|xtern)|f(?:alse|n|or)");
+        assert!(NFA::from_expr(&simplified).len() < NFA::from_expr(&expr).len());
+    }
+
+    #[test]
+    fn matches_are_unchanged() {
+        let patterns = ["ab|abc|a", "(a|ab)(c|bcd)", "(?:x|y)*(?:)z?", "a(?i)b|ac", "(a)|(a)|b", "a*ab|a*", "a?ab|a?", "a*(a)|a*"];
+        let haystacks = ["abc", "abcd", "xyzz", "aB", "ac", "b", "aab", "ab", "aa"];
+        for pattern in patterns {
+            let nfa = NFA::from_expr(&expr(pattern));
+            let simplified = NFA::from_expr(&simplify(&expr(pattern)));
+            for haystack in haystacks {
+                let expected = Simulation::with_captures(&nfa).captures_at(haystack, 0, false);
+                let actual = Simulation::with_captures(&simplified).captures_at(haystack, 0, false);
+                assert_eq!(actual, expected, "{} on {}", pattern, haystack);
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::sync::OnceLock;
+
+const MIN_CHAR: char = '\u{0}';
+const MAX_CHAR: char = char::MAX;
+
+// surrogates are not valid chars so arithmetic on chars has to jump over them
+const BEFORE_SURROGATES: char = '\u{D7FF}';
+const AFTER_SURROGATES: char = '\u{E000}';
+
+// no character above this one has a different upper or lower case
+const LAST_CASED: char = '\u{1E943}';
+
+// Inclusive range of characters, start is always <= end.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ClassRange {
+    start: char,
+    end: char
+}
+
+impl ClassRange {
+    pub const fn new(start: char, end: char) -> ClassRange {
+        if start <= end {
+            ClassRange { start, end }
+        } else {
+            ClassRange { start: end, end: start }
+        }
+    }
+
+    pub fn start(&self) -> char {
+        self.start
+    }
+
+    pub fn end(&self) -> char {
+        self.end
+    }
+
+    pub fn contains(&self, char: char) -> bool {
+        self.start <= char && char <= self.end
+    }
+}
+
+// Set of characters stored as sorted, non-overlapping and non-adjacent ranges.
+// Every operation keeps ranges in this canonical form, so two classes are equal
+// exactly when they contain the same characters.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct CharClass {
+    ranges: Vec<ClassRange>
+}
+
+impl CharClass {
+    pub fn empty() -> CharClass {
+        CharClass {
+            ranges: Vec::new()
+        }
+    }
+
+    pub fn new(ranges: impl IntoIterator<Item = ClassRange>) -> CharClass {
+        let mut class = CharClass {
+            ranges: ranges.into_iter().collect()
+        };
+        class.canonicalize();
+        class
+    }
+
+    // every character, what "." matches in dot all mode
+    pub fn any() -> CharClass {
+        CharClass::empty().negate()
+    }
+
+    pub fn single(char: char) -> CharClass {
+        CharClass {
+            ranges: vec![ClassRange::new(char, char)]
+        }
+    }
+
+    pub fn ranges(&self) -> &[ClassRange] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, char: char) -> bool {
+        self.ranges
+            .binary_search_by(|range| {
+                if range.end < char {
+                    Ordering::Less
+                } else if range.start > char {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn push(&mut self, start: char, end: char) {
+        self.ranges.push(ClassRange::new(start, end));
+        self.canonicalize();
+    }
+
+    pub fn union(&mut self, other: &CharClass) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.canonicalize();
+    }
+
+    // all characters that are not in the class
+    pub fn negate(&self) -> CharClass {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut next = Some(MIN_CHAR);
+        for range in self.ranges.iter() {
+            if let Some(start) = next {
+                if start < range.start {
+                    // range.start > MIN_CHAR here, so decrement can not fail
+                    ranges.push(ClassRange::new(start, decrement(range.start).unwrap()));
+                }
+            }
+            next = increment(range.end);
+        }
+        if let Some(start) = next {
+            ranges.push(ClassRange::new(start, MAX_CHAR));
+        }
+
+        CharClass { ranges }
+    }
+
+    // POSIX bracket expression classes such as [:alpha:], all of them are ASCII only
+    pub fn posix(name: &str) -> Option<CharClass> {
+        let ranges: &[(char, char)] = match name {
+            "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+            "alpha" => &[('A', 'Z'), ('a', 'z')],
+            "ascii" => &[('\u{0}', '\u{7F}')],
+            "blank" => &[('\t', '\t'), (' ', ' ')],
+            "cntrl" => &[('\u{0}', '\u{1F}'), ('\u{7F}', '\u{7F}')],
+            "digit" => &[('0', '9')],
+            "graph" => &[('!', '~')],
+            "lower" => &[('a', 'z')],
+            "print" => &[(' ', '~')],
+            "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            "space" => &[('\t', '\r'), (' ', ' ')],
+            "upper" => &[('A', 'Z')],
+            "word" => &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+            "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+            _ => return None
+        };
+        Some(CharClass::new(ranges.iter().map(|(start, end)| ClassRange::new(*start, *end))))
+    }
+
+    // Adds every character that has the same case folding as one of the class, so
+    // "k" brings in "K" and the Kelvin sign and each of them brings in the other two.
+    // Only mappings to a single character are used, so "ß" does not match "SS".
+    pub fn case_fold(&self) -> CharClass {
+        let mut folded = self.clone();
+        for range in self.ranges.iter() {
+            let end = range.end.min(LAST_CASED);
+            for char in range.start..=end {
+                for other in case_variants(char) {
+                    if !self.contains(other) {
+                        folded.ranges.push(ClassRange::new(other, other));
+                    }
+                }
+            }
+        }
+        folded.canonicalize();
+        folded
+    }
+
+    // Perl shorthand classes \d, \w and \s, uppercase letters negate them.
+    // They are ASCII only like the POSIX ones, \w agrees with \b on what a word is.
+    pub fn perl(name: char) -> Option<CharClass> {
+        let class = match name.to_ascii_lowercase() {
+            'd' => CharClass::posix("digit"),
+            'w' => CharClass::posix("word"),
+            's' => CharClass::posix("space"),
+            _ => None
+        }?;
+        Some(if name.is_ascii_uppercase() { class.negate() } else { class })
+    }
+
+    // sorts ranges and merges the ones that overlap or touch each other
+    fn canonicalize(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<ClassRange> = Vec::with_capacity(self.ranges.len());
+        for range in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if increment(last.end).is_none_or(|next| next >= range.start) => {
+                    last.end = last.end.max(range.end);
+                }
+                _ => merged.push(range)
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+// Writes class as a bracket expression, "[^...]" is used when it is shorter. The
+// parser rejects "[]", so the empty class is written as the negation of everything.
+impl Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let negated = self.negate();
+        let shorter = !negated.is_empty() && negated.ranges.len() < self.ranges.len();
+        let (caret, ranges) = if self.is_empty() || shorter {
+            ("^", &negated.ranges)
+        } else {
+            ("", &self.ranges)
+        };
+
+        write!(f, "[{}", caret)?;
+        for range in ranges {
+            write!(f, "{}", escape_class_char(range.start))?;
+            if range.start != range.end {
+                write!(f, "-{}", escape_class_char(range.end))?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+// escapes characters that are special inside of a class or can not be seen
+pub(crate) fn escape_class_char(char: char) -> String {
+    match char {
+        '\\' | ']' | '[' | '-' | '^' => format!("\\{}", char),
+        char => escape_invisible(char)
+    }
+}
+
+// control and white space characters other than space are written as escapes, e.g. "\n",
+// and so is char::MAX, a noncharacter that ends ranges such as the one of the empty class
+pub(crate) fn escape_invisible(char: char) -> String {
+    if char.is_control() || char == char::MAX || (char.is_whitespace() && char != ' ') {
+        char.escape_default().to_string()
+    } else {
+        char.to_string()
+    }
+}
+
+// other characters with the same case folding as char, e.g. 'ſ' gives 'S' and 's'
+// and 's' gives 'S' and 'ſ'
+fn case_variants(char: char) -> impl Iterator<Item = char> {
+    let orbits = case_orbits();
+    let key = fold_key(char);
+    let start = orbits.partition_point(|(other, _)| *other < key);
+    orbits[start..]
+        .iter()
+        .take_while(move |(other, _)| *other == key)
+        .map(|(_, other)| *other)
+        .filter(move |other| *other != char)
+}
+
+// lower case of the upper case of char, characters that differ only in case share it
+fn fold_key(char: char) -> char {
+    let upper = single(char.to_uppercase()).unwrap_or(char);
+    single(upper.to_lowercase()).unwrap_or(upper)
+}
+
+// every cased character with its fold key, sorted by the key, so characters with the
+// same key are next to each other no matter which way their case mappings go
+fn case_orbits() -> &'static [(char, char)] {
+    static ORBITS: OnceLock<Vec<(char, char)>> = OnceLock::new();
+    ORBITS.get_or_init(|| {
+        let mut orbits = Vec::new();
+        for char in MIN_CHAR..=LAST_CASED {
+            let key = fold_key(char);
+            if key != char {
+                orbits.push((key, char));
+                orbits.push((key, key));
+            }
+        }
+        orbits.sort_unstable();
+        orbits.dedup();
+        orbits
+    })
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let char = chars.next()?;
+    chars.next().is_none().then_some(char)
+}
+
+pub(crate) fn increment(char: char) -> Option<char> {
+    match char {
+        BEFORE_SURROGATES => Some(AFTER_SURROGATES),
+        MAX_CHAR => None,
+        char => char::from_u32(char as u32 + 1)
+    }
+}
+
+pub(crate) fn decrement(char: char) -> Option<char> {
+    match char {
+        AFTER_SURROGATES => Some(BEFORE_SURROGATES),
+        MIN_CHAR => None,
+        char => char::from_u32(char as u32 - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CharClass, ClassRange};
+
+    fn class(ranges: &[(char, char)]) -> CharClass {
+        CharClass::new(ranges.iter().map(|(start, end)| ClassRange::new(*start, *end)))
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges_are_merged() {
+        let merged = class(&[('d', 'f'), ('a', 'c'), ('x', 'z'), ('e', 'g')]);
+        assert_eq!(merged, class(&[('a', 'g'), ('x', 'z')]));
+    }
+
+    #[test]
+    fn contains_uses_ranges() {
+        let digits_and_lower = class(&[('0', '9'), ('a', 'z')]);
+        assert!(digits_and_lower.contains('5'));
+        assert!(digits_and_lower.contains('q'));
+        assert!(!digits_and_lower.contains('A'));
+        assert!(!digits_and_lower.contains(':'));
+    }
+
+    #[test]
+    fn negate_twice_is_identity() {
+        let original = class(&[('\u{0}', 'a'), ('\u{D7FF}', '\u{E000}'), ('z', 'z')]);
+        let negated = original.negate();
+        assert!(!negated.contains('a'));
+        assert!(negated.contains('b'));
+        assert!(!negated.contains('\u{E000}'));
+        assert!(negated.contains(char::MAX));
+        assert_eq!(negated.negate(), original);
+        assert!(CharClass::empty().negate().negate().is_empty());
+    }
+
+    #[test]
+    fn perl_classes() {
+        let word = CharClass::perl('w').unwrap();
+        assert_eq!(Some(word.clone()), CharClass::posix("word"));
+        assert_eq!(CharClass::perl('W'), Some(word.negate()));
+        assert!(CharClass::perl('s').unwrap().contains('\t'));
+        assert_eq!(CharClass::perl('q'), None);
+        assert!(CharClass::any().contains('\n'));
+    }
+
+    #[test]
+    fn case_fold() {
+        assert_eq!(class(&[('a', 'c')]).case_fold(), class(&[('A', 'C'), ('a', 'c')]));
+        assert_eq!(class(&[('0', '9')]).case_fold(), class(&[('0', '9')]));
+        assert!(class(&[('ä', 'ä')]).case_fold().contains('Ä'));
+        assert!(class(&[('ſ', 'ſ')]).case_fold().contains('s'));
+        assert!(class(&[('s', 's')]).case_fold().contains('ſ'));
+        assert!(class(&[('S', 'S')]).case_fold().contains('ſ'));
+        assert!(class(&[('k', 'k')]).case_fold().contains('\u{212A}'));
+        assert!(class(&[('K', 'K')]).case_fold().contains('\u{212A}'));
+        assert_eq!(class(&[('\u{212A}', '\u{212A}')]).case_fold(), class(&[('K', 'K'), ('k', 'k'), ('\u{212A}', '\u{212A}')]));
+        assert!(!class(&[('ß', 'ß')]).case_fold().contains('S'));
+    }
+
+    #[test]
+    fn display_as_bracket_expression() {
+        assert_eq!(class(&[('a', 'z'), ('_', '_')]).to_string(), "[_a-z]");
+        assert_eq!(class(&[('-', '-'), (']', '^')]).to_string(), "[\\-\\]-\\^]");
+        assert_eq!(class(&[('"', '"')]).negate().to_string(), "[^\"]");
+        assert_eq!(class(&[('\n', '\n')]).to_string(), "[\\n]");
+        assert_eq!(class(&[]).to_string(), "[^\\u{0}-\\u{10ffff}]");
+    }
+}
@@ -0,0 +1,397 @@
+use std::fmt::{self, Debug};
+use std::rc::Rc;
+
+use crate::ast::fold::{fold, Fold};
+use crate::ast::Expr;
+use crate::class::CharClass;
+
+// Regular expression that is matched by taking Brzozowski derivatives. The derivative
+// of a term by a character matches the rest of every string the term matches that starts
+// with the character, so a string matches when the term left after taking the derivative
+// by each of its characters matches the empty string.
+//
+// Terms are only built through the constructors below, they keep every term in a
+// normal form: alternations and intersections are flattened, sorted and without
+// duplicates, concatenations are flattened and the identities of all operators are
+// removed. Terms that only differ by these rules are equal, which is what keeps the
+// number of distinct derivatives of a term finite.
+//
+// Unlike the NFA a term can also be an intersection or a complement. Terms match
+// whole strings and do not know about captures or assertions.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Term(Rc<Node>);
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+enum Node {
+    // matches nothing
+    Nothing,
+    // matches the empty string
+    Empty,
+    Class(CharClass),
+    Concat(Vec<Term>),
+    Star(Term),
+    Or(Vec<Term>),
+    And(Vec<Term>),
+    Not(Term)
+}
+
+impl Term {
+    // None when expr has assertions, derivatives are taken by characters only
+    // and can not see what surrounds them
+    pub fn from_expr(expr: &Expr) -> Option<Term> {
+        let mut terms = TermFold { assertions: false };
+        let term = fold(expr, &mut terms);
+        match terms.assertions {
+            true => None,
+            false => Some(term)
+        }
+    }
+
+    pub fn nothing() -> Term {
+        Term(Rc::new(Node::Nothing))
+    }
+
+    pub fn empty() -> Term {
+        Term(Rc::new(Node::Empty))
+    }
+
+    // matches every string
+    pub fn anything() -> Term {
+        Term::not(&Term::nothing())
+    }
+
+    pub fn class(class: CharClass) -> Term {
+        match class.is_empty() {
+            true => Term::nothing(),
+            false => Term(Rc::new(Node::Class(class)))
+        }
+    }
+
+    pub fn concat(left: &Term, right: &Term) -> Term {
+        match (left.node(), right.node()) {
+            (Node::Nothing, _) | (_, Node::Nothing) => Term::nothing(),
+            (Node::Empty, _) => right.clone(),
+            (_, Node::Empty) => left.clone(),
+            _ => {
+                let mut operands = left.operands_of(|node| matches!(node, Node::Concat(_)));
+                operands.extend(right.operands_of(|node| matches!(node, Node::Concat(_))));
+                Term(Rc::new(Node::Concat(operands)))
+            }
+        }
+    }
+
+    pub fn or(left: &Term, right: &Term) -> Term {
+        let is_or = |node: &Node| matches!(node, Node::Or(_));
+        let mut operands = left.operands_of(is_or);
+        operands.extend(right.operands_of(is_or));
+        if let Some(anything) = operands.iter().find(|term| term.is_anything()) {
+            return anything.clone();
+        }
+        operands.retain(|term| !term.is_nothing());
+
+        // all single characters become one class
+        let mut chars = CharClass::empty();
+        operands.retain(|term| match term.node() {
+            Node::Class(class) => {
+                chars.union(class);
+                false
+            },
+            _ => true
+        });
+        if !chars.is_empty() {
+            operands.push(Term::class(chars));
+        }
+
+        operands.sort();
+        operands.dedup();
+        match operands.len() {
+            0 => Term::nothing(),
+            1 => operands.remove(0),
+            _ => Term(Rc::new(Node::Or(operands)))
+        }
+    }
+
+    pub fn and(left: &Term, right: &Term) -> Term {
+        let is_and = |node: &Node| matches!(node, Node::And(_));
+        let mut operands = left.operands_of(is_and);
+        operands.extend(right.operands_of(is_and));
+        if operands.iter().any(|term| term.is_nothing()) {
+            return Term::nothing();
+        }
+        operands.retain(|term| !term.is_anything());
+        operands.sort();
+        operands.dedup();
+        match operands.len() {
+            0 => Term::anything(),
+            1 => operands.remove(0),
+            _ => Term(Rc::new(Node::And(operands)))
+        }
+    }
+
+    pub fn not(term: &Term) -> Term {
+        match term.node() {
+            Node::Not(term) => term.clone(),
+            _ => Term(Rc::new(Node::Not(term.clone())))
+        }
+    }
+
+    pub fn star(term: &Term) -> Term {
+        match term.node() {
+            Node::Nothing | Node::Empty => Term::empty(),
+            Node::Star(_) => term.clone(),
+            _ => Term(Rc::new(Node::Star(term.clone())))
+        }
+    }
+
+    pub fn is_nothing(&self) -> bool {
+        matches!(self.node(), Node::Nothing)
+    }
+
+    pub fn is_anything(&self) -> bool {
+        matches!(self.node(), Node::Not(term) if term.is_nothing())
+    }
+
+    // true if term matches the empty string
+    pub fn nullable(&self) -> bool {
+        match self.node() {
+            Node::Nothing | Node::Class(_) => false,
+            Node::Empty | Node::Star(_) => true,
+            Node::Concat(terms) | Node::And(terms) => terms.iter().all(|term| term.nullable()),
+            Node::Or(terms) => terms.iter().any(|term| term.nullable()),
+            Node::Not(term) => !term.nullable()
+        }
+    }
+
+    pub fn derivative(&self, char: char) -> Term {
+        match self.node() {
+            Node::Nothing | Node::Empty => Term::nothing(),
+            Node::Class(class) => match class.contains(char) {
+                true => Term::empty(),
+                false => Term::nothing()
+            },
+            Node::Concat(terms) => {
+                let rest = terms[1..]
+                    .iter()
+                    .fold(Term::empty(), |rest, term| Term::concat(&rest, term));
+                let derivative = Term::concat(&terms[0].derivative(char), &rest);
+                match terms[0].nullable() {
+                    true => Term::or(&derivative, &rest.derivative(char)),
+                    false => derivative
+                }
+            },
+            Node::Star(term) => Term::concat(&term.derivative(char), self),
+            Node::Or(terms) => terms
+                .iter()
+                .fold(Term::nothing(), |or, term| Term::or(&or, &term.derivative(char))),
+            Node::And(terms) => terms
+                .iter()
+                .fold(Term::anything(), |and, term| Term::and(&and, &term.derivative(char))),
+            Node::Not(term) => Term::not(&term.derivative(char))
+        }
+    }
+
+    // true if the whole input is matched
+    pub fn is_match(&self, input: &str) -> bool {
+        let mut term = self.clone();
+        for char in input.chars() {
+            term = term.derivative(char);
+            if term.is_nothing() {
+                return false;
+            }
+        }
+        term.nullable()
+    }
+
+    // classes the term is made of, every derivative is made of the same ones
+    pub fn classes(&self) -> Vec<&CharClass> {
+        let mut classes = Vec::new();
+        let mut stack = vec![self];
+        while let Some(term) = stack.pop() {
+            match term.node() {
+                Node::Nothing | Node::Empty => {},
+                Node::Class(class) => classes.push(class),
+                Node::Concat(terms) | Node::Or(terms) | Node::And(terms) => stack.extend(terms),
+                Node::Star(term) | Node::Not(term) => stack.push(term)
+            }
+        }
+        classes
+    }
+
+    fn node(&self) -> &Node {
+        &self.0
+    }
+
+    // operands of self when it is the kind of node is_kind accepts, self otherwise
+    fn operands_of(&self, is_kind: impl Fn(&Node) -> bool) -> Vec<Term> {
+        match self.node() {
+            Node::Concat(terms) | Node::Or(terms) | Node::And(terms) if is_kind(self.node()) => terms.clone(),
+            _ => vec![self.clone()]
+        }
+    }
+}
+
+// S-expression like the one of Expr, "(or 'a' (star \d))"
+impl Debug for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (head, terms) = match self.node() {
+            Node::Nothing => return write!(f, "nothing"),
+            Node::Empty => return write!(f, "empty"),
+            Node::Class(class) => return write!(f, "{}", class),
+            Node::Concat(terms) => ("concat", terms.as_slice()),
+            Node::Or(terms) => ("or", terms.as_slice()),
+            Node::And(terms) => ("and", terms.as_slice()),
+            Node::Star(term) => ("star", std::slice::from_ref(term)),
+            Node::Not(term) => ("not", std::slice::from_ref(term))
+        };
+        write!(f, "({}", head)?;
+        for term in terms {
+            write!(f, " {:?}", term)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// term of every node out of the terms of its children
+struct TermFold {
+    assertions: bool
+}
+
+impl Fold for TermFold {
+    type Output = Term;
+
+    fn fold(&mut self, expr: &Expr, children: Vec<Term>) -> Term {
+        let child = children.first().cloned().unwrap_or_else(Term::empty);
+        match expr {
+            Expr::Empty => Term::empty(),
+            Expr::Literal(literal) => Term::class(CharClass::single(literal.literal())),
+            Expr::Class(class) => Term::class(class.as_ref().clone()),
+            Expr::Escape(escape) => Term::class(escape.class().clone()),
+            Expr::MetaChar(meta) => Term::class(meta.class().clone()),
+            Expr::Assertion(_) => {
+                self.assertions = true;
+                Term::nothing()
+            },
+            Expr::Repetition(_) => Term::star(&child),
+            Expr::ZeroOrOne(_) => Term::or(&child, &Term::empty()),
+            Expr::OneOrMany(_) => Term::concat(&child, &Term::star(&child)),
+            Expr::Bounded(rep) => {
+                let required = (0..rep.min()).fold(Term::empty(), |term, _| Term::concat(&term, &child));
+                let optional = match rep.max() {
+                    None => Term::star(&child),
+                    Some(max) => {
+                        let optional = Term::or(&child, &Term::empty());
+                        (rep.min()..max).fold(Term::empty(), |term, _| Term::concat(&term, &optional))
+                    }
+                };
+                Term::concat(&required, &optional)
+            },
+            Expr::Group(_) => child,
+            Expr::Alternation(_) => children
+                .iter()
+                .fold(Term::nothing(), |or, term| Term::or(&or, term)),
+            Expr::Concatenation(_) => children
+                .iter()
+                .fold(Term::empty(), |concat, term| Term::concat(&concat, term)),
+            Expr::Intersection(_) => children
+                .iter()
+                .fold(Term::anything(), |and, term| Term::and(&and, term)),
+            Expr::Complement(_) => Term::not(&child)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dfa::DFA;
+    use crate::parser::parse;
+
+    use super::Term;
+
+    fn term(pattern: &str) -> Term {
+        let Ok(expr) = parse(pattern) else {
+            panic!("failed to parse {}", pattern)
+        };
+        match Term::from_expr(&expr) {
+            Some(term) => term,
+            None => panic!("{} has assertions", pattern)
+        }
+    }
+
+    // every string over chars up to length
+    fn strings(chars: &[char], length: usize) -> Vec<String> {
+        let mut strings = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..length {
+            last = last
+                .iter()
+                .flat_map(|string| chars.iter().map(move |char| format!("{}{}", string, char)))
+                .collect();
+            strings.extend(last.iter().cloned());
+        }
+        strings
+    }
+
+    #[test]
+    fn similar_terms_are_equal() {
+        assert_eq!(term("a|b|a"), term("[ab]"));
+        assert_eq!(term("(?:ab)c"), term("a(?:bc)"));
+        assert_eq!(term("x|y*"), term("(?:y*)*|x"));
+        assert_eq!(format!("{:?}", term("a(?:)b|c*")), "(or (concat [a] [b]) (star [c]))");
+        assert_eq!(Term::not(&Term::not(&term("a"))), term("a"));
+        assert!(Term::and(&term("a"), &Term::nothing()).is_nothing());
+    }
+
+    #[test]
+    fn matches_whole_strings() {
+        let term = term("(a|bc)*d{1,2}");
+        assert!(term.is_match("d"));
+        assert!(term.is_match("abcadd"));
+        assert!(!term.is_match("abcaddd"));
+        assert!(!term.is_match("bd"));
+        assert!(!term.is_match(""));
+    }
+
+    #[test]
+    fn agrees_with_subset_construction() {
+        let patterns = ["(a|b)*abb", "a*(?:b|ab)+a?", "(?:ab|a)(?:bc|c)", "a{2,3}b*|[ab]{3}", "(a*)*b|\\w"];
+        let chars = ['a', 'b', 'c', '_', '.'];
+        for pattern in patterns {
+            let Ok(subset) = DFA::from_pattern(pattern) else {
+                panic!("failed to compile {}", pattern)
+            };
+            let derivatives = DFA::from_term(&term(pattern)).minimize();
+            assert_eq!(derivatives.number_of_states(), subset.number_of_states(), "{}", pattern);
+            for string in strings(&chars, 5) {
+                assert_eq!(term(pattern).is_match(&string), subset.is_match(&string), "{} on {}", pattern, string);
+                assert_eq!(derivatives.is_match(&string), subset.is_match(&string), "{} on {}", pattern, string);
+            }
+        }
+    }
+
+    #[test]
+    fn intersection_and_complement() {
+        // strings over a and b without two a in a row
+        let no_aa = Term::and(&term("[ab]*"), &Term::not(&term(".*aa.*")));
+        let dfa = DFA::from_term(&no_aa);
+        for string in strings(&['a', 'b', 'c'], 5) {
+            let expected = !string.contains('c') && !string.contains("aa");
+            assert_eq!(no_aa.is_match(&string), expected, "{}", string);
+            assert_eq!(dfa.is_match(&string), expected, "{}", string);
+        }
+        assert_eq!(dfa.minimize().number_of_states(), 2);
+
+        // complement also takes characters no class mentions
+        let not_a = Term::not(&term("a"));
+        assert!(not_a.is_match("\u{10FFFF}"));
+        assert!(DFA::from_term(&not_a).is_match("ö"));
+        assert!(!DFA::from_term(&not_a).is_match("a"));
+    }
+
+    #[test]
+    fn assertions_are_not_supported() {
+        let Ok(expr) = parse("^a\\b") else {
+            panic!("failed to parse")
+        };
+        assert_eq!(Term::from_expr(&expr), None);
+    }
+}
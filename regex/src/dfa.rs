@@ -0,0 +1,750 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use crate::alphabet::{Alphabet, ClassId};
+use crate::ast::Expr;
+use crate::class::CharClass;
+use crate::derivative::Term;
+use crate::error::RegexError;
+use crate::look::Context;
+use crate::thompson::{self, Symbol, NFA};
+use crate::utils::graphviz;
+
+pub type StateId = usize;
+
+// accepting regardless of what follows
+const ACCEPT_ALWAYS: u8 = 0b1111;
+
+// Deterministic automaton. States are numbered 0..number_of_states(),
+// missing transition means that the input is rejected.
+#[derive(Debug, Clone)]
+pub struct DFA<S, T> {
+    states: Vec<S>,
+    transitions: HashMap<S, HashMap<T, S>>,
+    // every symbol that appears on some transition
+    alphabet: Vec<T>,
+    // characters of every symbol, only DFAs built from an NFA have them
+    classes: Alphabet,
+    start: S,
+    // Accepting states with the contexts they accept before, as Context bits.
+    // Only assertions such as $ or \b make this depend on the next character.
+    accepting: HashMap<S, u8>
+}
+
+impl<S, T> DFA<S, T>
+where S: Copy + Eq + Hash,
+      T: Copy + Eq + Hash
+{
+    pub fn new(start: S) -> Self {
+        Self {
+            states: vec![start],
+            transitions: HashMap::new(),
+            alphabet: Vec::new(),
+            classes: Alphabet::default(),
+            start,
+            accepting: HashMap::new()
+        }
+    }
+
+    pub fn add_state(&mut self, state: S) {
+        self.states.push(state);
+    }
+
+    pub fn add_transition(&mut self, from: S, to: S, on: T) {
+        let transitions = self.transitions.entry(from).or_default();
+        transitions.insert(on, to);
+    }
+
+    pub fn set_accepting(&mut self, state: S) {
+        self.set_accepting_before(state, ACCEPT_ALWAYS);
+    }
+
+    // state accepts only when followed by one of the contexts in mask
+    pub fn set_accepting_before(&mut self, state: S, mask: u8) {
+        if mask != 0 {
+            self.accepting.insert(state, mask);
+        }
+    }
+
+    pub fn enumerate_states(&self) -> impl Iterator<Item = (usize, &S)> {
+        self.states.iter().enumerate()
+    }
+
+    pub fn transition_from_on(&self, state: &S, on: T) -> Option<&S> {
+        self.transitions
+            .get(state)
+            .and_then(|transitions| transitions.get(&on))
+    }
+
+    pub fn transitions_from(&self, state: &S) -> impl Iterator<Item = (&T, &S)> {
+        self.transitions
+            .get(state)
+            .into_iter()
+            .flat_map(|transitions| transitions.iter())
+    }
+
+    pub fn alphabet(&self) -> &[T] {
+        &self.alphabet
+    }
+
+    pub fn start(&self) -> S {
+        self.start
+    }
+
+    // accepting at the end of the input
+    pub fn is_accepting(&self, state: &S) -> bool {
+        self.is_accepting_before(state, Context::Edge)
+    }
+
+    pub fn is_accepting_before(&self, state: &S, next: Context) -> bool {
+        self.accept_mask(state) & next.bit() != 0
+    }
+
+    fn accept_mask(&self, state: &S) -> u8 {
+        self.accepting.get(state).copied().unwrap_or(0)
+    }
+
+    pub fn number_of_states(&self) -> usize {
+        self.states.len()
+    }
+}
+
+// DFA recognizing a regular expression. Its symbols are the equivalence classes of
+// characters of the NFA, every character of a class behaves the same way, so there
+// is one transition per class no matter how many characters it has.
+impl DFA<StateId, ClassId> {
+    pub fn from_pattern(pattern: &str) -> Result<Self, RegexError> {
+        NFA::from_pattern(pattern).map(|nfa| DFA::from_nfa(&nfa).minimize())
+    }
+
+    // Subset construction, every DFA state stands for an epsilon closed set of NFA states.
+    //
+    // Look edges can only be resolved once the characters on both sides are known. A state
+    // therefore also remembers the context of the character it was entered on, and follows
+    // look edges right before it moves on the next character. Whether a state accepts
+    // depends on the next character as well, so accepting states carry a mask of contexts.
+    // Without assertions the context is irrelevant and always Edge.
+    pub fn from_nfa(nfa: &NFA) -> Self {
+        let has_looks = nfa.has_looks();
+        let classes = Alphabet::from_nfa(nfa);
+
+        let start = (epsilon_closure(nfa, vec![nfa.start()]), Context::Edge);
+        let mut dfa = DFA::new(0);
+        dfa.alphabet = (0..classes.len()).collect();
+        dfa.set_accepting_before(0, accept_mask(nfa, &start));
+
+        let mut ids = HashMap::from([(start.clone(), 0)]);
+        let mut unmarked = VecDeque::from([start]);
+        while let Some((set, prev)) = unmarked.pop_front() {
+            let from = ids[&(set.clone(), prev)];
+            for class in 0..classes.len() {
+                let char = classes.representative(class);
+                let next = Context::of(Some(char));
+                let moved = look_closure(nfa, set.clone(), prev, next)
+                    .iter()
+                    .flat_map(|state| nfa.transitions(*state))
+                    .filter(|transition| transition.symbol().matches(char))
+                    .map(|transition| transition.next())
+                    .collect();
+                let target = epsilon_closure(nfa, moved);
+                if target.is_empty() {
+                    continue;
+                }
+                let target = (target, if has_looks { next } else { Context::Edge });
+
+                let to = match ids.get(&target) {
+                    Some(to) => *to,
+                    None => {
+                        let to = dfa.number_of_states();
+                        dfa.add_state(to);
+                        dfa.set_accepting_before(to, accept_mask(nfa, &target));
+                        ids.insert(target.clone(), to);
+                        unmarked.push_back(target);
+                        to
+                    }
+                };
+                dfa.add_transition(from, to, class);
+            }
+        }
+        dfa.classes = classes;
+        dfa
+    }
+
+    // Brzozowski's construction, every state is a derivative of term. Derivatives are
+    // compared after normalization, which leaves finitely many of them. Classes also
+    // cover the characters term does not mention, a complement can still match them.
+    pub fn from_term(term: &Term) -> Self {
+        let any = CharClass::any();
+        let classes = Alphabet::from_classes(term.classes().into_iter().chain([&any]), false);
+
+        let mut dfa = DFA::new(0);
+        dfa.alphabet = (0..classes.len()).collect();
+        if term.nullable() {
+            dfa.set_accepting(0);
+        }
+
+        let mut ids = HashMap::from([(term.clone(), 0)]);
+        let mut unmarked = VecDeque::from([term.clone()]);
+        while let Some(term) = unmarked.pop_front() {
+            let from = ids[&term];
+            for class in 0..classes.len() {
+                let target = term.derivative(classes.representative(class));
+                if target.is_nothing() {
+                    continue;
+                }
+                let to = match ids.get(&target) {
+                    Some(to) => *to,
+                    None => {
+                        let to = dfa.number_of_states();
+                        dfa.add_state(to);
+                        if target.nullable() {
+                            dfa.set_accepting(to);
+                        }
+                        ids.insert(target.clone(), to);
+                        unmarked.push_back(target);
+                        to
+                    }
+                };
+                dfa.add_transition(from, to, class);
+            }
+        }
+        dfa.classes = classes;
+        dfa
+    }
+
+    // minimized DFA of an expression, which can use the extended syntax
+    pub fn from_expr(expr: &Expr) -> Self {
+        DFA::from_nfa(&NFA::from_expr(expr)).minimize()
+    }
+
+    // strings both DFAs accept
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.product(other, |left, right| left & right).minimize()
+    }
+
+    // strings either DFA accepts
+    pub fn union(&self, other: &Self) -> Self {
+        self.product(other, |left, right| left | right).minimize()
+    }
+
+    // strings self accepts and other does not
+    pub fn difference(&self, other: &Self) -> Self {
+        self.product(other, |left, right| left & !right).minimize()
+    }
+
+    // strings self does not accept, including the ones with characters it has no class for
+    pub fn complement(&self) -> Self {
+        self.product(self, |left, _| !left & ACCEPT_ALWAYS).minimize()
+    }
+
+    // Product construction, every state is a pair of a state of self and one of other,
+    // None is the dead state. The pair accepts before the contexts op gives for the
+    // masks of its states. Classes of the product split the characters wherever the
+    // classes of either DFA do, and also cover the characters neither of them has a class
+    // for, so that the dead state can move on them too.
+    fn product(&self, other: &Self, op: impl Fn(u8, u8) -> u8) -> Self {
+        let any = CharClass::any();
+        let symbols = self.classes.classes().chain(other.classes.classes()).chain([&any]);
+        let classes = Alphabet::from_classes(symbols, false);
+
+        type Pair = (Option<StateId>, Option<StateId>);
+        let mask = |(left, right): Pair| {
+            let left = left.map_or(0, |state| self.accept_mask(&state));
+            let right = right.map_or(0, |state| other.accept_mask(&state));
+            op(left, right) & ACCEPT_ALWAYS
+        };
+        // pair of dead states stays dead unless op accepts what neither does
+        let dead_accepts = mask((None, None)) != 0;
+
+        let start = (Some(self.start), Some(other.start));
+        let mut dfa = DFA::new(0);
+        dfa.alphabet = (0..classes.len()).collect();
+        dfa.set_accepting_before(0, mask(start));
+
+        let mut ids = HashMap::from([(start, 0)]);
+        let mut unmarked = VecDeque::from([start]);
+        while let Some((left, right)) = unmarked.pop_front() {
+            let from = ids[&(left, right)];
+            for class in 0..classes.len() {
+                let char = classes.representative(class);
+                let target = (
+                    left.and_then(|state| self.next_state(state, char)),
+                    right.and_then(|state| other.next_state(state, char))
+                );
+                if target == (None, None) && !dead_accepts {
+                    continue;
+                }
+                let to = match ids.get(&target) {
+                    Some(to) => *to,
+                    None => {
+                        let to = dfa.number_of_states();
+                        dfa.add_state(to);
+                        dfa.set_accepting_before(to, mask(target));
+                        ids.insert(target, to);
+                        unmarked.push_back(target);
+                        to
+                    }
+                };
+                dfa.add_transition(from, to, class);
+            }
+        }
+        dfa.classes = classes;
+        dfa
+    }
+
+    // Shortest string in the language, None when the language is empty. Breadth first
+    // search tries the classes in the order of their readable characters, so among the
+    // shortest strings it finds the smallest one that uses only those characters.
+    pub fn shortest_string(&self) -> Option<String> {
+        let mut chars = (0..self.classes.len())
+            .map(|class| (self.classes.readable(class), class))
+            .collect::<Vec<_>>();
+        chars.sort();
+
+        // state it was reached from and on which character
+        let mut parents: HashMap<StateId, Option<(StateId, char)>> = HashMap::from([(self.start, None)]);
+        let mut queue = VecDeque::from([self.start]);
+        while let Some(state) = queue.pop_front() {
+            if self.is_accepting(&state) {
+                let mut string = Vec::new();
+                let mut current = state;
+                while let Some(Some((parent, char))) = parents.get(&current) {
+                    string.push(*char);
+                    current = *parent;
+                }
+                return Some(string.into_iter().rev().collect());
+            }
+            for (char, class) in &chars {
+                let Some(next) = self.transition_from_on(&state, *class) else {
+                    continue;
+                };
+                if !parents.contains_key(next) {
+                    parents.insert(*next, Some((state, *char)));
+                    queue.push_back(*next);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn classes(&self) -> &Alphabet {
+        &self.classes
+    }
+
+    // state reached from state on char, None when char is rejected
+    pub fn next_state(&self, state: StateId, char: char) -> Option<StateId> {
+        let class = self.classes.class_of(char)?;
+        self.transition_from_on(&state, class).copied()
+    }
+
+    // true if the whole input is in the language
+    pub fn is_match(&self, input: &str) -> bool {
+        let mut state = self.start;
+        for char in input.chars() {
+            match self.next_state(state, char) {
+                Some(next) => state = next,
+                None => return false
+            }
+        }
+        self.is_accepting(&state)
+    }
+
+    // byte length of the longest prefix of input that is in the language
+    pub fn longest_match(&self, input: &str) -> Option<usize> {
+        let mut state = self.start;
+        let mut longest = None;
+        let chars = input
+            .char_indices()
+            .map(|(offset, char)| (offset, Some(char)))
+            .chain(std::iter::once((input.len(), None)));
+        for (offset, char) in chars {
+            if self.is_accepting_before(&state, Context::of(char)) {
+                longest = Some(offset);
+            }
+            let Some(char) = char else {
+                break;
+            };
+            match self.next_state(state, char) {
+                Some(next) => state = next,
+                None => break
+            }
+        }
+        longest
+    }
+
+    // DOT description of the DFA, states accepting at the end of input are drawn with double circles.
+    // All classes leading to the same state are merged into a single label.
+    pub fn to_graphviz_string(&self) -> String {
+        let mut graph = String::new();
+        graphviz::header(&mut graph, "finite_state_machine");
+        graph.push_str("rankdir=LR;\n");
+        let mut accepting = (0..self.number_of_states())
+            .filter(|state| self.is_accepting(state))
+            .collect::<Vec<_>>();
+        accepting.sort_unstable();
+        let accepting = accepting.iter().map(|state| state.to_string()).collect::<Vec<_>>();
+        if !accepting.is_empty() {
+            graph.push_str(&format!("node [shape = doublecircle]; {};\n", accepting.join(" ")));
+        }
+        graph.push_str("node [shape = circle];\n");
+        graph.push_str("__start [shape = point];\n");
+        graph.push_str(&format!("__start -> {};\n", self.start));
+
+        for from in 0..self.number_of_states() {
+            let mut targets: Vec<(StateId, CharClass)> = Vec::new();
+            for symbol in self.alphabet.iter() {
+                let Some(to) = self.transition_from_on(&from, *symbol) else {
+                    continue;
+                };
+                let class = self.classes.class(*symbol);
+                match targets.iter_mut().find(|(target, _)| target == to) {
+                    Some((_, label)) => label.union(class),
+                    None => targets.push((*to, class.clone()))
+                }
+            }
+            for (to, class) in targets {
+                let label = edge_label(class);
+                graph.push_str(&format!("{} -> {} [label = \"{}\"];\n", from, to, graphviz::escape(&label)));
+            }
+        }
+        graph.push_str("}\n");
+        graph
+    }
+}
+
+// single characters are written as they are, everything else as a class
+fn edge_label(class: CharClass) -> String {
+    match class.ranges() {
+        [range] if range.start() == range.end() => crate::class::escape_invisible(range.start()),
+        _ => class.to_string()
+    }
+}
+
+impl<T> DFA<StateId, T>
+where T: Copy + Eq + Hash
+{
+    // Hopcroft's partition refinement. States start split into blocks by the
+    // contexts they accept before, a block is split whenever some of its states move into
+    // a splitter block on a symbol and others do not. Only the smaller half of
+    // a split has to be used as a splitter later, which gives O(n log n).
+    pub fn minimize(&self) -> Self {
+        let states = self.number_of_states();
+        // transitions are partial, missing ones go to an implicit dead state
+        let dead = states;
+
+        let mut inverse: HashMap<(StateId, T), Vec<StateId>> = HashMap::new();
+        for from in 0..=dead {
+            for symbol in self.alphabet.iter() {
+                let to = self.transition_from_on(&from, *symbol).copied().unwrap_or(dead);
+                inverse.entry((to, *symbol)).or_default().push(from);
+            }
+        }
+
+        let mut by_mask: BTreeMap<u8, Vec<StateId>> = BTreeMap::new();
+        for state in 0..=dead {
+            by_mask.entry(self.accept_mask(&state)).or_default().push(state);
+        }
+        let mut blocks: Vec<Vec<StateId>> = by_mask.into_values().collect();
+        let mut block_of = vec![0; dead + 1];
+        for (index, block) in blocks.iter().enumerate() {
+            block.iter().for_each(|state| block_of[*state] = index);
+        }
+
+        let mut pending = vec![true; blocks.len()];
+        let mut splitters: Vec<usize> = (0..blocks.len()).collect();
+        while let Some(splitter) = splitters.pop() {
+            pending[splitter] = false;
+            let splitter_states = blocks[splitter].clone();
+            for symbol in self.alphabet.iter() {
+                // states that move into the splitter on symbol, grouped by their block
+                let mut touched: HashMap<usize, Vec<StateId>> = HashMap::new();
+                for state in splitter_states.iter() {
+                    for from in inverse.get(&(*state, *symbol)).into_iter().flatten() {
+                        touched.entry(block_of[*from]).or_default().push(*from);
+                    }
+                }
+
+                for (block, moved) in touched {
+                    if moved.len() == blocks[block].len() {
+                        continue;
+                    }
+                    let new_block = blocks.len();
+                    moved.iter().for_each(|state| block_of[*state] = new_block);
+                    blocks[block].retain(|state| block_of[*state] == block);
+                    blocks.push(moved);
+
+                    if pending[block] {
+                        pending.push(true);
+                        splitters.push(new_block);
+                    } else {
+                        let smaller = if blocks[new_block].len() < blocks[block].len() {
+                            new_block
+                        } else {
+                            block
+                        };
+                        pending.push(smaller == new_block);
+                        pending[smaller] = true;
+                        splitters.push(smaller);
+                    }
+                }
+            }
+        }
+
+        // states equivalent to the dead one can never accept, they are dropped.
+        // The rest is renumbered in breadth first order from the start state.
+        let dead_block = block_of[dead];
+        let mut ids: HashMap<usize, StateId> = HashMap::from([(block_of[self.start], 0)]);
+        let mut queue = VecDeque::from([block_of[self.start]]);
+        let mut minimized = DFA::new(0);
+        minimized.alphabet = self.alphabet.clone();
+        minimized.classes = self.classes.clone();
+        while let Some(block) = queue.pop_front() {
+            let from = ids[&block];
+            let representative = blocks[block][0];
+            minimized.set_accepting_before(from, self.accept_mask(&representative));
+            for symbol in self.alphabet.iter() {
+                let Some(to) = self.transition_from_on(&representative, *symbol) else {
+                    continue;
+                };
+                let target = block_of[*to];
+                if target == dead_block {
+                    continue;
+                }
+                let to = *ids.entry(target).or_insert_with(|| {
+                    queue.push_back(target);
+                    minimized.states.len()
+                });
+                if to == minimized.states.len() {
+                    minimized.add_state(to);
+                }
+                minimized.add_transition(from, to, *symbol);
+            }
+        }
+        minimized
+    }
+}
+
+// all NFA states reachable from states through epsilon transitions, sorted
+pub(crate) fn epsilon_closure(nfa: &NFA, states: Vec<thompson::StateId>) -> Vec<thompson::StateId> {
+    closure(nfa, states, Symbol::is_epsilon)
+}
+
+// epsilon closure that also follows look edges holding between prev and next
+pub(crate) fn look_closure(
+    nfa: &NFA,
+    states: Vec<thompson::StateId>,
+    prev: Context,
+    next: Context
+) -> Vec<thompson::StateId> {
+    closure(nfa, states, |symbol| {
+        symbol.is_epsilon() || symbol.look().is_some_and(|look| look.holds(prev, next))
+    })
+}
+
+// contexts before which the NFA states accept, as Context bits
+fn accept_mask(nfa: &NFA, (states, prev): &(Vec<thompson::StateId>, Context)) -> u8 {
+    Context::ALL
+        .iter()
+        .filter(|next| look_closure(nfa, states.clone(), *prev, **next).contains(&nfa.accept()))
+        .fold(0, |mask, next| mask | next.bit())
+}
+
+fn closure<F>(nfa: &NFA, mut states: Vec<thompson::StateId>, follow: F) -> Vec<thompson::StateId>
+where F: Fn(&Symbol) -> bool
+{
+    let mut closure = HashSet::new();
+    while let Some(state) = states.pop() {
+        if !closure.insert(state) {
+            continue;
+        }
+        nfa.transitions(state)
+            .filter(|transition| follow(transition.symbol()))
+            .for_each(|transition| states.push(transition.next()));
+    }
+    let mut closure = closure.into_iter().collect::<Vec<_>>();
+    closure.sort_unstable();
+    closure
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alphabet::ClassId;
+    use crate::class::{CharClass, ClassRange};
+    use crate::look::Context;
+    use crate::parser::{parse_with, Config};
+    use crate::thompson::NFA;
+
+    use super::{StateId, DFA};
+
+    fn dfa(pattern: &str) -> DFA<StateId, ClassId> {
+        let Ok(nfa) = NFA::from_pattern(pattern) else {
+            panic!("failed to compile {}", pattern)
+        };
+        DFA::from_nfa(&nfa)
+    }
+
+    #[test]
+    fn subset_construction() {
+        // the running example from the dragon book, states A to E
+        let dfa = dfa("(a|b)*abb");
+        assert_eq!(dfa.number_of_states(), 5);
+        assert!(dfa.is_match("abb"));
+        assert!(dfa.is_match("aababb"));
+        assert!(!dfa.is_match("abab"));
+    }
+
+    #[test]
+    fn minimization() {
+        let minimized = dfa("(a|b)*abb").minimize();
+        assert_eq!(minimized.number_of_states(), 4);
+        assert!(minimized.is_match("babb"));
+        assert!(!minimized.is_match("bab"));
+
+        // all of them accept a non empty sequence of a's
+        for pattern in ["a+", "aa*", "a*a", "(a|a)+", "a+a*", "a{1,}"] {
+            let minimized = dfa(pattern).minimize();
+            assert_eq!(minimized.number_of_states(), 2, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn minimization_drops_dead_states() {
+        let minimized = dfa("ab|ac").minimize();
+        assert_eq!(minimized.number_of_states(), 3);
+        assert_eq!(minimized.start(), 0);
+    }
+
+    #[test]
+    fn transitions_on_equivalence_classes() {
+        let dfa = dfa("[a-z]+|[0-9]x");
+        assert_eq!(dfa.alphabet(), &[0, 1, 2]);
+        assert_eq!(dfa.classes().class(2), &CharClass::new([ClassRange::new('x', 'x')]));
+        assert_eq!(dfa.transitions_from(&dfa.start()).count(), 3);
+        let minimized = dfa.minimize();
+        assert!(minimized.is_match("hello"));
+        assert!(minimized.is_match("7x"));
+        assert!(minimized.is_match("ax"));
+        assert!(!minimized.is_match("7y"));
+    }
+
+    #[test]
+    fn negated_class() {
+        let minimized = dfa("\"[^\"]*\"").minimize();
+        assert_eq!(minimized.number_of_states(), 3);
+        assert!(minimized.is_match("\"hello, wörld\""));
+        assert!(!minimized.is_match("\"a\"b\""));
+    }
+
+    #[test]
+    fn graphviz() {
+        let expected = "\
+digraph finite_state_machine {
+fontname=\"Helvetica,Arial,sans-serif\"
+node [fontname=\"Helvetica,Arial,sans-serif\"]
+edge [fontname=\"Helvetica,Arial,sans-serif\"]
+rankdir=LR;
+node [shape = doublecircle]; 1;
+node [shape = circle];
+__start [shape = point];
+__start -> 0;
+0 -> 1 [label = \"[0-9a-f]\"];
+1 -> 1 [label = \"[0-9a-f]\"];
+}
+";
+        assert_eq!(dfa("[0-9]+|[a-f]+|[0-9a-f]+").minimize().to_graphviz_string(), expected);
+    }
+
+    #[test]
+    fn longest_match() {
+        let minimized = dfa("[0-9]+(\\.[0-9]+)?").minimize();
+        assert_eq!(minimized.longest_match("3.14)"), Some(4));
+        assert_eq!(minimized.longest_match("42."), Some(2));
+        assert_eq!(minimized.longest_match("x"), None);
+    }
+
+    #[test]
+    fn anchors() {
+        let minimized = dfa("^(ab)*$").minimize();
+        assert!(minimized.is_match(""));
+        assert!(minimized.is_match("abab"));
+        assert!(!minimized.is_match("aba"));
+        // $ can not match in front of a character
+        assert_eq!(dfa("a*$").minimize().longest_match("aab"), None);
+        assert_eq!(dfa("a*$").minimize().longest_match("aa"), Some(2));
+    }
+
+    #[test]
+    fn word_boundaries() {
+        let minimized = dfa("[a-z]+\\b").minimize();
+        assert_eq!(minimized.longest_match("abc def"), Some(3));
+        assert_eq!(minimized.longest_match("abc1"), None);
+        assert!(minimized.is_match("abc"));
+
+        let inner = dfa("a\\Bb").minimize();
+        assert!(inner.is_match("ab"));
+        assert!(!dfa("a\\bb").minimize().is_match("ab"));
+        assert!(dfa("a\\b \\bb").minimize().is_match("a b"));
+    }
+
+    #[test]
+    fn accepting_depends_on_next_char() {
+        let dfa = dfa("a\\b");
+        let Some(state) = dfa.next_state(dfa.start(), 'a') else {
+            panic!("a is accepted")
+        };
+        assert!(dfa.is_accepting(&state));
+        assert!(dfa.is_accepting_before(&state, Context::Other));
+        assert!(!dfa.is_accepting_before(&state, Context::Word));
+    }
+
+    #[test]
+    fn shortest_string() {
+        assert_eq!(dfa("(a|b)*abb").minimize().shortest_string(), Some("abb".to_string()));
+        assert_eq!(dfa("x*").minimize().shortest_string(), Some(String::new()));
+        assert_eq!(dfa("[^a-z]\\d").minimize().shortest_string(), Some("!0".to_string()));
+        assert_eq!(dfa("a").minimize().difference(&dfa("a|b").minimize()).shortest_string(), None);
+    }
+
+    #[test]
+    fn language_operations() {
+        let identifiers = dfa("[a-z]+").minimize();
+        let keywords = dfa("if|else").minimize();
+        let short = dfa("[a-z]{1,3}").minimize();
+
+        let both = identifiers.intersection(&short);
+        assert!(both.is_match("abc"));
+        assert!(!both.is_match("abcd"));
+
+        let either = keywords.union(&dfa("[0-9]+").minimize());
+        assert!(either.is_match("else"));
+        assert!(either.is_match("42"));
+        assert!(!either.is_match("else42"));
+
+        // identifiers that are not keywords
+        let names = identifiers.difference(&keywords);
+        assert!(names.is_match("iff"));
+        assert!(names.is_match("el"));
+        assert!(!names.is_match("if"));
+        assert!(!names.is_match("Else"));
+
+        let not = keywords.complement();
+        assert!(not.is_match(""));
+        assert!(not.is_match("ifx"));
+        assert!(not.is_match("€"));
+        assert!(!not.is_match("else"));
+        assert_eq!(not.complement().number_of_states(), keywords.number_of_states());
+    }
+
+    #[test]
+    fn extended_expressions() {
+        // a block comment does not contain "*/" before its end
+        let Ok(expr) = parse_with("/\\*~(.*\\*/.*)\\*/", &Config::new().extended(true).dot_matches_new_line(true)) else {
+            panic!("failed to parse block comment")
+        };
+        let comment = DFA::from_expr(&expr);
+        assert!(comment.is_match("/* a\n * b */"));
+        assert!(comment.is_match("/**/"));
+        assert!(!comment.is_match("/* a */ b */"));
+        assert_eq!(comment.longest_match("/* a */ b */"), Some(7));
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt::{self, Display};
+
+// Error returned when a pattern can not be turned into a regular expression.
+// It keeps a copy of the pattern so that it can point at the offending
+// character when displayed:
+//
+//     regex parse error: unbalanced parenthesis
+//         a(b|c
+//          ^
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RegexError {
+    kind: ErrorKind,
+    offset: usize,
+    pattern: String
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ErrorKind {
+    // "(" without matching ")" or ")" without matching "("
+    UnbalancedParen,
+    // quantifier that does not follow an expression, e.g. "*a" or "a|+"
+    DanglingQuantifier,
+    // "\" followed by a character that can not be escaped or by nothing
+    BadEscape,
+    // alternation with a missing side, e.g. "a|" or "(|b)"
+    EmptyAlternationBranch,
+    // metacharacter that is used outside of its context, e.g. "a}"
+    UnescapedMetaChar(char),
+    // "[" without matching "]"
+    UnclosedClass,
+    // class that has no members, e.g. "[]"
+    EmptyClass,
+    // range whose end comes before its start, e.g. "[z-a]"
+    InvalidRange,
+    // POSIX class that does not exist, e.g. "[[:alfa:]]"
+    UnknownClassName,
+    // malformed counted repetition, e.g. "a{2,1}" or "a{,3}"
+    InvalidRepetition,
+    // counted repetition that expands above the configured size limit
+    RepetitionTooLarge,
+    // "(?" followed by something other than ":" or "<name>", e.g. "(?=a)"
+    InvalidGroup,
+    // unknown inline flag or a flag group that is malformed, e.g. "(?iq)" or "(?i-)"
+    InvalidFlag,
+    // capture group name that is empty or not an identifier, e.g. "(?<1>a)"
+    InvalidCaptureName,
+    // two capture groups with the same name
+    DuplicateCaptureName,
+    // operator of the extended syntax with a missing operand, e.g. "a&" or "(~)"
+    MissingOperand,
+    // assertion inside of an operand of "&" or "~", e.g. "~^a"
+    AssertionInSetOperation,
+}
+
+impl RegexError {
+    pub(crate) fn new(kind: ErrorKind, offset: usize, pattern: &str) -> RegexError {
+        RegexError {
+            kind,
+            offset,
+            pattern: pattern.to_string()
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    // byte offset into the pattern where the error was detected
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnbalancedParen => write!(f, "unbalanced parenthesis"),
+            ErrorKind::DanglingQuantifier => write!(f, "quantifier does not follow an expression"),
+            ErrorKind::BadEscape => write!(f, "invalid escape sequence"),
+            ErrorKind::EmptyAlternationBranch => write!(f, "empty alternation branch"),
+            ErrorKind::UnescapedMetaChar(char) => write!(f, "metacharacter '{}' must be escaped", char),
+            ErrorKind::UnclosedClass => write!(f, "unclosed character class"),
+            ErrorKind::EmptyClass => write!(f, "empty character class"),
+            ErrorKind::InvalidRange => write!(f, "invalid character class range"),
+            ErrorKind::UnknownClassName => write!(f, "unknown POSIX character class"),
+            ErrorKind::InvalidRepetition => write!(f, "invalid counted repetition"),
+            ErrorKind::RepetitionTooLarge => write!(f, "counted repetition exceeds size limit"),
+            ErrorKind::InvalidGroup => write!(f, "invalid group"),
+            ErrorKind::InvalidFlag => write!(f, "invalid inline flag"),
+            ErrorKind::InvalidCaptureName => write!(f, "invalid capture group name"),
+            ErrorKind::DuplicateCaptureName => write!(f, "duplicate capture group name"),
+            ErrorKind::MissingOperand => write!(f, "operator is missing an operand"),
+            ErrorKind::AssertionInSetOperation => write!(f, "assertions are not allowed in operands of '&' and '~'"),
+        }
+    }
+}
+
+impl Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // offset is in bytes, the caret has to be placed in characters
+        let column = self.pattern
+            .get(..self.offset)
+            .map_or(0, |prefix| prefix.chars().count());
+
+        writeln!(f, "regex parse error: {}", self.kind)?;
+        writeln!(f, "    {}", self.pattern)?;
+        write!(f, "    {}^", " ".repeat(column))
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorKind, RegexError};
+
+    #[test]
+    fn caret_points_at_offset() {
+        let error = RegexError::new(ErrorKind::UnbalancedParen, 1, "a(b|c");
+        let expected = "regex parse error: unbalanced parenthesis\n    a(b|c\n     ^";
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn caret_counts_characters_not_bytes() {
+        let error = RegexError::new(ErrorKind::DanglingQuantifier, 3, "ä|*");
+        let expected = "regex parse error: quantifier does not follow an expression\n    ä|*\n      ^";
+        assert_eq!(error.to_string(), expected);
+    }
+}
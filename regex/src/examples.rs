@@ -0,0 +1,436 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::alphabet::ClassId;
+use crate::ast::Expr;
+use crate::dfa::{StateId, DFA};
+
+// Strings of the language of a DFA, for showing what a pattern matches or for
+// generating test inputs:
+//
+//     let examples = Examples::from_expr(&parse("[a-z]+[0-9]?")?);
+//     examples.shortest_first().take(4)   // "a", "a0", "aa", "aa0"
+//     examples.lexicographic(3)           // "a", "a0", "aa", "aa0", "aaa"
+//
+// A class of characters stands for all of them, so enumeration spells every class
+// with its readable character and strings only differ in the classes they go
+// through. Random samples pick any visible ASCII character of a class instead.
+pub struct Examples {
+    dfa: DFA<StateId, ClassId>,
+    // readable character of every class and the class, sorted by the character
+    chars: Vec<(char, ClassId)>,
+    // visible ASCII characters of every class, its readable character when it has none
+    members: Vec<Vec<char>>,
+    // length of the shortest string accepted from every state, None if nothing is
+    distances: Vec<Option<usize>>
+}
+
+impl Examples {
+    pub fn new(dfa: DFA<StateId, ClassId>) -> Examples {
+        let classes = dfa.classes();
+        let mut chars = (0..classes.len())
+            .map(|class| (classes.readable(class), class))
+            .collect::<Vec<_>>();
+        chars.sort();
+        let members = (0..classes.len())
+            .map(|class| {
+                let visible = (' '..='~')
+                    .filter(|char| classes.class(class).contains(*char))
+                    .collect::<Vec<_>>();
+                match visible.is_empty() {
+                    true => vec![classes.readable(class)],
+                    false => visible
+                }
+            })
+            .collect();
+
+        let mut examples = Examples {
+            dfa,
+            chars,
+            members,
+            distances: Vec::new()
+        };
+        examples.distances = examples.distances();
+        examples
+    }
+
+    pub fn from_expr(expr: &Expr) -> Examples {
+        Examples::new(DFA::from_expr(expr))
+    }
+
+    // Every accepted string, shorter ones first and strings of the same length in
+    // lexicographic order. The iterator is endless when the language is infinite.
+    pub fn shortest_first(&self) -> ShortestFirst<'_> {
+        let queue = match self.distances[self.dfa.start()] {
+            Some(_) => VecDeque::from([(self.dfa.start(), String::new())]),
+            None => VecDeque::new()
+        };
+        ShortestFirst {
+            examples: self,
+            queue
+        }
+    }
+
+    // Accepted strings of at most max_length characters in lexicographic order, a
+    // string comes right before the ones it is a prefix of.
+    pub fn lexicographic(&self, max_length: usize) -> Lexicographic<'_> {
+        let start = self.dfa.start();
+        let stack = match self.distances[start] {
+            Some(distance) if distance <= max_length => vec![(start, String::new(), 0)],
+            _ => Vec::new()
+        };
+        Lexicographic {
+            examples: self,
+            stack,
+            max_length
+        }
+    }
+
+    // Endless sequence of random accepted strings of at most max_length characters,
+    // the same seed gives the same sequence. Every string the enumeration would give
+    // is equally likely, empty when there is none that short.
+    pub fn sample(&self, seed: u64, max_length: usize) -> Sample<'_> {
+        // counts[length][state] is the logarithm of the number of strings of at most length
+        // characters accepted from state, the numbers themselves overflow f64 quickly
+        let mut counts = vec![vec![f64::NEG_INFINITY; self.distances.len()]];
+        for length in 0..=max_length {
+            let shorter = counts.last().cloned().unwrap_or_default();
+            let count = (0..self.distances.len())
+                .map(|state| {
+                    let accepts = if self.dfa.is_accepting(&state) { 0.0 } else { f64::NEG_INFINITY };
+                    match length {
+                        0 => accepts,
+                        _ => log_sum(self.targets(state).map(|(_, _, next)| shorter[next]).chain([accepts]))
+                    }
+                })
+                .collect();
+            counts.push(count);
+        }
+        counts.remove(0);
+        Sample {
+            examples: self,
+            counts,
+            random: Random::new(seed)
+        }
+    }
+
+    // Up to count strings that are rejected but only one edit away from an accepted
+    // one: a character deleted, inserted or replaced. Edits insert the readable
+    // characters of the classes and a character that is in none of them. Shorter
+    // strings come first, near misses of the shortest examples are tried first.
+    pub fn near_misses(&self, count: usize) -> Vec<String> {
+        let outside = ('!'..='~')
+            .chain(['\u{FFFD}'])
+            .find(|char| self.dfa.classes().class_of(*char).is_none());
+        let edits = self.chars
+            .iter()
+            .map(|(char, _)| *char)
+            .chain(outside)
+            .collect::<Vec<_>>();
+
+        let mut misses = BTreeSet::new();
+        for example in self.shortest_first().take(count) {
+            let chars = example.chars().collect::<Vec<_>>();
+            let mut candidates = Vec::new();
+            for at in 0..=chars.len() {
+                for edit in &edits {
+                    candidates.push([&chars[..at], &[*edit], &chars[at..]].concat());
+                    if at < chars.len() {
+                        candidates.push([&chars[..at], &[*edit], &chars[at + 1..]].concat());
+                    }
+                }
+                if at < chars.len() {
+                    candidates.push([&chars[..at], &chars[at + 1..]].concat());
+                }
+            }
+            misses.extend(candidates
+                .into_iter()
+                .map(|candidate| candidate.into_iter().collect::<String>())
+                .filter(|candidate| !self.dfa.is_match(candidate))
+                .map(|candidate| (candidate.chars().count(), candidate)));
+        }
+        misses.into_iter().take(count).map(|(_, miss)| miss).collect()
+    }
+
+    // characters out of state with the class and state they lead to, in the order of the characters
+    fn targets(&self, state: StateId) -> impl Iterator<Item = (char, ClassId, StateId)> + '_ {
+        self.chars
+            .iter()
+            .filter_map(move |(char, class)| {
+                let next = self.dfa.transition_from_on(&state, *class)?;
+                Some((*char, *class, *next))
+            })
+    }
+
+    // breadth first search backwards from the accepting states
+    fn distances(&self) -> Vec<Option<usize>> {
+        let states = self.dfa.number_of_states();
+        let mut sources = vec![Vec::new(); states];
+        for state in 0..states {
+            for (_, _, next) in self.targets(state) {
+                sources[next].push(state);
+            }
+        }
+        let mut distances = vec![None; states];
+        let mut queue = VecDeque::new();
+        for state in (0..states).filter(|state| self.dfa.is_accepting(state)) {
+            distances[state] = Some(0);
+            queue.push_back(state);
+        }
+        while let Some(state) = queue.pop_front() {
+            let distance = distances[state].unwrap_or_default() + 1;
+            for source in &sources[state] {
+                if distances[*source].is_none() {
+                    distances[*source] = Some(distance);
+                    queue.push_back(*source);
+                }
+            }
+        }
+        distances
+    }
+}
+
+// Breadth first over all paths from the start, paths that can not reach an
+// accepting state are never taken, so every string in the queue leads to an example.
+pub struct ShortestFirst<'e> {
+    examples: &'e Examples,
+    queue: VecDeque<(StateId, String)>
+}
+
+impl Iterator for ShortestFirst<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some((state, string)) = self.queue.pop_front() {
+            for (char, _, next) in self.examples.targets(state) {
+                if self.examples.distances[next].is_some() {
+                    let mut longer = string.clone();
+                    longer.push(char);
+                    self.queue.push_back((next, longer));
+                }
+            }
+            if self.examples.dfa.is_accepting(&state) {
+                return Some(string);
+            }
+        }
+        None
+    }
+}
+
+// Depth first over the paths that reach an accepting state within max_length,
+// the stack holds the state, the string and its length in characters.
+pub struct Lexicographic<'e> {
+    examples: &'e Examples,
+    stack: Vec<(StateId, String, usize)>,
+    max_length: usize
+}
+
+impl Iterator for Lexicographic<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some((state, string, length)) = self.stack.pop() {
+            let reachable = self.examples
+                .targets(state)
+                .filter(|(_, _, next)| {
+                    self.examples.distances[*next].is_some_and(|distance| length + 1 + distance <= self.max_length)
+                })
+                .collect::<Vec<_>>();
+            // pushed in reverse so that the smallest character is popped first
+            for (char, _, next) in reachable.into_iter().rev() {
+                let mut longer = string.clone();
+                longer.push(char);
+                self.stack.push((next, longer, length + 1));
+            }
+            if self.examples.dfa.is_accepting(&state) {
+                return Some(string);
+            }
+        }
+        None
+    }
+}
+
+pub struct Sample<'e> {
+    examples: &'e Examples,
+    counts: Vec<Vec<f64>>,
+    random: Random
+}
+
+impl Iterator for Sample<'_> {
+    type Item = String;
+
+    // Walks from the start and picks every step with a probability proportional to the
+    // number of strings it leaves, stopping counts as one when the state accepts. The
+    // probabilities are ratios of counts, so they are taken in log space as well.
+    fn next(&mut self) -> Option<String> {
+        let max_length = self.counts.len() - 1;
+        let mut state = self.examples.dfa.start();
+        if self.counts[max_length][state] == f64::NEG_INFINITY {
+            return None;
+        }
+        let mut string = String::new();
+        for remaining in (0..=max_length).rev() {
+            let total = self.counts[remaining][state];
+            let mut pick = self.random.float();
+            if self.examples.dfa.is_accepting(&state) {
+                pick -= (-total).exp();
+                if pick < 0.0 {
+                    break;
+                }
+            }
+            if remaining == 0 {
+                break;
+            }
+            let mut choice = None;
+            for (_, class, next) in self.examples.targets(state) {
+                let count = self.counts[remaining - 1][next];
+                if count == f64::NEG_INFINITY {
+                    continue;
+                }
+                choice = Some((class, next));
+                pick -= (count - total).exp();
+                if pick < 0.0 {
+                    break;
+                }
+            }
+            // rounding can leave pick a little above 0, the last choice takes it then
+            let Some((class, next)) = choice else {
+                break;
+            };
+            let members = &self.examples.members[class];
+            string.push(members[self.random.below(members.len())]);
+            state = next;
+        }
+        Some(string)
+    }
+}
+
+// logarithm of the sum of the numbers whose logarithms are given, scaled by the
+// largest one so that nothing overflows
+fn log_sum(logs: impl Iterator<Item = f64>) -> f64 {
+    let logs = logs.collect::<Vec<_>>();
+    let max = logs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + logs.iter().map(|log| (log - max).exp()).sum::<f64>().ln()
+}
+
+// SplitMix64, small and good enough for test inputs
+struct Random {
+    state: u64
+}
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Scanner;
+    use crate::parser::{parse_with, Config};
+
+    use super::Examples;
+
+    fn examples(pattern: &str) -> Examples {
+        match parse_with(pattern, &Config::new().extended(true)) {
+            Ok(expr) => Examples::from_expr(&expr),
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    #[test]
+    fn shortest_first() {
+        let found = examples("[a-c]+x?").shortest_first().take(5).collect::<Vec<_>>();
+        assert_eq!(found, ["a", "aa", "ax", "aaa", "aax"]);
+        assert_eq!(examples("int|if|in").shortest_first().collect::<Vec<_>>(), ["if", "in", "int"]);
+        assert_eq!(examples("a*&b").shortest_first().next(), None);
+    }
+
+    #[test]
+    fn lexicographic() {
+        assert_eq!(examples("[a-z]+[0-9]?").lexicographic(3).collect::<Vec<_>>(), ["a", "a0", "aa", "aa0", "aaa"]);
+        assert_eq!(examples("b|ab|a").lexicographic(5).collect::<Vec<_>>(), ["a", "ab", "b"]);
+        assert_eq!(examples("(?:ab)*").lexicographic(5).collect::<Vec<_>>(), ["", "ab", "abab"]);
+        assert_eq!(examples("abc").lexicographic(2).next(), None);
+    }
+
+    #[test]
+    fn seeded_samples() {
+        let examples = examples("[a-z]{2,4}[0-9]");
+        let samples = examples.sample(7, 10).take(50).collect::<Vec<_>>();
+        for sample in &samples {
+            assert!(examples.dfa.is_match(sample), "{} is accepted", sample);
+        }
+        assert!(samples.iter().any(|sample| sample.len() == 3));
+        assert!(samples.iter().any(|sample| sample.len() == 5));
+        assert!(samples.iter().any(|sample| !sample.starts_with('a')));
+
+        assert_eq!(examples.sample(7, 10).take(50).collect::<Vec<_>>(), samples);
+        assert_ne!(examples.sample(8, 10).take(50).collect::<Vec<_>>(), samples);
+        assert_eq!(examples.sample(7, 2).next(), None);
+    }
+
+    #[test]
+    fn samples_of_huge_languages() {
+        // there are far more than f64::MAX strings, nearly all of them as long as allowed
+        let examples = examples("(?:a|bc|d)*e?");
+        for sample in examples.sample(7, 3000).take(10) {
+            assert!(examples.dfa.is_match(&sample), "{} is accepted", sample);
+            assert!(sample.len() > 2900, "{} is long", sample.len());
+        }
+    }
+
+    #[test]
+    fn near_misses() {
+        let numbers = examples("[0-9]+");
+        assert_eq!(numbers.near_misses(5), ["", "!", "!0", "0!", "!00"]);
+        assert!(examples("(?s).*").near_misses(5).is_empty());
+        for miss in examples("if|int").near_misses(20) {
+            assert!(!["if", "int"].contains(&miss.as_str()));
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    enum Kind {
+        Keyword,
+        Ident,
+        Int
+    }
+
+    #[test]
+    fn generates_lexer_inputs() {
+        let rules = [("class|int|if|while", Kind::Keyword), ("[a-zA-Z_][a-zA-Z0-9_]*", Kind::Ident), ("[0-9]+", Kind::Int)];
+        let Ok(scanner) = Scanner::new(&rules) else {
+            panic!("failed to compile rules")
+        };
+        // every identifier is scanned as one token, some of them are keywords
+        let identifiers = examples(rules[1].0);
+        for input in identifiers.sample(42, 8).take(100).chain(identifiers.lexicographic(2)) {
+            let kind = scanner.token_at(&input, 0).filter(|token| token.range() == (0..input.len())).map(|token| token.kind());
+            assert!(matches!(kind, Some(Kind::Ident | Kind::Keyword)), "{}", input);
+        }
+        // while names that are not keywords never are
+        for input in examples("[a-zA-Z_][a-zA-Z0-9_]*&~(?:class|int|if|while)").sample(1, 6).take(100) {
+            assert_eq!(scanner.token_at(&input, 0).map(|token| token.kind()), Some(Kind::Ident), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::ops::Range;
+
+use crate::ast::fold::{fold, Fold};
+use crate::ast::Expr;
+use crate::class::CharClass;
+use crate::thompson::{Symbol, NFA};
+
+pub type Position = usize;
+
+// Glushkov's position automaton. Every occurrence of a character or class in the
+// expression is a position, and the automaton has one state per position plus the
+// initial state. There are no epsilon edges: the initial state moves to the positions
+// in first, position p moves to the positions in follow(p), and the automaton accepts
+// in the positions of last, or in the initial state when the expression is nullable.
+//
+// "(a|b)*abb" has the positions a1 b2 a3 b4 b5, first is {1, 2, 3}, last is {5} and
+// follow(1) = follow(2) = {1, 2, 3}. Every edge into a position is on the class of
+// that position, so a set of positions together with the last character is all a
+// DFA state needs, which is the followpos construction of the Dragon Book.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Glushkov {
+    // class of every position
+    classes: Vec<CharClass>,
+    nullable: bool,
+    first: Vec<Position>,
+    last: Vec<Position>,
+    // sorted positions that can follow each position
+    follow: Vec<Vec<Position>>
+}
+
+impl Glushkov {
+    // None when expr has assertions, they do not occupy a position, or
+    // intersections and complements, which have no positions of their own
+    pub fn from_expr(expr: &Expr) -> Option<Glushkov> {
+        let mut positions = PositionFold {
+            automaton: Glushkov {
+                classes: Vec::new(),
+                nullable: true,
+                first: Vec::new(),
+                last: Vec::new(),
+                follow: Vec::new()
+            },
+            unsupported: false
+        };
+        let fragment = fold(expr, &mut positions);
+        if positions.unsupported {
+            return None;
+        }
+        let mut automaton = positions.automaton;
+        automaton.nullable = fragment.nullable;
+        automaton.first = fragment.first;
+        automaton.last = fragment.last;
+        for positions in [&mut automaton.first, &mut automaton.last].into_iter().chain(automaton.follow.iter_mut()) {
+            positions.sort_unstable();
+            positions.dedup();
+        }
+        Some(automaton)
+    }
+
+    // number of positions, they are numbered 0..positions()
+    pub fn positions(&self) -> usize {
+        self.classes.len()
+    }
+
+    // positions and the initial state
+    pub fn number_of_states(&self) -> usize {
+        self.positions() + 1
+    }
+
+    pub fn class(&self, position: Position) -> &CharClass {
+        &self.classes[position]
+    }
+
+    // true if the expression matches the empty string
+    pub fn nullable(&self) -> bool {
+        self.nullable
+    }
+
+    // positions that can match the first character
+    pub fn first(&self) -> &[Position] {
+        &self.first
+    }
+
+    // positions that can match the last character
+    pub fn last(&self) -> &[Position] {
+        &self.last
+    }
+
+    // positions that can match the character after the one position matched
+    pub fn follow(&self, position: Position) -> &[Position] {
+        &self.follow[position]
+    }
+
+    // true if the whole input is matched
+    pub fn is_match(&self, input: &str) -> bool {
+        // None is the initial state
+        let mut current: Option<Vec<Position>> = None;
+        for char in input.chars() {
+            let mut next = match &current {
+                None => self.first.clone(),
+                Some(positions) => positions
+                    .iter()
+                    .flat_map(|position| self.follow(*position))
+                    .copied()
+                    .collect()
+            };
+            next.retain(|position| self.classes[*position].contains(char));
+            next.sort_unstable();
+            next.dedup();
+            if next.is_empty() {
+                return false;
+            }
+            current = Some(next);
+        }
+        match current {
+            None => self.nullable,
+            Some(positions) => positions.iter().any(|position| self.last.contains(position))
+        }
+    }
+
+    // NFA with the same states, state 0 is the initial state and position p is
+    // state p + 1. It only adds an accept state that last and a nullable initial
+    // state reach by epsilon edges, as NFA has a single accept state.
+    pub fn to_nfa(&self) -> NFA {
+        let mut nfa = NFA::new();
+        for _ in 0..self.positions() {
+            nfa.add_state();
+        }
+        let accept = nfa.add_state();
+        for position in self.first.iter() {
+            nfa.add_transition(0, position + 1, Symbol::Class(self.classes[*position].clone()));
+        }
+        for (from, follow) in self.follow.iter().enumerate() {
+            for to in follow {
+                nfa.add_transition(from + 1, to + 1, Symbol::Class(self.classes[*to].clone()));
+            }
+        }
+        for position in self.last.iter() {
+            nfa.add_transition(position + 1, accept, Symbol::Epsilon);
+        }
+        if self.nullable {
+            nfa.add_transition(0, accept, Symbol::Epsilon);
+        }
+        nfa.set_fragment((0, accept));
+        nfa
+    }
+}
+
+// nullable, first and last of a subexpression, which owns the positions in range
+struct Fragment {
+    positions: Range<Position>,
+    nullable: bool,
+    first: Vec<Position>,
+    last: Vec<Position>
+}
+
+// Computes the sets bottom up, the fragment of every node out of the fragments
+// of its children. Follow sets only grow when fragments are combined.
+struct PositionFold {
+    automaton: Glushkov,
+    // expression has a node the construction does not handle
+    unsupported: bool
+}
+
+impl PositionFold {
+    fn empty(&self) -> Fragment {
+        let at = self.automaton.positions();
+        Fragment {
+            positions: at..at,
+            nullable: true,
+            first: Vec::new(),
+            last: Vec::new()
+        }
+    }
+
+    fn position(&mut self, class: CharClass) -> Fragment {
+        let position = self.automaton.positions();
+        self.automaton.classes.push(class);
+        self.automaton.follow.push(Vec::new());
+        Fragment {
+            positions: position..position + 1,
+            nullable: false,
+            first: vec![position],
+            last: vec![position]
+        }
+    }
+
+    // every position of last is followed by every position of first
+    fn link(&mut self, last: &[Position], first: &[Position]) {
+        for position in last {
+            self.automaton.follow[*position].extend_from_slice(first);
+        }
+    }
+
+    fn concatenation(&mut self, left: Fragment, right: Fragment) -> Fragment {
+        self.link(&left.last, &right.first);
+        let mut first = left.first;
+        if left.nullable {
+            first.extend(right.first);
+        }
+        let mut last = right.last;
+        if right.nullable {
+            last.extend(left.last);
+        }
+        Fragment {
+            positions: span(&left.positions, &right.positions),
+            nullable: left.nullable && right.nullable,
+            first,
+            last
+        }
+    }
+
+    fn alternation(&mut self, left: Fragment, right: Fragment) -> Fragment {
+        let mut first = left.first;
+        first.extend(right.first);
+        let mut last = left.last;
+        last.extend(right.last);
+        Fragment {
+            positions: span(&left.positions, &right.positions),
+            nullable: left.nullable || right.nullable,
+            first,
+            last
+        }
+    }
+
+    fn repetition(&mut self, fragment: Fragment, nullable: bool) -> Fragment {
+        self.link(&fragment.last, &fragment.first);
+        Fragment {
+            nullable: nullable || fragment.nullable,
+            ..fragment
+        }
+    }
+
+    fn optional(&mut self, fragment: Fragment) -> Fragment {
+        Fragment {
+            nullable: true,
+            ..fragment
+        }
+    }
+
+    // Fresh positions for another copy of fragment. Follow edges of its positions
+    // all stay inside of it, so they are copied along.
+    fn copy(&mut self, fragment: &Fragment) -> Fragment {
+        let offset = self.automaton.positions() - fragment.positions.start;
+        for position in fragment.positions.clone() {
+            let class = self.automaton.classes[position].clone();
+            let follow = self.automaton.follow[position]
+                .iter()
+                .map(|position| position + offset)
+                .collect();
+            self.automaton.classes.push(class);
+            self.automaton.follow.push(follow);
+        }
+        let shift = |positions: &[Position]| positions.iter().map(|position| position + offset).collect();
+        Fragment {
+            positions: fragment.positions.start + offset..fragment.positions.end + offset,
+            nullable: fragment.nullable,
+            first: shift(&fragment.first),
+            last: shift(&fragment.last)
+        }
+    }
+
+    // e{m,n} is m copies of e followed by n - m optional ones and e{m,} is m copies
+    // followed by e*, the first copy reuses the positions of e
+    fn bounded(&mut self, fragment: Fragment, min: u32, max: Option<u32>) -> Fragment {
+        let copies = max.unwrap_or(min + 1);
+        if copies == 0 {
+            // positions of e stay unreachable
+            return self.empty();
+        }
+        let mut copies = (1..copies).map(|_| self.copy(&fragment)).collect::<Vec<_>>();
+        copies.insert(0, fragment);
+
+        let mut result = self.empty();
+        for (index, copy) in copies.into_iter().enumerate() {
+            let copy = match max {
+                None if index as u32 >= min => self.repetition(copy, true),
+                Some(_) if index as u32 >= min => self.optional(copy),
+                _ => copy
+            };
+            result = self.concatenation(result, copy);
+        }
+        result
+    }
+}
+
+impl Fold for PositionFold {
+    type Output = Fragment;
+
+    fn fold(&mut self, expr: &Expr, children: Vec<Fragment>) -> Fragment {
+        let mut children = children.into_iter();
+        let empty = self.empty();
+        let child = children.next().unwrap_or(empty);
+        match expr {
+            Expr::Empty => child,
+            Expr::Literal(literal) => self.position(CharClass::single(literal.literal())),
+            Expr::Class(class) => self.position(class.as_ref().clone()),
+            Expr::Escape(escape) => self.position(escape.class().clone()),
+            Expr::MetaChar(meta) => self.position(meta.class().clone()),
+            Expr::Assertion(_) | Expr::Intersection(_) | Expr::Complement(_) => {
+                self.unsupported = true;
+                child
+            },
+            Expr::Repetition(_) => self.repetition(child, true),
+            Expr::ZeroOrOne(_) => self.optional(child),
+            Expr::OneOrMany(_) => self.repetition(child, false),
+            Expr::Bounded(rep) => self.bounded(child, rep.min(), rep.max()),
+            Expr::Group(_) => child,
+            Expr::Alternation(_) => children.fold(child, |left, right| self.alternation(left, right)),
+            Expr::Concatenation(_) => children.fold(child, |left, right| self.concatenation(left, right))
+        }
+    }
+}
+
+// positions of two fragments, the ones in between belong to one of them
+fn span(left: &Range<Position>, right: &Range<Position>) -> Range<Position> {
+    left.start.min(right.start)..left.end.max(right.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dfa::DFA;
+    use crate::parser::parse;
+    use crate::thompson::NFA;
+
+    use super::Glushkov;
+
+    fn glushkov(pattern: &str) -> Glushkov {
+        let Ok(expr) = parse(pattern) else {
+            panic!("failed to parse {}", pattern)
+        };
+        match Glushkov::from_expr(&expr) {
+            Some(glushkov) => glushkov,
+            None => panic!("{} has assertions", pattern)
+        }
+    }
+
+    #[test]
+    fn position_sets() {
+        let glushkov = glushkov("(a|b)*abb");
+        assert_eq!(glushkov.positions(), 5);
+        assert!(!glushkov.nullable());
+        assert_eq!(glushkov.first(), &[0, 1, 2]);
+        assert_eq!(glushkov.last(), &[4]);
+        assert_eq!(glushkov.follow(0), &[0, 1, 2]);
+        assert_eq!(glushkov.follow(1), &[0, 1, 2]);
+        assert_eq!(glushkov.follow(2), &[3]);
+        assert_eq!(glushkov.follow(3), &[4]);
+        assert!(glushkov.follow(4).is_empty());
+    }
+
+    #[test]
+    fn counted_repetitions_copy_positions() {
+        let glushkov = glushkov("(ab){2,3}");
+        assert_eq!(glushkov.positions(), 6);
+        assert_eq!(glushkov.last(), &[3, 5]);
+        assert!(glushkov.is_match("abab"));
+        assert!(glushkov.is_match("ababab"));
+        assert!(!glushkov.is_match("ab"));
+        assert!(!glushkov.is_match("abababab"));
+        assert!(self::glushkov("a{0}").is_match(""));
+        assert!(self::glushkov("a{2,}").is_match("aaaaa"));
+    }
+
+    #[test]
+    fn agrees_with_thompson() {
+        let patterns = ["(a|b)*abb", "a*(?:b|ab)+a?", "(?:ab|a)(?:bc|c)", "a{2,3}b*|[ab]{3}", "(a*)*b|\\w", "(?:)"];
+        let strings = ["", "a", "b", "ab", "abb", "aabb", "abc", "aab", "aaab", "abab", "_", "bab"];
+        for pattern in patterns {
+            let glushkov = glushkov(pattern);
+            let Ok(dfa) = DFA::from_pattern(pattern) else {
+                panic!("failed to compile {}", pattern)
+            };
+            let positions = DFA::from_nfa(&glushkov.to_nfa()).minimize();
+            for string in strings {
+                assert_eq!(glushkov.is_match(string), dfa.is_match(string), "{} on {}", pattern, string);
+                assert_eq!(positions.is_match(string), dfa.is_match(string), "{} on {}", pattern, string);
+            }
+            assert_eq!(positions.number_of_states(), dfa.number_of_states(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn keywords_need_fewer_states() {
+        let pattern = "break|const|continue|crate|else|enum|extern|false|fn|for";
+        let Ok(thompson) = NFA::from_pattern(pattern) else {
+            panic!("failed to compile")
+        };
+        let glushkov = glushkov(pattern);
+        assert_eq!(glushkov.number_of_states(), pattern.len() - 9 + 1);
+        assert!(glushkov.number_of_states() * 2 < thompson.len());
+    }
+
+    #[test]
+    fn assertions_are_not_supported() {
+        let Ok(expr) = parse("a$") else {
+            panic!("failed to parse")
+        };
+        assert_eq!(Glushkov::from_expr(&expr), None);
+    }
+}
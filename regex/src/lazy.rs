@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use crate::alphabet::{Alphabet, ClassId};
+use crate::look::Context;
+use crate::thompson::{StateId, NFA};
+use crate::utils::sparse_set::SparseSet;
+
+// number of DFA states that are cached before the cache is flushed
+const DEFAULT_CAPACITY: usize = 4096;
+
+// the search gives up once it flushed the cache this many times while
+// creating a new state for less than every MIN_CHARS_PER_STATE characters
+const MIN_FLUSHES: usize = 3;
+const MIN_CHARS_PER_STATE: usize = 10;
+
+// transition that was not computed yet
+const UNKNOWN: u32 = u32::MAX;
+
+type LazyStateId = usize;
+
+// Forward DFAs search for the end of the leftmost-first match, reverse ones run
+// over the reversed NFA backwards from that end and find where the match starts.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Direction {
+    Forward,
+    Reverse
+}
+
+// search flushed the cache too often, the NFA simulation has to be used instead
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct GaveUp;
+
+// DFA that is built while it is used. A state is computed by subset construction
+// the first time the search reaches it and then cached, so no time is spent on
+// states that input never reaches and the exponential blow up of full subset
+// construction only happens on input that really needs all of those states.
+//
+// Like the Pike VM, a state is an ordered list of NFA states, lower priority ones
+// are dropped once a higher one matches, which gives leftmost-first semantics.
+// Assertions can only be resolved once the next character is known, so a state
+// holds NFA states before their epsilon closure is taken, together with the
+// context of the previous character.
+//
+// The cache holds at most capacity states, once it is full it is flushed and
+// filled again. A search that keeps flushing gives up.
+pub(crate) struct LazyDFA {
+    nfa: NFA,
+    alphabet: Alphabet,
+    direction: Direction,
+    has_looks: bool,
+    capacity: usize,
+    cache: Mutex<Cache>
+}
+
+#[derive(Clone, Eq, PartialEq, Hash)]
+struct Key {
+    // NFA states in priority order, not epsilon closed
+    kernel: Vec<StateId>,
+    prev: Context,
+    // forward search still starts new threads, it stops once something matched
+    seeding: bool
+}
+
+struct Cache {
+    ids: HashMap<Key, LazyStateId>,
+    states: Vec<Key>,
+    // next state shifted left by one, lowest bit is set when the transition
+    // ends a match, i.e. the state matched right before the character
+    transitions: Vec<u32>,
+    // whether state matches before each context, computed on demand
+    accepts: Vec<[Option<bool>; 4]>,
+    flushes: usize,
+    // characters since the last flush
+    chars: usize,
+    closure: SparseSet,
+    moved: SparseSet,
+    stack: Vec<StateId>
+}
+
+impl Cache {
+    fn new(nfa: &NFA) -> Cache {
+        Cache {
+            ids: HashMap::new(),
+            states: Vec::new(),
+            transitions: Vec::new(),
+            accepts: Vec::new(),
+            flushes: 0,
+            chars: 0,
+            closure: SparseSet::new(nfa.len()),
+            moved: SparseSet::new(nfa.len()),
+            stack: Vec::new()
+        }
+    }
+
+    fn clear(&mut self) {
+        self.ids.clear();
+        self.states.clear();
+        self.transitions.clear();
+        self.accepts.clear();
+    }
+}
+
+impl LazyDFA {
+    pub(crate) fn new(nfa: NFA, direction: Direction) -> LazyDFA {
+        LazyDFA::with_capacity(nfa, direction, DEFAULT_CAPACITY)
+    }
+
+    pub(crate) fn with_capacity(nfa: NFA, direction: Direction, capacity: usize) -> LazyDFA {
+        let cache = Cache::new(&nfa);
+        LazyDFA {
+            alphabet: Alphabet::from_nfa(&nfa),
+            has_looks: nfa.has_looks(),
+            nfa,
+            direction,
+            // there always has to be room for the current state and the next one
+            capacity: capacity.max(2),
+            cache: Mutex::new(cache)
+        }
+    }
+
+    pub(crate) fn nfa(&self) -> &NFA {
+        &self.nfa
+    }
+
+    // end of the leftmost-first match that starts at or after start. With earliest
+    // set it returns as soon as it knows that there is a match.
+    pub(crate) fn find_end(&self, haystack: &str, start: usize, earliest: bool) -> Result<Option<usize>, GaveUp> {
+        debug_assert_eq!(self.direction, Direction::Forward);
+        let mut cache = self.lock();
+        let prev = self.context(haystack[..start].chars().next_back());
+        let mut state = self.add_state(&mut cache, Key { kernel: Vec::new(), prev, seeding: true });
+
+        let mut last = None;
+        for (offset, char) in haystack[start..].char_indices() {
+            let (next, matched) = self.next_state(&mut cache, state, char)?;
+            if matched {
+                last = Some(start + offset);
+                if earliest {
+                    return Ok(last);
+                }
+            }
+            if self.is_dead(&cache, next) {
+                return Ok(last);
+            }
+            state = next;
+        }
+
+        if self.accepts(&mut cache, state, Context::Edge) {
+            last = Some(haystack.len());
+        }
+        Ok(last)
+    }
+
+    // start of the longest match that ends at end and does not start before start
+    pub(crate) fn find_start(&self, haystack: &str, start: usize, end: usize) -> Result<Option<usize>, GaveUp> {
+        debug_assert_eq!(self.direction, Direction::Reverse);
+        let mut cache = self.lock();
+        let prev = self.context(haystack[end..].chars().next());
+        let kernel = vec![self.nfa.start()];
+        let mut state = self.add_state(&mut cache, Key { kernel, prev, seeding: false });
+
+        let mut last = None;
+        for (offset, char) in haystack[start..end].char_indices().rev() {
+            let (next, matched) = self.next_state(&mut cache, state, char)?;
+            if matched {
+                last = Some(start + offset + char.len_utf8());
+            }
+            if self.is_dead(&cache, next) {
+                return Ok(last);
+            }
+            state = next;
+        }
+
+        // characters in front of start are not part of the match, but assertions see them
+        let before = self.context(haystack[..start].chars().next_back());
+        if self.accepts(&mut cache, state, before) {
+            last = Some(start);
+        }
+        Ok(last)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Cache> {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        cache.flushes = 0;
+        cache.chars = 0;
+        cache
+    }
+
+    // without assertions context does not matter, all states use the same one
+    fn context(&self, char: Option<char>) -> Context {
+        if self.has_looks {
+            Context::of(char)
+        } else {
+            Context::Edge
+        }
+    }
+
+    // Every class of the alphabet has a column in the transition table, characters
+    // that no edge takes are in one of the extra columns for their context.
+    fn columns(&self) -> usize {
+        self.alphabet.len() + Context::ALL.len()
+    }
+
+    fn column(&self, char: char) -> (usize, Option<ClassId>) {
+        match self.alphabet.class_of(char) {
+            Some(class) => (class, Some(class)),
+            None => (self.alphabet.len() + self.context(Some(char)).index(), None)
+        }
+    }
+
+    fn is_dead(&self, cache: &Cache, state: LazyStateId) -> bool {
+        let key = &cache.states[state];
+        key.kernel.is_empty() && !key.seeding
+    }
+
+    fn next_state(&self, cache: &mut Cache, state: LazyStateId, char: char) -> Result<(LazyStateId, bool), GaveUp> {
+        cache.chars += 1;
+        let (column, class) = self.column(char);
+        let transition = cache.transitions[state * self.columns() + column];
+        if transition != UNKNOWN {
+            return Ok(((transition >> 1) as LazyStateId, transition & 1 == 1));
+        }
+
+        let key = cache.states[state].clone();
+        let (target, matched) = self.step(cache, &key, class, self.context(Some(char)));
+        let (state, next) = match cache.ids.get(&target) {
+            Some(next) => (state, *next),
+            None if cache.states.len() < self.capacity => (state, self.add_state(cache, target)),
+            None => {
+                self.flush(cache)?;
+                let state = self.add_state(cache, key);
+                (state, self.add_state(cache, target))
+            }
+        };
+        cache.transitions[state * self.columns() + column] = ((next as u32) << 1) | matched as u32;
+        Ok((next, matched))
+    }
+
+    fn flush(&self, cache: &mut Cache) -> Result<(), GaveUp> {
+        cache.flushes += 1;
+        if cache.flushes >= MIN_FLUSHES && cache.chars < MIN_CHARS_PER_STATE * self.capacity {
+            return Err(GaveUp);
+        }
+        cache.chars = 0;
+        cache.clear();
+        Ok(())
+    }
+
+    fn add_state(&self, cache: &mut Cache, key: Key) -> LazyStateId {
+        if let Some(state) = cache.ids.get(&key) {
+            return *state;
+        }
+        let state = cache.states.len();
+        cache.ids.insert(key.clone(), state);
+        cache.states.push(key);
+        cache.transitions.extend(std::iter::repeat_n(UNKNOWN, self.columns()));
+        cache.accepts.push([None; 4]);
+        state
+    }
+
+    // true if state matches right before a character with context next
+    fn accepts(&self, cache: &mut Cache, state: LazyStateId, next: Context) -> bool {
+        if let Some(accepts) = cache.accepts[state][next.index()] {
+            return accepts;
+        }
+        let key = cache.states[state].clone();
+        self.closure(cache, &key, next);
+        let accepts = cache.closure.contains(self.nfa.accept());
+        cache.accepts[state][next.index()] = Some(accepts);
+        accepts
+    }
+
+    // subset construction for a single transition, returns the next state and
+    // whether state matched right before the character
+    fn step(&self, cache: &mut Cache, key: &Key, class: Option<ClassId>, next: Context) -> (Key, bool) {
+        self.closure(cache, key, next);
+
+        let mut matched = false;
+        let mut kernel = Vec::new();
+        cache.moved.clear();
+        for state in cache.closure.iter() {
+            if *state == self.nfa.accept() {
+                matched = true;
+                match self.direction {
+                    // threads with lower priority can not win anymore
+                    Direction::Forward => break,
+                    Direction::Reverse => continue
+                }
+            }
+            let Some(class) = class else {
+                continue;
+            };
+            let char = self.alphabet.representative(class);
+            for transition in self.nfa.transitions(*state) {
+                if transition.symbol().matches(char) && cache.moved.insert(transition.next()) {
+                    kernel.push(transition.next());
+                }
+            }
+        }
+
+        let seeding = key.seeding && !matched;
+        (Key { kernel, prev: next, seeding }, matched)
+    }
+
+    // epsilon closure of the kernel in priority order, a new thread that starts at
+    // the current position comes last
+    fn closure(&self, cache: &mut Cache, key: &Key, next: Context) {
+        cache.closure.clear();
+        let seed = key.seeding.then_some(self.nfa.start());
+        for state in key.kernel.iter().copied().chain(seed) {
+            cache.stack.push(state);
+            while let Some(state) = cache.stack.pop() {
+                if !cache.closure.insert(state) {
+                    continue;
+                }
+                for transition in self.nfa.transitions(state).rev() {
+                    let symbol = transition.symbol();
+                    if symbol.is_epsilon() || symbol.look().is_some_and(|look| look.holds(key.prev, next)) {
+                        cache.stack.push(transition.next());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::thompson::NFA;
+
+    use super::{Direction, GaveUp, LazyDFA};
+
+    fn nfa(pattern: &str) -> NFA {
+        let Ok(nfa) = NFA::from_pattern(pattern) else {
+            panic!("failed to compile {}", pattern)
+        };
+        nfa
+    }
+
+    fn find(pattern: &str, haystack: &str) -> Option<(usize, usize)> {
+        let forward = LazyDFA::new(nfa(pattern), Direction::Forward);
+        let reverse = LazyDFA::new(nfa(pattern).reverse(), Direction::Reverse);
+        let end = forward.find_end(haystack, 0, false).unwrap()?;
+        let start = reverse.find_start(haystack, 0, end).unwrap()?;
+        Some((start, end))
+    }
+
+    #[test]
+    fn leftmost_first() {
+        assert_eq!(find("b+", "abbcbbb"), Some((1, 3)));
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("ab|a", "ab"), Some((0, 2)));
+        assert_eq!(find("a*", "bbb"), Some((0, 0)));
+        assert_eq!(find("x", "abc"), None);
+    }
+
+    #[test]
+    fn assertions() {
+        assert_eq!(find("\\bcat\\b", "concat cat"), Some((7, 10)));
+        assert_eq!(find("^a|b$", "aab"), Some((0, 1)));
+        assert_eq!(find("b$", "abb"), Some((2, 3)));
+        assert_eq!(find("\\Bb", "b ab"), Some((3, 4)));
+    }
+
+    #[test]
+    fn states_are_reused() {
+        let forward = LazyDFA::new(nfa("[a-z]+"), Direction::Forward);
+        assert_eq!(forward.find_end(&"x".repeat(1000), 0, false), Ok(Some(1000)));
+        let cached = forward.cache.lock().unwrap().states.len();
+        assert!(cached <= 3);
+    }
+
+    #[test]
+    fn flushes_and_gives_up() {
+        // the n-th character from the end is an "a", needs 2^n DFA states
+        let pattern = "(a|b)*a(a|b)(a|b)(a|b)(a|b)(a|b)";
+        let haystack = "ab".repeat(100) + "aababbbabaabbba";
+        let small = LazyDFA::with_capacity(nfa(pattern), Direction::Forward, 8);
+        assert_eq!(small.find_end(&haystack, 0, false), Err(GaveUp));
+
+        let large = LazyDFA::with_capacity(nfa(pattern), Direction::Forward, 16);
+        assert_eq!(large.find_end(&haystack, 0, false), Ok(Some(haystack.len())));
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use crate::alphabet::Alphabet;
+use crate::dfa::{epsilon_closure, look_closure};
+use crate::error::RegexError;
+use crate::look::Context;
+use crate::parser::{parse_with, Config};
+use crate::thompson::{self, NFA};
+
+mod codegen;
+
+pub use codegen::{Codegen, Mode};
+
+pub type StateId = usize;
+
+// index of a rule, rules with lower index win ties
+pub type RuleId = usize;
+
+// DFA state under construction, the epsilon closed NFA states and the context it was entered on
+type Subset = (Vec<thompson::StateId>, Context);
+
+// Scanner generated from an ordered list of (pattern, kind) rules. NFAs of all
+// rules are put side by side with a separate accept state per rule and the result
+// is determinized, so the whole input is scanned by a single DFA.
+//
+// Tokens are resolved by maximal munch, the scanner takes the longest prefix of
+// the remaining input that some rule matches. When several rules match that
+// prefix, the one that comes first in the list wins, which is how keywords are
+// kept apart from identifiers:
+//
+//     let scanner = Scanner::new(&[("if", If), ("[a-z]+", Ident)])?;
+//
+// scans "if" as If but "iffy" as a single Ident. Rules never match the empty string.
+#[derive(Debug, Clone)]
+pub struct Scanner<K> {
+    kinds: Vec<K>,
+    alphabet: Alphabet,
+    // transitions of state s start at s * alphabet.len(), None means that no rule can match anymore
+    transitions: Vec<Option<StateId>>,
+    // rule accepted by each state before each context of the next character
+    accepts: Vec<[Option<RuleId>; 4]>,
+    // start state after each context, they are all the same unless some rule has assertions
+    starts: [StateId; 4]
+}
+
+// single token, start and end are byte offsets into the input
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Token<'h, K> {
+    kind: K,
+    input: &'h str,
+    start: usize,
+    end: usize
+}
+
+// no rule matches the input at offset
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ScanError {
+    offset: usize
+}
+
+impl<K: Copy> Scanner<K> {
+    pub fn new(rules: &[(&str, K)]) -> Result<Scanner<K>, RegexError> {
+        Scanner::with_config(rules, &Config::default())
+    }
+
+    // every rule is parsed with config, e.g. to write rules in the extended syntax
+    pub fn with_config(rules: &[(&str, K)], config: &Config) -> Result<Scanner<K>, RegexError> {
+        let nfas = rules
+            .iter()
+            .map(|(pattern, _)| parse_with(pattern, config).map(|expr| NFA::from_expr(&expr)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let kinds = rules.iter().map(|(_, kind)| *kind).collect();
+        Ok(Scanner::from_nfas(&nfas, kinds).minimize())
+    }
+
+    // Subset construction like DFA::from_nfa, but a state remembers which rule
+    // it accepts instead of just whether it accepts.
+    fn from_nfas(nfas: &[NFA], kinds: Vec<K>) -> Scanner<K> {
+        let (nfa, rule_accepts) = NFA::tagged_union(nfas);
+        let has_looks = nfa.has_looks();
+        let context = |context| if has_looks { context } else { Context::Edge };
+
+        let mut scanner = Scanner {
+            kinds,
+            alphabet: Alphabet::from_nfa(&nfa),
+            transitions: Vec::new(),
+            accepts: Vec::new(),
+            starts: [0; 4]
+        };
+        let mut ids: HashMap<Subset, StateId> = HashMap::new();
+        let mut unmarked = VecDeque::new();
+        for prev in Context::ALL {
+            let start = (epsilon_closure(&nfa, vec![nfa.start()]), context(prev));
+            let start = scanner.add_state(&nfa, &rule_accepts, &mut ids, &mut unmarked, start);
+            scanner.starts[prev.index()] = start;
+        }
+
+        while let Some((set, prev)) = unmarked.pop_front() {
+            let from = ids[&(set.clone(), prev)];
+            for class in 0..scanner.alphabet.len() {
+                let char = scanner.alphabet.representative(class);
+                let next = Context::of(Some(char));
+                let moved = look_closure(&nfa, set.clone(), prev, next)
+                    .iter()
+                    .flat_map(|state| nfa.transitions(*state))
+                    .filter(|transition| transition.symbol().matches(char))
+                    .map(|transition| transition.next())
+                    .collect();
+                let target = epsilon_closure(&nfa, moved);
+                if target.is_empty() {
+                    continue;
+                }
+                let target = (target, context(next));
+                let to = scanner.add_state(&nfa, &rule_accepts, &mut ids, &mut unmarked, target);
+                scanner.transitions[from * scanner.alphabet.len() + class] = Some(to);
+            }
+        }
+        scanner
+    }
+
+    fn add_state(
+        &mut self,
+        nfa: &NFA,
+        rule_accepts: &[thompson::StateId],
+        ids: &mut HashMap<Subset, StateId>,
+        unmarked: &mut VecDeque<Subset>,
+        subset: Subset
+    ) -> StateId {
+        if let Some(state) = ids.get(&subset) {
+            return *state;
+        }
+        // the first rule whose accept state is reached wins
+        let (set, prev) = &subset;
+        let accepts = Context::ALL.map(|next| {
+            let closure = look_closure(nfa, set.clone(), *prev, next);
+            rule_accepts.iter().position(|accept| closure.binary_search(accept).is_ok())
+        });
+
+        let state = self.accepts.len();
+        self.accepts.push(accepts);
+        self.transitions.extend(std::iter::repeat_n(None, self.alphabet.len()));
+        ids.insert(subset.clone(), state);
+        unmarked.push_back(subset);
+        state
+    }
+
+    // Moore's partition refinement. States start split into blocks by the rules they
+    // accept, blocks are split until all states of a block move into the same blocks.
+    // Unlike DFA::minimize, two accepting states are only equivalent when they accept
+    // the same rule, so the tags survive minimization.
+    fn minimize(&self) -> Scanner<K> {
+        let classes = self.alphabet.len();
+        // transitions are partial, missing ones go to an implicit dead state
+        let dead = self.number_of_states();
+        let target = |state: StateId, class: usize| -> StateId {
+            match self.transitions.get(state * classes + class) {
+                Some(Some(next)) => *next,
+                _ => dead
+            }
+        };
+        let accepts = |state: StateId| self.accepts.get(state).copied().unwrap_or([None; 4]);
+
+        let mut block_of = renumber((0..=dead).map(accepts));
+        let mut blocks = block_of.iter().max().map_or(0, |max| max + 1);
+        loop {
+            let refined = renumber((0..=dead).map(|state| {
+                let targets = (0..classes)
+                    .map(|class| block_of[target(state, class)])
+                    .collect::<Vec<_>>();
+                (block_of[state], targets)
+            }));
+            let refined_blocks = refined.iter().max().map_or(0, |max| max + 1);
+            block_of = refined;
+            if refined_blocks == blocks {
+                break;
+            }
+            blocks = refined_blocks;
+        }
+
+        // states equivalent to the dead one can never accept, they are dropped.
+        // The rest is renumbered in breadth first order from the start states.
+        let mut ids: HashMap<usize, StateId> = HashMap::new();
+        let mut queue = VecDeque::new();
+        let mut minimized = Scanner {
+            kinds: self.kinds.clone(),
+            alphabet: self.alphabet.clone(),
+            transitions: Vec::new(),
+            accepts: Vec::new(),
+            starts: [0; 4]
+        };
+        let mut add_block = |minimized: &mut Scanner<K>, queue: &mut VecDeque<StateId>, state: StateId| {
+            *ids.entry(block_of[state]).or_insert_with(|| {
+                queue.push_back(state);
+                minimized.accepts.push(accepts(state));
+                minimized.transitions.extend(std::iter::repeat_n(None, classes));
+                minimized.accepts.len() - 1
+            })
+        };
+        for (index, start) in self.starts.iter().enumerate() {
+            minimized.starts[index] = add_block(&mut minimized, &mut queue, *start);
+        }
+        while let Some(representative) = queue.pop_front() {
+            let from = add_block(&mut minimized, &mut queue, representative);
+            for class in 0..classes {
+                let to = target(representative, class);
+                if block_of[to] == block_of[dead] {
+                    continue;
+                }
+                let to = add_block(&mut minimized, &mut queue, to);
+                minimized.transitions[from * classes + class] = Some(to);
+            }
+        }
+        minimized
+    }
+
+    pub fn number_of_states(&self) -> usize {
+        self.accepts.len()
+    }
+
+    pub fn number_of_rules(&self) -> usize {
+        self.kinds.len()
+    }
+
+    // longest token at offset at of input, None when no rule matches there
+    pub fn token_at<'h>(&self, input: &'h str, at: usize) -> Option<Token<'h, K>> {
+        let prev = Context::of(input[..at].chars().next_back());
+        let mut state = self.starts[prev.index()];
+        let mut longest = None;
+        let chars = input[at..]
+            .char_indices()
+            .map(|(offset, char)| (at + offset, Some(char)))
+            .chain(std::iter::once((input.len(), None)));
+        for (offset, char) in chars {
+            if offset > at {
+                if let Some(rule) = self.accepts[state][Context::of(char).index()] {
+                    longest = Some((rule, offset));
+                }
+            }
+            let Some(next) = char.and_then(|char| self.next_state(state, char)) else {
+                break;
+            };
+            state = next;
+        }
+        longest.map(|(rule, end)| Token {
+            kind: self.kinds[rule],
+            input,
+            start: at,
+            end
+        })
+    }
+
+    // Tokens of the whole input one after another. When no rule matches, the
+    // iterator reports an error and carries on after the offending character.
+    pub fn tokens<'s, 'h>(&'s self, input: &'h str) -> Tokens<'s, 'h, K> {
+        Tokens {
+            scanner: self,
+            input,
+            at: 0
+        }
+    }
+
+    fn next_state(&self, state: StateId, char: char) -> Option<StateId> {
+        let class = self.alphabet.class_of(char)?;
+        self.transitions[state * self.alphabet.len() + class]
+    }
+}
+
+impl<'h, K: Copy> Token<'h, K> {
+    pub fn kind(&self) -> K {
+        self.kind
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.input[self.range()]
+    }
+}
+
+impl ScanError {
+    // byte offset into the input
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no rule matches input at offset {}", self.offset)
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+// iterator returned by Scanner::tokens
+pub struct Tokens<'s, 'h, K> {
+    scanner: &'s Scanner<K>,
+    input: &'h str,
+    at: usize
+}
+
+impl<'s, 'h, K: Copy> Iterator for Tokens<'s, 'h, K> {
+    type Item = Result<Token<'h, K>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let char = self.input[self.at..].chars().next()?;
+        match self.scanner.token_at(self.input, self.at) {
+            Some(token) => {
+                self.at = token.end;
+                Some(Ok(token))
+            }
+            None => {
+                let offset = self.at;
+                self.at += char.len_utf8();
+                Some(Err(ScanError { offset }))
+            }
+        }
+    }
+}
+
+// numbers distinct keys in the order they first appear
+fn renumber<T: Eq + std::hash::Hash>(keys: impl Iterator<Item = T>) -> Vec<usize> {
+    let mut numbers = HashMap::new();
+    keys.map(|key| {
+        let next = numbers.len();
+        *numbers.entry(key).or_insert(next)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Config;
+
+    use super::{ScanError, Scanner};
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    enum Kind {
+        If,
+        Ident,
+        Number,
+        Assign,
+        Equal,
+        Space
+    }
+
+    fn scanner(rules: &[(&str, Kind)]) -> Scanner<Kind> {
+        match Scanner::new(rules) {
+            Ok(scanner) => scanner,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    fn tokens(scanner: &Scanner<Kind>, input: &str) -> Vec<(Kind, String)> {
+        scanner
+            .tokens(input)
+            .map(|token| token.map(|token| (token.kind(), token.as_str().to_string())))
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn language() -> Scanner<Kind> {
+        scanner(&[
+            ("if", Kind::If),
+            ("[a-z][a-z0-9]*", Kind::Ident),
+            ("[0-9]+", Kind::Number),
+            ("==", Kind::Equal),
+            ("=", Kind::Assign),
+            ("\\s+", Kind::Space)
+        ])
+    }
+
+    #[test]
+    fn longest_match_wins() {
+        let scanner = language();
+        assert_eq!(tokens(&scanner, "iffy==x1"), vec![
+            (Kind::Ident, "iffy".to_string()),
+            (Kind::Equal, "==".to_string()),
+            (Kind::Ident, "x1".to_string())
+        ]);
+        assert_eq!(tokens(&scanner, "x=12"), vec![
+            (Kind::Ident, "x".to_string()),
+            (Kind::Assign, "=".to_string()),
+            (Kind::Number, "12".to_string())
+        ]);
+    }
+
+    #[test]
+    fn earlier_rule_wins_ties() {
+        assert_eq!(tokens(&language(), "if x"), vec![
+            (Kind::If, "if".to_string()),
+            (Kind::Space, " ".to_string()),
+            (Kind::Ident, "x".to_string())
+        ]);
+
+        let reordered = scanner(&[("[a-z]+", Kind::Ident), ("if", Kind::If)]);
+        assert_eq!(tokens(&reordered, "if"), vec![(Kind::Ident, "if".to_string())]);
+    }
+
+    #[test]
+    fn errors_are_reported_and_skipped() {
+        let scanner = language();
+        let results = scanner
+            .tokens("a?b")
+            .map(|token| token.map(|token| token.range()))
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![Ok(0..1), Err(ScanError { offset: 1 }), Ok(2..3)]);
+    }
+
+    #[test]
+    fn empty_matches_are_not_tokens() {
+        let scanner = scanner(&[("a*", Kind::Ident), ("b", Kind::Number)]);
+        assert_eq!(scanner.token_at("b", 0).map(|token| token.kind()), Some(Kind::Number));
+        assert_eq!(scanner.token_at("c", 0), None);
+    }
+
+    #[test]
+    fn assertions_see_surrounding_input() {
+        let scanner = scanner(&[("if\\b", Kind::If), ("[a-z]+", Kind::Ident), (" ", Kind::Space)]);
+        assert_eq!(tokens(&scanner, "if iffy"), vec![
+            (Kind::If, "if".to_string()),
+            (Kind::Space, " ".to_string()),
+            (Kind::Ident, "iffy".to_string())
+        ]);
+        let Some(token) = scanner.token_at("xif", 1) else {
+            panic!("if matches at 1")
+        };
+        assert_eq!(token.kind(), Kind::If);
+    }
+
+    #[test]
+    fn minimized_states_keep_rules_apart() {
+        // "ab" and "cb" end in the same state, but x and y must not
+        let scanner = scanner(&[("ab|cb", Kind::Ident), ("x", Kind::Number), ("y", Kind::Space)]);
+        assert_eq!(scanner.number_of_states(), 5);
+        assert_eq!(scanner.token_at("y", 0).map(|token| token.kind()), Some(Kind::Space));
+        assert_eq!(scanner.token_at("cb", 0).map(|token| token.kind()), Some(Kind::Ident));
+    }
+
+    #[test]
+    fn invalid_rule() {
+        assert!(Scanner::new(&[("a", 0), ("(b", 1)]).is_err());
+    }
+
+    #[test]
+    fn rules_in_extended_syntax() {
+        let config = Config::new().extended(true).dot_matches_new_line(true);
+        let rules = [("/\\*~(.*\\*/.*)\\*/", "comment"), ("[a-z]+&~(?:if|else)", "name"), ("[a-z]+", "keyword"), (" +", "space")];
+        let scanner = match Scanner::with_config(&rules, &config) {
+            Ok(scanner) => scanner,
+            Err(error) => panic!("{}", error)
+        };
+        let kinds = scanner
+            .tokens("if /* x\n*/ iffy */")
+            .map(|token| token.map(|token| (token.kind(), token.as_str())))
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            Ok(("keyword", "if")),
+            Ok(("space", " ")),
+            Ok(("comment", "/* x\n*/")),
+            Ok(("space", " ")),
+            Ok(("name", "iffy")),
+            Ok(("space", " ")),
+            Err(ScanError { offset: 16 }),
+            Err(ScanError { offset: 17 })
+        ]);
+    }
+}
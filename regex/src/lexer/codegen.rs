@@ -0,0 +1,354 @@
+use std::fmt::{Display, Write};
+use std::io;
+use std::path::Path;
+
+use crate::class::{CharClass, ClassRange};
+use crate::look::Context;
+
+use super::{RuleId, Scanner, StateId};
+
+// Shape of the generated scanner.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
+    // transitions are looked up in a perfect hash map from (state, class) to the next
+    // state, so the table only holds transitions that exist. Needs phf at runtime.
+    Table,
+    // every state is a match arm that tests the next character directly,
+    // bigger but free of lookups and dependencies
+    Direct
+}
+
+// element type of the generated rule table and its maximum, which stands for no rule,
+// so it has to be above every rule
+fn rule_type(rules: usize) -> (&'static str, u64) {
+    match u16::try_from(rules) {
+        Ok(_) => ("u16", u16::MAX.into()),
+        Err(_) => ("u32", u32::MAX.into())
+    }
+}
+
+// Turns a Scanner into Rust source, so that a build script can generate the scanner
+// once instead of building automata at every process start:
+//
+//     let scanner = Scanner::new(&[("if", "Token::If"), ("[a-z]+", "Token::Ident")])?;
+//     Codegen::new("Token").write_to(&scanner, out_dir.join("scanner.rs"))?;
+//
+// and then include!(concat!(env!("OUT_DIR"), "/scanner.rs")) next to the Token type.
+// Kinds of the rules are written as they are displayed, so they have to be Rust
+// expressions of the kind type. The generated code is a module with a single function
+//
+//     pub fn scan(input: &str, at: usize) -> Option<(Token, usize)>
+//
+// returning the kind and end of the longest token at byte offset at, it does not
+// depend on this crate.
+#[derive(Debug, Clone)]
+pub struct Codegen {
+    kind_type: String,
+    module: String,
+    mode: Mode
+}
+
+impl Codegen {
+    pub fn new(kind_type: &str) -> Codegen {
+        Codegen {
+            kind_type: kind_type.to_string(),
+            module: "scanner".to_string(),
+            mode: Mode::Table
+        }
+    }
+
+    // name of the generated module, "scanner" by default
+    pub fn module(mut self, module: &str) -> Codegen {
+        self.module = module.to_string();
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Codegen {
+        self.mode = mode;
+        self
+    }
+
+    pub fn generate<K: Copy + Display>(&self, scanner: &Scanner<K>) -> String {
+        let mut source = String::new();
+        // writing into a String never fails
+        self.write_module(&mut source, scanner).unwrap();
+        source
+    }
+
+    pub fn write_to<K: Copy + Display>(&self, scanner: &Scanner<K>, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.generate(scanner))
+    }
+
+    fn write_module<K: Copy + Display>(&self, out: &mut String, scanner: &Scanner<K>) -> std::fmt::Result {
+        writeln!(out, "// generated by regex::lexer::Codegen, do not edit")?;
+        writeln!(out, "#[allow(dead_code, clippy::all)]")?;
+        writeln!(out, "pub mod {} {{", self.module)?;
+        writeln!(out, "    use super::*;")?;
+        writeln!(out)?;
+        writeln!(out, "    const START: [usize; 4] = {:?};", scanner.starts)?;
+        writeln!(out)?;
+        writeln!(out, "    pub fn scan(input: &str, at: usize) -> Option<({}, usize)> {{", self.kind_type)?;
+        writeln!(out, "        let mut state = START[context(input[..at].chars().next_back())];")?;
+        writeln!(out, "        let mut longest = None;")?;
+        writeln!(out, "        let mut end = at;")?;
+        writeln!(out, "        let mut chars = input[at..].chars();")?;
+        writeln!(out, "        loop {{")?;
+        writeln!(out, "            let char = chars.next();")?;
+        writeln!(out, "            if end > at {{")?;
+        writeln!(out, "                if let Some(kind) = accept(state, context(char)) {{")?;
+        writeln!(out, "                    longest = Some((kind, end));")?;
+        writeln!(out, "                }}")?;
+        writeln!(out, "            }}")?;
+        writeln!(out, "            let Some(char) = char else {{")?;
+        writeln!(out, "                return longest;")?;
+        writeln!(out, "            }};")?;
+        writeln!(out, "            state = match next(state, char) {{")?;
+        writeln!(out, "                Some(next) => next,")?;
+        writeln!(out, "                None => return longest")?;
+        writeln!(out, "            }};")?;
+        writeln!(out, "            end += char.len_utf8();")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out)?;
+        write_context(out)?;
+        writeln!(out)?;
+        match self.mode {
+            Mode::Table => self.write_tables(out, scanner)?,
+            Mode::Direct => self.write_matches(out, scanner)?
+        }
+        writeln!(out, "}}")
+    }
+
+    fn write_tables<K: Copy + Display>(&self, out: &mut String, scanner: &Scanner<K>) -> std::fmt::Result {
+        let classes = scanner.alphabet.len();
+        let intervals = scanner.alphabet.intervals().collect::<Vec<_>>();
+        writeln!(out, "    const CLASSES: usize = {};", classes)?;
+        writeln!(out)?;
+        writeln!(out, "    // sorted intervals of characters and their class")?;
+        writeln!(out, "    static INTERVALS: [(char, char, usize); {}] = [", intervals.len())?;
+        for (interval, class) in intervals {
+            writeln!(out, "        ({:?}, {:?}, {}),", interval.start(), interval.end(), class)?;
+        }
+        writeln!(out, "    ];")?;
+        writeln!(out)?;
+        writeln!(out, "    // next state by state * CLASSES + class")?;
+        writeln!(out, "    static TRANSITIONS: phf::Map<u32, u32> = phf::phf_map! {{")?;
+        for (index, next) in scanner.transitions.iter().enumerate() {
+            if let Some(next) = next {
+                writeln!(out, "        {}u32 => {},", index, next)?;
+            }
+        }
+        writeln!(out, "    }};")?;
+        writeln!(out)?;
+        let (rule_type, no_rule) = rule_type(scanner.number_of_rules());
+        writeln!(out, "    // rule accepted by each state before each context, {} if none", no_rule)?;
+        writeln!(out, "    static ACCEPTS: [[{}; 4]; {}] = [", rule_type, scanner.number_of_states())?;
+        for accepts in scanner.accepts.iter() {
+            let rules = accepts.map(|rule| rule.map_or(no_rule, |rule| rule as u64));
+            writeln!(out, "        {:?},", rules)?;
+        }
+        writeln!(out, "    ];")?;
+        writeln!(out)?;
+        writeln!(out, "    fn next(state: usize, char: char) -> Option<usize> {{")?;
+        writeln!(out, "        let index = INTERVALS.binary_search_by(|(start, end, _)| {{")?;
+        writeln!(out, "            if *end < char {{")?;
+        writeln!(out, "                std::cmp::Ordering::Less")?;
+        writeln!(out, "            }} else if *start > char {{")?;
+        writeln!(out, "                std::cmp::Ordering::Greater")?;
+        writeln!(out, "            }} else {{")?;
+        writeln!(out, "                std::cmp::Ordering::Equal")?;
+        writeln!(out, "            }}")?;
+        writeln!(out, "        }}).ok()?;")?;
+        writeln!(out, "        let key = (state * CLASSES + INTERVALS[index].2) as u32;")?;
+        writeln!(out, "        TRANSITIONS.get(&key).map(|next| *next as usize)")?;
+        writeln!(out, "    }}")?;
+        writeln!(out)?;
+        writeln!(out, "    fn accept(state: usize, context: usize) -> Option<{}> {{", self.kind_type)?;
+        writeln!(out, "        match ACCEPTS[state][context] {{")?;
+        for (rule, kind) in scanner.kinds.iter().enumerate() {
+            writeln!(out, "            {} => Some({}),", rule, kind)?;
+        }
+        writeln!(out, "            _ => None")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")
+    }
+
+    fn write_matches<K: Copy + Display>(&self, out: &mut String, scanner: &Scanner<K>) -> std::fmt::Result {
+        let classes = scanner.alphabet.len();
+        writeln!(out, "    fn next(state: usize, char: char) -> Option<usize> {{")?;
+        writeln!(out, "        match state {{")?;
+        for state in 0..scanner.number_of_states() {
+            // all classes leading to the same state become a single arm
+            let mut targets: Vec<(StateId, CharClass)> = Vec::new();
+            for class in 0..classes {
+                let Some(to) = scanner.transitions[state * classes + class] else {
+                    continue;
+                };
+                let class = scanner.alphabet.class(class);
+                match targets.iter_mut().find(|(target, _)| *target == to) {
+                    Some((_, chars)) => chars.union(class),
+                    None => targets.push((to, class.clone()))
+                }
+            }
+            if targets.is_empty() {
+                continue;
+            }
+            writeln!(out, "            {} => match char {{", state)?;
+            for (to, chars) in targets {
+                writeln!(out, "                {} => Some({}),", pattern(chars.ranges()), to)?;
+            }
+            writeln!(out, "                _ => None")?;
+            writeln!(out, "            }},")?;
+        }
+        writeln!(out, "            _ => None")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")?;
+        writeln!(out)?;
+        writeln!(out, "    fn accept(state: usize, context: usize) -> Option<{}> {{", self.kind_type)?;
+        writeln!(out, "        match (state, context) {{")?;
+        for (state, accepts) in scanner.accepts.iter().enumerate() {
+            for (rule, contexts) in group_contexts(accepts) {
+                let contexts = match contexts.len() {
+                    4 => "_".to_string(),
+                    _ => contexts.iter().map(|context| context.index().to_string()).collect::<Vec<_>>().join(" | ")
+                };
+                writeln!(out, "            ({}, {}) => Some({}),", state, contexts, scanner.kinds[rule])?;
+            }
+        }
+        writeln!(out, "            _ => None")?;
+        writeln!(out, "        }}")?;
+        writeln!(out, "    }}")
+    }
+}
+
+// same categories as look::Context, by their index
+fn write_context(out: &mut String) -> std::fmt::Result {
+    writeln!(out, "    fn context(char: Option<char>) -> usize {{")?;
+    writeln!(out, "        match char {{")?;
+    writeln!(out, "            None => {},", Context::Edge.index())?;
+    writeln!(out, "            Some('\\n') => {},", Context::LineFeed.index())?;
+    writeln!(out, "            Some(char) if char.is_ascii_alphanumeric() || char == '_' => {},", Context::Word.index())?;
+    writeln!(out, "            Some(_) => {}", Context::Other.index())?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")
+}
+
+// match pattern of the characters of ranges, e.g. 'a'..='z' | '_'
+fn pattern(ranges: &[ClassRange]) -> String {
+    ranges
+        .iter()
+        .map(|range| match range.start() == range.end() {
+            true => format!("{:?}", range.start()),
+            false => format!("{:?}..={:?}", range.start(), range.end())
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+// contexts of a state grouped by the rule accepted before them
+fn group_contexts(accepts: &[Option<RuleId>; 4]) -> Vec<(RuleId, Vec<Context>)> {
+    let mut groups: Vec<(RuleId, Vec<Context>)> = Vec::new();
+    for context in Context::ALL {
+        let Some(rule) = accepts[context.index()] else {
+            continue;
+        };
+        match groups.iter_mut().find(|(accepted, _)| *accepted == rule) {
+            Some((_, contexts)) => contexts.push(context),
+            None => groups.push((rule, vec![context]))
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rule_type, Codegen, Mode};
+    use crate::lexer::Scanner;
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    enum Token {
+        If,
+        Ident,
+        Number,
+        Space
+    }
+
+    mod table {
+        use super::Token;
+        include!("fixtures/table.rs");
+    }
+
+    mod direct {
+        use super::Token;
+        include!("fixtures/direct.rs");
+    }
+
+    fn rules() -> Scanner<&'static str> {
+        let rules = [
+            ("if\\b", "Token::If"),
+            ("[a-zA-Z_][a-zA-Z0-9_]*", "Token::Ident"),
+            ("[0-9]+", "Token::Number"),
+            ("[ \\t\\n]+", "Token::Space")
+        ];
+        match Scanner::new(&rules) {
+            Ok(scanner) => scanner,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    // Generated fixtures are compiled into this module, run REGENERATE=1 cargo test
+    // to update them after the generator changed.
+    fn check_fixture(mode: Mode, file: &str) {
+        let source = Codegen::new("Token").mode(mode).generate(&rules());
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lexer/fixtures").join(file);
+        if std::env::var_os("REGENERATE").is_some() {
+            std::fs::write(&path, &source).unwrap();
+        }
+        let fixture = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(source, fixture, "{} is out of date", file);
+    }
+
+    #[test]
+    fn fixtures_are_up_to_date() {
+        check_fixture(Mode::Table, "table.rs");
+        check_fixture(Mode::Direct, "direct.rs");
+    }
+
+    #[test]
+    fn generated_scanners_agree_with_scanner() {
+        let scanner = rules();
+        let input = "if iffy\tx_1 42if\nif";
+        for at in 0..=input.len() {
+            let expected = scanner
+                .token_at(input, at)
+                .map(|token| (token.kind(), token.end()));
+            let kind = |token: Token| match token {
+                Token::If => "Token::If",
+                Token::Ident => "Token::Ident",
+                Token::Number => "Token::Number",
+                Token::Space => "Token::Space"
+            };
+            let table = table::scanner::scan(input, at).map(|(token, end)| (kind(token), end));
+            let direct = direct::scanner::scan(input, at).map(|(token, end)| (kind(token), end));
+            assert_eq!(table, expected, "table at {}", at);
+            assert_eq!(direct, expected, "direct at {}", at);
+        }
+    }
+
+    #[test]
+    fn non_ascii_characters_are_escaped() {
+        let Ok(scanner) = Scanner::new(&[("'|\\u{301}", "0")]) else {
+            panic!("failed to compile scanner")
+        };
+        let source = Codegen::new("u8").mode(Mode::Direct).generate(&scanner);
+        assert!(source.contains("'\\'' | '\\u{301}' => Some(1)"));
+    }
+    #[test]
+    fn rule_table_fits_every_rule() {
+        assert_eq!(rule_type(4), ("u16", 65_535));
+        assert_eq!(rule_type(65_535), ("u16", 65_535));
+        // rule 65,535 would be taken for no rule
+        assert_eq!(rule_type(65_536), ("u32", 4_294_967_295));
+        assert_eq!(rule_type(100_000), ("u32", 4_294_967_295));
+    }
+}
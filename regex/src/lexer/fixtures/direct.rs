@@ -0,0 +1,85 @@
+// generated by regex::lexer::Codegen, do not edit
+#[allow(dead_code, clippy::all)]
+pub mod scanner {
+    use super::*;
+
+    const START: [usize; 4] = [0, 0, 0, 0];
+
+    pub fn scan(input: &str, at: usize) -> Option<(Token, usize)> {
+        let mut state = START[context(input[..at].chars().next_back())];
+        let mut longest = None;
+        let mut end = at;
+        let mut chars = input[at..].chars();
+        loop {
+            let char = chars.next();
+            if end > at {
+                if let Some(kind) = accept(state, context(char)) {
+                    longest = Some((kind, end));
+                }
+            }
+            let Some(char) = char else {
+                return longest;
+            };
+            state = match next(state, char) {
+                Some(next) => next,
+                None => return longest
+            };
+            end += char.len_utf8();
+        }
+    }
+
+    fn context(char: Option<char>) -> usize {
+        match char {
+            None => 0,
+            Some('\n') => 1,
+            Some(char) if char.is_ascii_alphanumeric() || char == '_' => 2,
+            Some(_) => 3
+        }
+    }
+
+    fn next(state: usize, char: char) -> Option<usize> {
+        match state {
+            0 => match char {
+                '\t'..='\n' | ' ' => Some(1),
+                '0'..='9' => Some(2),
+                'A'..='Z' | '_' | 'a'..='h' | 'j'..='z' => Some(3),
+                'i' => Some(4),
+                _ => None
+            },
+            1 => match char {
+                '\t'..='\n' | ' ' => Some(1),
+                _ => None
+            },
+            2 => match char {
+                '0'..='9' => Some(2),
+                _ => None
+            },
+            3 => match char {
+                '0'..='9' | 'A'..='Z' | '_' | 'a'..='z' => Some(3),
+                _ => None
+            },
+            4 => match char {
+                '0'..='9' | 'A'..='Z' | '_' | 'a'..='e' | 'g'..='z' => Some(3),
+                'f' => Some(5),
+                _ => None
+            },
+            5 => match char {
+                '0'..='9' | 'A'..='Z' | '_' | 'a'..='z' => Some(3),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    fn accept(state: usize, context: usize) -> Option<Token> {
+        match (state, context) {
+            (1, _) => Some(Token::Space),
+            (2, _) => Some(Token::Number),
+            (3, _) => Some(Token::Ident),
+            (4, _) => Some(Token::Ident),
+            (5, 0 | 1 | 3) => Some(Token::If),
+            (5, 2) => Some(Token::Ident),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// generated by regex::lexer::Codegen, do not edit
+#[allow(dead_code, clippy::all)]
+pub mod scanner {
+    use super::*;
+
+    const START: [usize; 4] = [0, 0, 0, 0];
+
+    pub fn scan(input: &str, at: usize) -> Option<(Token, usize)> {
+        let mut state = START[context(input[..at].chars().next_back())];
+        let mut longest = None;
+        let mut end = at;
+        let mut chars = input[at..].chars();
+        loop {
+            let char = chars.next();
+            if end > at {
+                if let Some(kind) = accept(state, context(char)) {
+                    longest = Some((kind, end));
+                }
+            }
+            let Some(char) = char else {
+                return longest;
+            };
+            state = match next(state, char) {
+                Some(next) => next,
+                None => return longest
+            };
+            end += char.len_utf8();
+        }
+    }
+
+    fn context(char: Option<char>) -> usize {
+        match char {
+            None => 0,
+            Some('\n') => 1,
+            Some(char) if char.is_ascii_alphanumeric() || char == '_' => 2,
+            Some(_) => 3
+        }
+    }
+
+    const CLASSES: usize = 6;
+
+    // sorted intervals of characters and their class
+    static INTERVALS: [(char, char, usize); 11] = [
+        ('\t', '\t', 0),
+        ('\n', '\n', 1),
+        (' ', ' ', 0),
+        ('0', '9', 2),
+        ('A', 'Z', 3),
+        ('_', '_', 3),
+        ('a', 'e', 3),
+        ('f', 'f', 4),
+        ('g', 'h', 3),
+        ('i', 'i', 5),
+        ('j', 'z', 3),
+    ];
+
+    // next state by state * CLASSES + class
+    static TRANSITIONS: phf::Map<u32, u32> = phf::phf_map! {
+        0u32 => 1,
+        1u32 => 1,
+        2u32 => 2,
+        3u32 => 3,
+        4u32 => 3,
+        5u32 => 4,
+        6u32 => 1,
+        7u32 => 1,
+        14u32 => 2,
+        20u32 => 3,
+        21u32 => 3,
+        22u32 => 3,
+        23u32 => 3,
+        26u32 => 3,
+        27u32 => 3,
+        28u32 => 5,
+        29u32 => 3,
+        32u32 => 3,
+        33u32 => 3,
+        34u32 => 3,
+        35u32 => 3,
+    };
+
+    // rule accepted by each state before each context, 65535 if none
+    static ACCEPTS: [[u16; 4]; 6] = [
+        [65535, 65535, 65535, 65535],
+        [3, 3, 3, 3],
+        [2, 2, 2, 2],
+        [1, 1, 1, 1],
+        [1, 1, 1, 1],
+        [0, 0, 1, 0],
+    ];
+
+    fn next(state: usize, char: char) -> Option<usize> {
+        let index = INTERVALS.binary_search_by(|(start, end, _)| {
+            if *end < char {
+                std::cmp::Ordering::Less
+            } else if *start > char {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        }).ok()?;
+        let key = (state * CLASSES + INTERVALS[index].2) as u32;
+        TRANSITIONS.get(&key).map(|next| *next as usize)
+    }
+
+    fn accept(state: usize, context: usize) -> Option<Token> {
+        match ACCEPTS[state][context] {
+            0 => Some(Token::If),
+            1 => Some(Token::Ident),
+            2 => Some(Token::Number),
+            3 => Some(Token::Space),
+            _ => None
+        }
+    }
+}
@@ -1,14 +1,31 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod alphabet;
+pub mod analysis;
 #[allow(dead_code)]
 #[allow(unused_variables)]
 #[allow(unused_imports)]
-
-mod ast;
-mod parser;
-mod thompson;
+pub mod ast;
+pub mod class;
+pub mod derivative;
+pub mod dfa;
+pub mod error;
+pub mod examples;
+pub mod glushkov;
+pub mod lexer;
+pub mod look;
+pub mod parser;
+pub mod thompson;
+mod lazy;
+mod regex;
+mod shift_and;
+mod simulation;
 mod utils;
 
+pub use crate::error::{ErrorKind, RegexError};
+pub use crate::regex::{Captures, Match, Matches, Regex, RegexBuilder};
 
 #[cfg(test)]
 mod tests {
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,127 @@
+use std::fmt::{self, Display};
+
+// Zero width assertions. They do not consume input, they only check
+// characters around the current position.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Look {
+    // ^, or \A in multi line mode
+    StartText,
+    // $, or \z in multi line mode
+    EndText,
+    // ^ in multi line mode
+    StartLine,
+    // $ in multi line mode
+    EndLine,
+    // \b
+    WordBoundary,
+    // \B
+    NotWordBoundary
+}
+
+// What is on one side of a position, all assertions only depend on these
+// categories of the previous and the next character.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Context {
+    // start or end of the input
+    Edge,
+    LineFeed,
+    Word,
+    Other
+}
+
+impl Context {
+    pub const ALL: [Context; 4] = [Context::Edge, Context::LineFeed, Context::Word, Context::Other];
+
+    pub fn of(char: Option<char>) -> Context {
+        match char {
+            None => Context::Edge,
+            Some('\n') => Context::LineFeed,
+            Some(char) if is_word_char(char) => Context::Word,
+            Some(_) => Context::Other
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn bit(&self) -> u8 {
+        1 << self.index()
+    }
+}
+
+impl Look {
+    // true when assertion holds between prev and next
+    pub fn holds(&self, prev: Context, next: Context) -> bool {
+        match self {
+            Look::StartText => prev == Context::Edge,
+            Look::EndText => next == Context::Edge,
+            Look::StartLine => matches!(prev, Context::Edge | Context::LineFeed),
+            Look::EndLine => matches!(next, Context::Edge | Context::LineFeed),
+            Look::WordBoundary => (prev == Context::Word) != (next == Context::Word),
+            Look::NotWordBoundary => (prev == Context::Word) == (next == Context::Word)
+        }
+    }
+
+    // same assertion for input that is read backwards
+    pub fn reversed(&self) -> Look {
+        match self {
+            Look::StartText => Look::EndText,
+            Look::EndText => Look::StartText,
+            Look::StartLine => Look::EndLine,
+            Look::EndLine => Look::StartLine,
+            look => *look
+        }
+    }
+
+    // true when assertion holds at byte offset at of haystack
+    pub fn holds_at(&self, haystack: &str, at: usize) -> bool {
+        let prev = haystack[..at].chars().next_back();
+        let next = haystack[at..].chars().next();
+        self.holds(Context::of(prev), Context::of(next))
+    }
+}
+
+impl Display for Look {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Look::StartText => write!(f, "\\A"),
+            Look::EndText => write!(f, "\\z"),
+            Look::StartLine => write!(f, "(?m:^)"),
+            Look::EndLine => write!(f, "(?m:$)"),
+            Look::WordBoundary => write!(f, "\\b"),
+            Look::NotWordBoundary => write!(f, "\\B")
+        }
+    }
+}
+
+// characters of \w and \b
+pub fn is_word_char(char: char) -> bool {
+    char.is_ascii_alphanumeric() || char == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Look;
+
+    #[test]
+    fn text_and_line_anchors() {
+        let haystack = "ab\ncd";
+        assert!(Look::StartText.holds_at(haystack, 0));
+        assert!(!Look::StartText.holds_at(haystack, 3));
+        assert!(Look::StartLine.holds_at(haystack, 3));
+        assert!(Look::EndLine.holds_at(haystack, 2));
+        assert!(!Look::EndText.holds_at(haystack, 2));
+        assert!(Look::EndText.holds_at(haystack, 5));
+    }
+
+    #[test]
+    fn word_boundaries() {
+        let haystack = "x+y1";
+        assert!(Look::WordBoundary.holds_at(haystack, 0));
+        assert!(Look::WordBoundary.holds_at(haystack, 1));
+        assert!(Look::NotWordBoundary.holds_at(haystack, 3));
+        assert!(Look::WordBoundary.holds_at(haystack, 4));
+        assert!(Look::NotWordBoundary.holds_at("", 0));
+    }
+}
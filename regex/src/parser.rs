@@ -1,27 +1,53 @@
+use crate::ast::{Expr, Group, Concatenation, Alternation, Intersection, Complement, KleeneStar, OneOrMany, ZeroOrOne, BoundedRepetition, Literal, Assertion, Escape, MetaChar};
+use crate::class::CharClass;
+use crate::error::{ErrorKind, RegexError};
+use crate::look::Look;
 
-use std::iter::Peekable;
+use scanner::Scanner;
 
-use crate::ast::{Expr, Group, Concatenation, Alternation, KleeneStar, Literal, Escape};
+mod scanner;
 
 const MIN_PRECEDENCE: usize = 0;
 
 const ALTERNATION_LBP: usize = 25;
 const ALTERNATION_RBP: usize = 30; 
 
+// intersection binds tighter than alternation and looser than concatenation,
+// "a|b&cd" is a|(b&(cd))
+const INTERSECTION_LBP: usize = 30;
+const INTERSECTION_RBP: usize = 32;
+
 const CONCATENATION_LBP: usize = 35; 
 const CONCATENATION_RBP: usize = 40; 
 
-const STAR_LBP: usize = 50;
-
-const OP_PAREN_RBP: usize = 0;
-const CL_PAREN_RBP: usize = 70;
+// operand of complement is a single atom with its quantifiers, "~ab*" is (~a)(b*)
+const COMPLEMENT_RBP: usize = 45;
 
-const ESCAPE_RBP: usize = 80;
+const STAR_LBP: usize = 50;
 
 const OP_PAREN: char = '(';
 const CL_PAREN: char = ')';
 
+const OP_BRACK: char = '[';
+const CL_BRACK: char = ']';
+const CARET: char = '^';
+const DOLLAR: char = '$';
+const DOT: char = '.';
+const HASH: char = '#';
+const SPACE: char = ' ';
+const NEW_LINE: char = '\n';
+const DASH: char = '-';
+const COLON: char = ':';
+const LESS: char = '<';
+const GREATER: char = '>';
+
 const PIPE: char = '|';
+const AMPERSAND: char = '&';
+const TILDE: char = '~';
+
+const OP_BRACE: char = '{';
+const CL_BRACE: char = '}';
+const COMMA: char = ',';
 
 const STAR: char = '*';
 const QUESTION: char = '?';
@@ -29,19 +55,174 @@ const PLUS: char = '+';
 
 const ESCAPE: char = '\\';
 
-const META: [char;15] = ['.','^','$','*','+','?','{','}','[',']','\\','|','(',')',','];
+pub(crate) const META: [char;15] = ['.','^','$','*','+','?','{','}','[',']','\\','|','(',')',','];
+// operators of the extended syntax, they can be escaped in every mode
+pub(crate) const SET_META: [char;2] = ['&','~'];
 const CC_META: [char;4] = ['-','^','\\',']'];
 
-const QUANTIFIER: [char;3] = ['*','+','?'];
+const QUANTIFIER: [char;4] = ['*','+','?','{'];
+
+// \b and \B are word boundaries, \A and \z are start and end of input even in multi line mode
+const ESCAPED_LOOK: [char;4] = ['b','B','A','z'];
+
+// inline flags, see Config
+const FLAGS: [char;4] = ['i','m','s','x'];
+
+fn escaped_look(char: char) -> Look {
+    match char {
+        'b' => Look::WordBoundary,
+        'B' => Look::NotWordBoundary,
+        'A' => Look::StartText,
+        _ => Look::EndText
+    }
+}
+
+// default limit on the size of an expression after counted repetitions are expanded, 
+// without it "a{1000}{1000}" would need a million states. 
+const DEFAULT_SIZE_LIMIT: usize = 100_000;
+
+// Parser settings. Counted repetitions are expanded when expression is compiled
+// so the parser rejects any pattern with counted repetitions whose expanded size
+// is above size_limit, "a{60000}b{60000}" too even if both of them are below it.
+// With multi_line set "^" and "$" match at the start and end of every line
+// instead of only at the start and end of the input. With dot_matches_new_line
+// set "." matches any character, otherwise any character except "\n".
+//
+// Flags can also be switched inside of the pattern, "(?i)" until the end of the
+// enclosing group and "(?i:...)" only inside of the group. The letters are
+// i for case_insensitive, m for multi_line, s for dot_matches_new_line and
+// x for ignore_whitespace, flags after "-" are switched off, e.g. "(?i-s)".
+//
+// With extended set the pattern can also use the operators of regular languages
+// that have no place in the usual syntax, "a&b" matches what both a and b match
+// and "~a" everything a does not match. They are compiled through DFAs, so their
+// operands can not have assertions and capture groups inside of them capture nothing.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Config {
+    size_limit: usize,
+    multi_line: bool,
+    dot_matches_new_line: bool,
+    case_insensitive: bool,
+    // verbose mode, white space and comments from "#" to the end of line are
+    // skipped outside of classes, "\ " and "\#" match themselves
+    ignore_whitespace: bool,
+    extended: bool
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            size_limit: DEFAULT_SIZE_LIMIT,
+            multi_line: false,
+            dot_matches_new_line: false,
+            case_insensitive: false,
+            ignore_whitespace: false,
+            extended: false
+        }
+    }
+
+    pub fn size_limit(mut self, size_limit: usize) -> Config {
+        self.size_limit = size_limit;
+        self
+    }
+
+    pub fn multi_line(mut self, multi_line: bool) -> Config {
+        self.multi_line = multi_line;
+        self
+    }
+
+    pub fn dot_matches_new_line(mut self, dot_matches_new_line: bool) -> Config {
+        self.dot_matches_new_line = dot_matches_new_line;
+        self
+    }
+
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Config {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    pub fn ignore_whitespace(mut self, ignore_whitespace: bool) -> Config {
+        self.ignore_whitespace = ignore_whitespace;
+        self
+    }
+
+    pub fn extended(mut self, extended: bool) -> Config {
+        self.extended = extended;
+        self
+    }
+
+    // switches flag named by letter, false if there is no such flag
+    fn set_flag(&mut self, flag: char, value: bool) -> bool {
+        match flag {
+            'i' => self.case_insensitive = value,
+            'm' => self.multi_line = value,
+            's' => self.dot_matches_new_line = value,
+            'x' => self.ignore_whitespace = value,
+            _ => return false
+        }
+        true
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+pub fn parse(pattern: &str) -> Result<Expr, RegexError> {
+    parse_with(pattern, &Config::default())
+}
+
+pub fn parse_with(pattern: &str, config: &Config) -> Result<Expr, RegexError> {
+    let mut scanner = Scanner::new(pattern);
+
+    if scanner.peek().is_none() {
+        return Ok(Expr::Empty);
+    }
+
+    let mut state = State::new(*config);
+    let expr = parse_reg_expr(&mut scanner, &mut state, MIN_PRECEDENCE)?;
+
+    // top level expression stops early only on ")" that has no matching "("
+    skip_ignored(&mut scanner, &state);
+    if scanner.peek().is_some() {
+        return Err(error(&mut scanner, ErrorKind::UnbalancedParen));
+    }
+    // the limit is on the whole expression, sizes of sibling repetitions add up,
+    // the error points at the largest of them
+    let largest = state.bounded.iter().max_by_key(|(_, size)| *size);
+    match largest {
+        Some((offset, _)) if expr.size() > state.config.size_limit => {
+            Err(RegexError::new(ErrorKind::RepetitionTooLarge, *offset, scanner.pattern()))
+        },
+        _ => Ok(expr)
+    }
+}
 
-pub fn parse(pattern: &str) -> Expr {
-    let mut scanner = pattern.chars().peekable();
+// Everything the parser keeps track of besides its position in the pattern.
+// Capture groups are numbered by their opening parenthesis, starting at 1.
+struct State {
+    config: Config,
+    captures: usize,
+    names: Vec<String>,
+    // offsets of counted repetitions with their expanded sizes
+    bounded: Vec<(usize, usize)>
+}
 
-    if let None = scanner.peek() {
-        return Expr::Empty;
+impl State {
+    fn new(config: Config) -> State {
+        State {
+            config,
+            captures: 0,
+            names: Vec::new(),
+            bounded: Vec::new()
+        }
     }
+}
 
-    parse_reg_expr(&mut scanner, MIN_PRECEDENCE)
+fn error(scanner: &mut Scanner, kind: ErrorKind) -> RegexError {
+    RegexError::new(kind, scanner.offset(), scanner.pattern())
 }
 
 // Nud and Led are terms from paper by Pratt trhat denote a token that can start an expression
@@ -50,8 +231,12 @@ pub fn parse(pattern: &str) -> Expr {
 // Nud tokens are tokens that start exression
 enum Nud {
     Char(char),
-    Escape(usize),
+    Escape,
     OpParen(usize),
+    OpBrack,
+    Anchor(char),
+    Dot,
+    Complement(usize),
 }
 
 impl Nud {
@@ -59,19 +244,32 @@ impl Nud {
         Nud::Char(char)
     }
     fn escape() -> Nud {
-        Nud::Escape(ESCAPE_RBP)
+        Nud::Escape
     }
     fn op_paren() -> Nud {
         Nud::OpParen(MIN_PRECEDENCE)
     }
+    fn op_brack() -> Nud {
+        Nud::OpBrack
+    }
+    fn complement() -> Nud {
+        Nud::Complement(COMPLEMENT_RBP)
+    }
 }
 
-fn nud_token(char: char) -> Nud {
+fn nud_token(char: char, extended: bool) -> Result<Nud, ErrorKind> {
     match char {
-        OP_PAREN => Nud::op_paren(),
-        ESCAPE => Nud::escape(),
-        char if !META.contains(&char) => Nud::Char(char),
-        _ => panic!("invalid Nud token symbol")
+        TILDE if extended => Ok(Nud::complement()),
+        AMPERSAND if extended => Err(ErrorKind::MissingOperand),
+        OP_PAREN => Ok(Nud::op_paren()),
+        ESCAPE => Ok(Nud::escape()),
+        OP_BRACK => Ok(Nud::op_brack()),
+        CARET | DOLLAR => Ok(Nud::Anchor(char)),
+        DOT => Ok(Nud::Dot),
+        PIPE => Err(ErrorKind::EmptyAlternationBranch),
+        char if QUANTIFIER.contains(&char) => Err(ErrorKind::DanglingQuantifier),
+        char if !META.contains(&char) => Ok(Nud::character(char)),
+        char => Err(ErrorKind::UnescapedMetaChar(char))
     }
 }
 
@@ -79,7 +277,11 @@ fn nud_token(char: char) -> Nud {
 enum Led {
     Alternation(usize,usize),
     Concatenation(usize,usize),
+    Intersection(usize,usize),
     Repetition(usize),
+    ZeroOrOne(usize),
+    OneOrMany(usize),
+    Bounded(usize),
 
     Break
 }
@@ -90,155 +292,906 @@ impl Led {
         Led::Alternation(ALTERNATION_LBP, ALTERNATION_RBP)
     }
     fn concatenation() -> Led {
-        Led::Concatenation(ALTERNATION_LBP,ALTERNATION_RBP)
+        Led::Concatenation(CONCATENATION_LBP, CONCATENATION_RBP)
+    }
+    fn intersection() -> Led {
+        Led::Intersection(INTERSECTION_LBP, INTERSECTION_RBP)
     }
     fn repetiton() -> Led {
         Led::Repetition(STAR_LBP)
     }
+    fn zero_or_one() -> Led {
+        Led::ZeroOrOne(STAR_LBP)
+    }
+    fn one_or_many() -> Led {
+        Led::OneOrMany(STAR_LBP)
+    }
+    fn bounded() -> Led {
+        Led::Bounded(STAR_LBP)
+    }
 }
 
-fn led_token(char: char) -> Led {
+fn led_token(char: char, extended: bool) -> Led {
     match char {
-        _ => todo!()
+        PIPE => Led::alternation(),
+        AMPERSAND if extended => Led::intersection(),
+        STAR => Led::repetiton(),
+        QUESTION => Led::zero_or_one(),
+        PLUS => Led::one_or_many(),
+        OP_BRACE => Led::bounded(),
+        CL_PAREN => Led::Break,
+        // anything else starts an expression that is concatenated to the current one,
+        // if it can not start one, Nud will report it. 
+        _ => Led::concatenation()
     }
 }
 
-fn parse_reg_expr(scanner: &mut Peekable<impl Iterator<Item = char>>, min_prec: usize) -> Expr {
-    if let Some(char) = scanner.next() {
-        // handle Nud case  
-        let mut expr = match nud_token(char) {
-            Nud::OpParen(min_rbp) => {
-                let expr = parse_reg_expr(scanner, min_rbp);
-                let Some(CL_PAREN) = scanner.next() else {
-                    panic!("no matching \")\"")
-                };
-                Expr::Group(Box::new(Group::new(expr)))
+fn parse_reg_expr(scanner: &mut Scanner, state: &mut State, min_prec: usize) -> Result<Expr, RegexError> {
+    // nothing to parse, e.g. "()"
+    skip_ignored(scanner, state);
+    let Some(char) = scanner.peek().filter(|char| *char != CL_PAREN) else {
+        return Ok(Expr::Empty);
+    };
+
+    // handle Nud case  
+    let offset = scanner.offset();
+    scanner.next();
+    let nud = nud_token(char, state.config.extended)
+        .map_err(|kind| RegexError::new(kind, offset, scanner.pattern()))?;
+
+    let mut expr = match nud {
+        Nud::OpParen(min_rbp) => {
+            // flags set inside of a group are only in effect until it is closed
+            let config = state.config;
+            match parse_group_kind(scanner, state, offset)? {
+                // "(?i)" is not a group, it only changes flags
+                GroupKind::Flags => Expr::Empty,
+                kind => {
+                    let expr = parse_reg_expr(scanner, state, min_rbp)?;
+                    let Some(CL_PAREN) = scanner.next() else {
+                        return Err(RegexError::new(ErrorKind::UnbalancedParen, offset, scanner.pattern()));
+                    };
+                    state.config = config;
+                    let group = match kind {
+                        GroupKind::Capturing(index, name) => Group::capturing(expr, index, name),
+                        _ => Group::new(expr)
+                    };
+                    Expr::Group(Box::new(group))
+                }
             }
-            // backslash is also a metacharacter so the next element must be meta_charater
-            // after a few hours of fiddling around I, I decided that it is the 
-            // best to handle this case inside Nud Operator. 
-            // and then add other characters from the inside corresponding match match 
-            Nud::Escape(min_rbp) => {
-                match scanner.next() {
-                    Some(c) if META.contains(&c) => {
-                        Expr::Literal(Box::new(Literal::new(char)))
-                    }
-                    _ => panic!("invalid character")
+        }
+        // backslash is also a metacharacter so the next element must be meta_charater
+        // after a few hours of fiddling around I, I decided that it is the 
+        // best to handle this case inside Nud Operator. 
+        // and then add other characters from the inside corresponding match match 
+        Nud::Escape => {
+            match scanner.peek() {
+                Some(c) if ESCAPED_LOOK.contains(&c) => {
+                    scanner.next();
+                    Expr::Assertion(Box::new(Assertion::new(escaped_look(c))))
+                }
+                _ => match parse_escape(scanner, offset, &SET_META)? {
+                    Escaped::Char(c) => literal(c, state),
+                    Escaped::Class(c, class) => Expr::Escape(Box::new(Escape::new(c, class)))
+                }
+            }
+        },
+        Nud::Dot => {
+            let class = if state.config.dot_matches_new_line {
+                CharClass::any()
+            } else {
+                CharClass::single(NEW_LINE).negate()
+            };
+            Expr::MetaChar(Box::new(MetaChar::new(DOT, class)))
+        },
+        Nud::Anchor(anchor) => {
+            let look = match (anchor, state.config.multi_line) {
+                (CARET, false) => Look::StartText,
+                (CARET, true) => Look::StartLine,
+                (_, false) => Look::EndText,
+                (_, true) => Look::EndLine,
+            };
+            Expr::Assertion(Box::new(Assertion::new(look)))
+        },
+        Nud::Complement(min_rbp) => {
+            if let None | Some(PIPE) | Some(AMPERSAND) | Some(CL_PAREN) = skip_ignored(scanner, state) {
+                return Err(RegexError::new(ErrorKind::MissingOperand, offset, scanner.pattern()));
+            }
+            let expr = parse_reg_expr(scanner, state, min_rbp)?;
+            if has_assertion(&expr) {
+                return Err(RegexError::new(ErrorKind::AssertionInSetOperation, offset, scanner.pattern()));
+            }
+            Expr::Complement(Box::new(Complement::new(expr)))
+        },
+        Nud::OpBrack => parse_character_class(scanner, state, offset)?,
+        Nud::Char(char) => literal(char, state)
+    };
+
+    // handle led case
+    while let Some(lexeme) = skip_ignored(scanner, state) {
+        match led_token(lexeme, state.config.extended) {
+            Led::Alternation(left_bp, right_bp) =>  {
+                if left_bp < min_prec {
+                    break;
+                }
+
+                let offset = scanner.offset();
+                scanner.next();
+                // right side has to start an expression, "a|" and "(a|)" are errors
+                if let None | Some(PIPE) | Some(CL_PAREN) = skip_ignored(scanner, state) {
+                    return Err(RegexError::new(ErrorKind::EmptyAlternationBranch, offset, scanner.pattern()));
                 }
+                let right_expr = parse_reg_expr(scanner, state, right_bp)?;
+                let alternation = Alternation::new(expr, right_expr);
+                expr = Expr::Alternation(Box::new(alternation));
             },
-            Nud::Char(char) => todo!()
+            Led::Intersection(left_bp, right_bp) => {
+                if left_bp < min_prec {
+                    break;
+                }
+
+                let offset = scanner.offset();
+                scanner.next();
+                if let None | Some(PIPE) | Some(AMPERSAND) | Some(CL_PAREN) = skip_ignored(scanner, state) {
+                    return Err(RegexError::new(ErrorKind::MissingOperand, offset, scanner.pattern()));
+                }
+                let right_expr = parse_reg_expr(scanner, state, right_bp)?;
+                if has_assertion(&expr) || has_assertion(&right_expr) {
+                    return Err(RegexError::new(ErrorKind::AssertionInSetOperation, offset, scanner.pattern()));
+                }
+                let intersection = Intersection::new(expr, right_expr);
+                expr = Expr::Intersection(Box::new(intersection));
+            },
+            Led::Concatenation(left_bp, right_bp) => {
+                if left_bp < min_prec {   
+                    break;
+                }
+                let right_expr = parse_reg_expr(scanner, state, right_bp)?;
+                let concat = Concatenation::new(expr, right_expr);
+                expr = Expr::Concatenation(Box::new(concat));
+            },
+            Led::Repetition(left_bp) => {
+                if left_bp < min_prec {
+                    break;
+                }
+                scanner.next();
+                let star = KleeneStar::new(expr);
+                expr = Expr::Repetition(Box::new(star));
+            },
+            Led::ZeroOrOne(left_bp) => {
+                if left_bp < min_prec {
+                    break;
+                }
+                scanner.next();
+                let optional = ZeroOrOne::new(expr);
+                expr = Expr::ZeroOrOne(Box::new(optional));
+            },
+            Led::OneOrMany(left_bp) => {
+                if left_bp < min_prec {
+                    break;
+                }
+                scanner.next();
+                let plus = OneOrMany::new(expr);
+                expr = Expr::OneOrMany(Box::new(plus));
+            },
+            Led::Bounded(left_bp) => {
+                if left_bp < min_prec {
+                    break;
+                }
+                let offset = scanner.offset();
+                scanner.next();
+                let (min, max) = parse_quantifier(scanner, offset)?;
+                let bounded = BoundedRepetition::new(expr, min, max);
+                expr = Expr::Bounded(Box::new(bounded));
+                state.bounded.push((offset, expr.size()));
+            },
+            Led::Break => break,
         };
+    }
+    Ok(expr)
+}
 
-        // handle led case
-        while let Some(lexeme) = scanner.peek() {
-            let op = match led_token(*lexeme) {
-                Led::Alternation(left_bp, right_bp) =>  {
-                    if left_bp < min_prec {
-                        break;
-                    }
+// in verbose mode skips white space and comments, returns the next character
+fn skip_ignored(scanner: &mut Scanner, state: &State) -> Option<char> {
+    if !state.config.ignore_whitespace {
+        return scanner.peek();
+    }
+    loop {
+        match scanner.peek() {
+            Some(char) if char.is_whitespace() => {
+                scanner.next();
+            },
+            Some(HASH) => {
+                while scanner.next().is_some_and(|char| char != NEW_LINE) {}
+            },
+            char => return char
+        }
+    }
+}
 
-                    scanner.next();
-                    let right_expr = parse_reg_expr(scanner, right_bp);
-                    let alternation = Alternation::new(expr, right_expr);
-                    expr = Expr::Alternation(Box::new(alternation));
-                    
+// operands of & and ~ go through a DFA, which can not look around
+fn has_assertion(expr: &Expr) -> bool {
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        if let Expr::Assertion(_) = expr {
+            return true;
+        }
+        stack.extend(expr.children());
+    }
+    false
+}
+
+// literal that matches every case of char when case_insensitive is set
+fn literal(char: char, state: &State) -> Expr {
+    if !state.config.case_insensitive {
+        return Expr::Literal(Box::new(Literal::new(char)));
+    }
+    let class = CharClass::single(char).case_fold();
+    match class.ranges() {
+        [range] if range.start() == range.end() => Expr::Literal(Box::new(Literal::new(char))),
+        _ => Expr::Class(Box::new(class))
+    }
+}
+
+enum GroupKind {
+    Capturing(usize, Option<String>),
+    NonCapturing,
+    // "(?flags)" that is already closed
+    Flags
+}
+
+// Reads what follows the "(" located at offset, "?:" starts a non-capturing group,
+// "?<name>" a named one and "?flags" either sets flags or starts a non-capturing
+// group with them, e.g. "(?i)" or "(?i:...)".
+fn parse_group_kind(scanner: &mut Scanner, state: &mut State, offset: usize) -> Result<GroupKind, RegexError> {
+    let mut name = None;
+    if scanner.peek() == Some(QUESTION) {
+        scanner.next();
+        match scanner.peek() {
+            Some(COLON) => {
+                scanner.next();
+                return Ok(GroupKind::NonCapturing);
+            },
+            Some(LESS) => {
+                scanner.next();
+                name = Some(parse_capture_name(scanner, state, offset)?);
+            },
+            Some(char) if char == DASH || FLAGS.contains(&char) => {
+                return parse_flags(scanner, state, offset);
+            },
+            _ => return Err(RegexError::new(ErrorKind::InvalidGroup, offset, scanner.pattern()))
+        }
+    }
+
+    state.captures += 1;
+    Ok(GroupKind::Capturing(state.captures, name))
+}
+
+// flags up to and including the closing ":" or ")" of a group located at offset
+fn parse_flags(scanner: &mut Scanner, state: &mut State, offset: usize) -> Result<GroupKind, RegexError> {
+    let invalid = |scanner: &mut Scanner| {
+        RegexError::new(ErrorKind::InvalidFlag, offset, scanner.pattern())
+    };
+
+    let mut value = true;
+    // "(?-)" and "(?i-)" switch nothing off
+    let mut expects_flag = false;
+    loop {
+        match scanner.next() {
+            Some(DASH) if value => {
+                value = false;
+                expects_flag = true;
+            },
+            Some(COLON) if !expects_flag => return Ok(GroupKind::NonCapturing),
+            Some(CL_PAREN) if !expects_flag => return Ok(GroupKind::Flags),
+            Some(flag) if state.config.set_flag(flag, value) => expects_flag = false,
+            None => return Err(RegexError::new(ErrorKind::UnbalancedParen, offset, scanner.pattern())),
+            _ => return Err(invalid(scanner))
+        }
+    }
+}
+
+// name of "(?<name>...)" up to and including ">", it must be an identifier
+fn parse_capture_name(scanner: &mut Scanner, state: &mut State, offset: usize) -> Result<String, RegexError> {
+    let mut name = String::new();
+    loop {
+        match scanner.next() {
+            Some(GREATER) => break,
+            Some(char) if char.is_ascii_alphanumeric() || char == '_' => name.push(char),
+            _ => return Err(RegexError::new(ErrorKind::InvalidCaptureName, offset, scanner.pattern()))
+        }
+    }
+
+    if name.is_empty() || name.starts_with(|char: char| char.is_ascii_digit()) {
+        return Err(RegexError::new(ErrorKind::InvalidCaptureName, offset, scanner.pattern()));
+    }
+    if state.names.contains(&name) {
+        return Err(RegexError::new(ErrorKind::DuplicateCaptureName, offset, scanner.pattern()));
+    }
+    state.names.push(name.clone());
+    Ok(name)
+}
+
+// parses class after the opening "[" located at offset, e.g. "[^a-z_[:digit:]]".
+// Negation is applied right away, so the resulting class holds exactly the characters it matches.
+// It comes after case folding, "(?i)[^a]" matches neither "a" nor "A".
+fn parse_character_class(scanner: &mut Scanner, state: &State, offset: usize) -> Result<Expr, RegexError> {
+    let negated = scanner.peek() == Some(CARET);
+    if negated {
+        scanner.next();
+    }
+
+    let mut class = CharClass::empty();
+    let mut is_empty = true;
+    loop {
+        let item_offset = scanner.offset();
+        let start = match scanner.next() {
+            None => return Err(RegexError::new(ErrorKind::UnclosedClass, offset, scanner.pattern())),
+            Some(CL_BRACK) if is_empty => {
+                return Err(RegexError::new(ErrorKind::EmptyClass, offset, scanner.pattern()))
+            },
+            Some(CL_BRACK) => break,
+            Some(OP_BRACK) if scanner.peek() == Some(COLON) => {
+                class.union(&parse_posix_class(scanner, item_offset)?);
+                is_empty = false;
+                continue;
+            },
+            Some(ESCAPE) => match parse_escape(scanner, item_offset, &CC_META)? {
+                Escaped::Char(char) => char,
+                // "[\d-z]" has no range, "-" is taken literally
+                Escaped::Class(_, escaped) => {
+                    class.union(&escaped);
+                    is_empty = false;
                     continue;
-                },
-                Led::Concatenation(left_bp, right_bp) => {
-                    if left_bp < min_prec {   
-                        break;
+                }
+            },
+            Some(char) => char
+        };
+        is_empty = false;
+
+        if scanner.peek() != Some(DASH) {
+            class.push(start, start);
+            continue;
+        }
+        scanner.next();
+
+        let end_offset = scanner.offset();
+        let end = match scanner.peek() {
+            // "-" in front of "]" is not a range, e.g. "[a-]"
+            Some(CL_BRACK) | None => {
+                class.push(start, start);
+                class.push(DASH, DASH);
+                continue;
+            },
+            Some(ESCAPE) => {
+                scanner.next();
+                match parse_escape(scanner, end_offset, &CC_META)? {
+                    Escaped::Char(char) => char,
+                    Escaped::Class(..) => {
+                        return Err(RegexError::new(ErrorKind::InvalidRange, item_offset, scanner.pattern()))
                     }
-                    let right_expr = parse_reg_expr(scanner, right_bp);
-                    let concat = Concatenation::new(expr, right_expr);
-                    expr = Expr::Concatenation(Box::new(concat));
-                    
-                    continue;
-                },
-                Led::Repetition(left_bp) => {
-                    let star = KleeneStar::new(expr);
-                    expr = Expr::Repetition(Box::new(star));
-                    
-                    continue;
-                },
-                Led::Break => break,
+                }
+            },
+            Some(char) => {
+                scanner.next();
+                char
+            }
+        };
+        if end < start {
+            return Err(RegexError::new(ErrorKind::InvalidRange, item_offset, scanner.pattern()));
+        }
+        class.push(start, end);
+    }
+
+    if state.config.case_insensitive {
+        class = class.case_fold();
+    }
+    if negated {
+        class = class.negate();
+    }
+    Ok(Expr::Class(Box::new(class)))
+}
+
+// what an escape sequence stands for
+enum Escaped {
+    Char(char),
+    // shorthand class with the letter that named it
+    Class(char, CharClass)
+}
+
+// parses escape after the "\" located at offset. Metacharacters, the ones in metas 
+// and the ones outside of classes, escape themselves, e.g. "\.", "\-". 
+// Other escapes are "\n", "\t", "\r", "\xHH", "\u{H...}" and the shorthand classes.
+fn parse_escape(scanner: &mut Scanner, offset: usize, metas: &[char]) -> Result<Escaped, RegexError> {
+    let bad_escape = |scanner: &mut Scanner| {
+        RegexError::new(ErrorKind::BadEscape, offset, scanner.pattern())
+    };
+
+    let char = match scanner.next() {
+        Some(char) if metas.contains(&char) || META.contains(&char) => char,
+        // verbose mode would skip them otherwise
+        Some(char @ (SPACE | HASH)) => char,
+        Some('n') => NEW_LINE,
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('x') => parse_hex(scanner, 2, 2).ok_or_else(|| bad_escape(scanner))?,
+        Some('u') => {
+            let Some(OP_BRACE) = scanner.next() else {
+                return Err(bad_escape(scanner));
             };
+            let char = parse_hex(scanner, 1, 6).ok_or_else(|| bad_escape(scanner))?;
+            let Some(CL_BRACE) = scanner.next() else {
+                return Err(bad_escape(scanner));
+            };
+            char
+        },
+        Some(name) => {
+            let class = CharClass::perl(name).ok_or_else(|| bad_escape(scanner))?;
+            return Ok(Escaped::Class(name, class));
+        },
+        None => return Err(bad_escape(scanner))
+    };
+    Ok(Escaped::Char(char))
+}
+
+// reads between min and max hex digits, None when there are too few of them
+// or they are not a valid code point, e.g. a surrogate
+fn parse_hex(scanner: &mut Scanner, min: usize, max: usize) -> Option<char> {
+    let mut value = 0;
+    let mut digits = 0;
+    while digits < max {
+        let Some(digit) = scanner.peek().and_then(|char| char.to_digit(16)) else {
+            break;
+        };
+        scanner.next();
+        value = value * 16 + digit;
+        digits += 1;
+    }
+    if digits < min {
+        return None;
+    }
+    char::from_u32(value)
+}
+
+// parses "[:name:]" after its opening "[" located at offset
+fn parse_posix_class(scanner: &mut Scanner, offset: usize) -> Result<CharClass, RegexError> {
+    scanner.next();
+    let mut name = String::new();
+    while let Some(char) = scanner.next() {
+        if char == COLON {
+            break;
+        }
+        name.push(char);
+    }
+
+    match (scanner.next(), CharClass::posix(&name)) {
+        (Some(CL_BRACK), Some(class)) => Ok(class),
+        _ => Err(RegexError::new(ErrorKind::UnknownClassName, offset, scanner.pattern()))
+    }
+}
+
+// parses "{m}", "{m,}" or "{m,n}" after the opening "{" located at offset, 
+// max is None when repetition has no upper bound. 
+fn parse_quantifier(scanner: &mut Scanner, offset: usize) -> Result<(u32, Option<u32>), RegexError> {
+    let invalid = |scanner: &mut Scanner| {
+        RegexError::new(ErrorKind::InvalidRepetition, offset, scanner.pattern())
+    };
+
+    let min = parse_number(scanner, offset)?.ok_or_else(|| invalid(scanner))?;
+    let max = match scanner.next() {
+        Some(CL_BRACE) => return Ok((min, Some(min))),
+        Some(COMMA) => parse_number(scanner, offset)?,
+        _ => return Err(invalid(scanner))
+    };
+
+    match (scanner.next(), max) {
+        (Some(CL_BRACE), Some(max)) if max < min => Err(invalid(scanner)),
+        (Some(CL_BRACE), max) => Ok((min, max)),
+        _ => Err(invalid(scanner))
+    }
+}
+
+// reads decimal number if there is one
+fn parse_number(scanner: &mut Scanner, offset: usize) -> Result<Option<u32>, RegexError> {
+    let mut number: Option<u32> = None;
+    while let Some(digit) = scanner.peek().and_then(|char| char.to_digit(10)) {
+        scanner.next();
+        number = number
+            .unwrap_or(0)
+            .checked_mul(10)
+            .and_then(|number| number.checked_add(digit))
+            .map(Some)
+            .ok_or_else(|| RegexError::new(ErrorKind::RepetitionTooLarge, offset, scanner.pattern()))?;
+    }
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Expr;
+    use crate::class::{CharClass, ClassRange};
+    use crate::error::ErrorKind;
+    use crate::look::Look;
+
+    use super::{parse, parse_with, Config};
+
+    fn error(pattern: &str) -> (ErrorKind, usize) {
+        match parse(pattern) {
+            Ok(_) => panic!("{} should not parse", pattern),
+            Err(error) => (error.kind(), error.offset())
+        }
+    }
+
+    #[test]
+    fn parse_valid_patterns() {
+        for pattern in ["", "a", "ab|c", "(a|b)*c", "a+b?", "()", "\\(\\*", "(a(b)c)|d", "[a-z]+"] {
+            assert!(parse(pattern).is_ok(), "{} should parse", pattern);
+        }
+    }
+
+    #[test]
+    fn alternation_binds_weaker_than_concatenation() {
+        let Ok(Expr::Alternation(alt)) = parse("ab|c") else {
+            panic!("expected alternation")
+        };
+        assert!(matches!(alt.left(), Expr::Concatenation(_)));
+        assert!(matches!(alt.right(), Expr::Literal(_)));
+    }
+
+    #[test]
+    fn star_binds_tighter_than_concatenation() {
+        let Ok(Expr::Concatenation(concat)) = parse("ab*") else {
+            panic!("expected concatenation")
+        };
+        assert!(matches!(concat.left(), Expr::Literal(_)));
+        assert!(matches!(concat.right(), Expr::Repetition(_)));
+    }
+
+    #[test]
+    fn escape_produces_escaped_character() {
+        let Ok(Expr::Literal(literal)) = parse("\\|") else {
+            panic!("expected literal")
+        };
+        assert_eq!(literal.literal(), '|');
+    }
+
+    fn class(pattern: &str) -> CharClass {
+        class_with(pattern, &Config::default())
+    }
+
+    fn class_with(pattern: &str, config: &Config) -> CharClass {
+        match parse_with(pattern, config) {
+            Ok(Expr::Class(class)) => *class,
+            _ => panic!("{} should parse into a class", pattern)
+        }
+    }
+
+    #[test]
+    fn class_with_ranges() {
+        let identifier = class("[a-z0-9_]");
+        assert!(identifier.contains('q'));
+        assert!(identifier.contains('7'));
+        assert!(identifier.contains('_'));
+        assert!(!identifier.contains('A'));
+        assert!(!identifier.contains('-'));
+    }
+
+    #[test]
+    fn negated_class() {
+        let not_quote = class(r#"[^"\\]"#);
+        assert!(not_quote.contains('a'));
+        assert!(!not_quote.contains('"'));
+        assert!(!not_quote.contains('\\'));
+    }
+
+    #[test]
+    fn class_with_escaped_metacharacters_and_literal_dash() {
+        let operators = class(r"[-+\]\^*]");
+        for char in ['-', '+', ']', '^', '*'] {
+            assert!(operators.contains(char), "{} should be in class", char);
+        }
+        assert!(!operators.contains(','));
+        assert!(class("[a-]").contains('-'));
+    }
+
+    #[test]
+    fn posix_class() {
+        let alpha = class("[[:alpha:]_]");
+        assert!(alpha.contains('x'));
+        assert!(alpha.contains('_'));
+        assert!(!alpha.contains('1'));
+    }
+
+    #[test]
+    fn class_errors() {
+        assert_eq!(error("a[bc"), (ErrorKind::UnclosedClass, 1));
+        assert_eq!(error("[]"), (ErrorKind::EmptyClass, 0));
+        assert_eq!(error("[az-a]"), (ErrorKind::InvalidRange, 2));
+        assert_eq!(error("[[:alfa:]]"), (ErrorKind::UnknownClassName, 1));
+        assert_eq!(error(r"[\q]"), (ErrorKind::BadEscape, 1));
+    }
+
+    fn bounds(pattern: &str) -> (u32, Option<u32>) {
+        match parse(pattern) {
+            Ok(Expr::Bounded(bounded)) => (bounded.min(), bounded.max()),
+            _ => panic!("{} should parse into a counted repetition", pattern)
+        }
+    }
+
+    #[test]
+    fn counted_repetition() {
+        assert_eq!(bounds("a{3}"), (3, Some(3)));
+        assert_eq!(bounds("a{3,}"), (3, None));
+        assert_eq!(bounds("[0-9]{1,10}"), (1, Some(10)));
+        assert_eq!(bounds("(ab){0,2}"), (0, Some(2)));
+    }
+
+    #[test]
+    fn counted_repetition_binds_like_star() {
+        let Ok(Expr::Concatenation(concat)) = parse("ab{2}") else {
+            panic!("expected concatenation")
+        };
+        assert!(matches!(concat.right(), Expr::Bounded(_)));
+    }
+
+    #[test]
+    fn invalid_counted_repetition() {
+        assert_eq!(error("a{"), (ErrorKind::InvalidRepetition, 1));
+        assert_eq!(error("a{,2}"), (ErrorKind::InvalidRepetition, 1));
+        assert_eq!(error("a{2,1}"), (ErrorKind::InvalidRepetition, 1));
+        assert_eq!(error("a{1x}"), (ErrorKind::InvalidRepetition, 1));
+        assert_eq!(error("{2}"), (ErrorKind::DanglingQuantifier, 0));
+        assert_eq!(error("a{99999999999}"), (ErrorKind::RepetitionTooLarge, 1));
+    }
+
+    #[test]
+    fn expansion_limit() {
+        assert_eq!(error("a{1000}{1000}"), (ErrorKind::RepetitionTooLarge, 7));
+        assert_eq!(error("(ab{100}){1000}"), (ErrorKind::RepetitionTooLarge, 9));
+        assert!(parse("a{1000}").is_ok());
+        assert_eq!(error("a{60000}b{60000}"), (ErrorKind::RepetitionTooLarge, 9));
+        assert_eq!(error("(?:a{50000}|b{50000})c{2}"), (ErrorKind::RepetitionTooLarge, 13));
+        assert_eq!(error("(?:a{99999}|b{10})c{2}"), (ErrorKind::RepetitionTooLarge, 4));
+        assert!(parse("a{50000}b{50000}").is_ok());
+
+        let config = Config::new().size_limit(10);
+        assert!(parse_with("a{10}", &config).is_ok());
+        assert!(parse_with("a{5,}", &config).is_ok());
+        let Err(error) = parse_with("(ab){3,6}", &config) else {
+            panic!("repetition is above the limit")
+        };
+        assert_eq!(error.kind(), ErrorKind::RepetitionTooLarge);
+    }
+
+    fn look(expr: &Expr) -> Look {
+        match expr {
+            Expr::Assertion(assertion) => assertion.look(),
+            _ => panic!("expected assertion")
+        }
+    }
+
+    #[test]
+    fn anchors() {
+        let Ok(Expr::Concatenation(concat)) = parse("^a$") else {
+            panic!("expected concatenation")
+        };
+        let Expr::Concatenation(left) = concat.left() else {
+            panic!("expected concatenation")
+        };
+        assert_eq!(look(left.left()), Look::StartText);
+        assert_eq!(look(concat.right()), Look::EndText);
+
+        let config = Config::new().multi_line(true);
+        let Ok(Expr::Concatenation(concat)) = parse_with("^$", &config) else {
+            panic!("expected concatenation")
+        };
+        assert_eq!(look(concat.left()), Look::StartLine);
+        assert_eq!(look(concat.right()), Look::EndLine);
+        let Ok(expr) = parse_with("\\A", &config) else {
+            panic!("expected assertion")
+        };
+        assert_eq!(look(&expr), Look::StartText);
+    }
+
+    #[test]
+    fn word_boundaries() {
+        let Ok(expr) = parse("\\b") else {
+            panic!("expected assertion")
+        };
+        assert_eq!(look(&expr), Look::WordBoundary);
+        let Ok(expr) = parse("\\B") else {
+            panic!("expected assertion")
+        };
+        assert_eq!(look(&expr), Look::NotWordBoundary);
+    }
+
+    fn group(expr: &Expr) -> (Option<usize>, Option<&str>) {
+        match expr {
+            Expr::Group(group) => (group.index(), group.name()),
+            _ => panic!("expected group")
         }
-        return expr;
-    }
-    Expr::Empty
-
-    // loop {
-    //     let operator = match scanner.peek() {
-    //         Some(char@_) if !META.contains(&char) => Led::concatenation(),
-    //         Some('(') => Led::concatenation(),
-    //         Some('|') => Led::alternation(),
-    //         Some('*') => Led::kleene_star(),
-    //         Some('?') => Led::zero_or_one(),
-    //         Some('+') => Led::one_or_many(),
-    //         Some(')') => break,
-    //         None => break,
-    //         _ => {
-    //             panic!("Encountered invalid operator")
-    //         }
-    //     };
-
-    //     match operator {
-    //         Led::Alternation(left_bp@_,right_bp) => {
-    //             if left_bp < min_prec {
-    //                 break;
-    //             }
-    //             scanner.next();
-    //             let right_expr = parse_reg_expr(scanner, right_bp);
-    //             let alternation = Alternation::new(expr, right_expr);
-    //             expr = Expr::Alternation(Box::new(alternation));
-
-    //             continue;
-    //         },
-    //         Led::Concatenation(left_bp@_,right_bp@_) => {
-    //             if left_bp < min_prec {
-    //                 break;
-    //             }
-    //             let right_expr = parse_reg_expr(scanner, right_bp);
-    //             let concat = Concatenation::new(expr, right_expr);
-    //             expr = Expr::Concatenation(Box::new(concat));
-
-    //             continue;
-    //         },
-    //         Led::Repetition(left) => {
-    //             let star = KleeneStar::new(expr);
-    //             expr = Expr::Repetition(Box::new(star));
-
-    //             continue;
-    //         },
-    //         Led::ZeroOrOne(left) => {
-    //             let empty = Expr::Empty;
-    //             let alternation = Alternation::new(expr,empty);
-    //             let inner_expr = Expr::Alternation(Box::new(alternation));
-    //             let group = Group::new(inner_expr);
-    //             expr = Expr::Group(Box::new(group));
-
-    //             continue;
-    //         },
-    //         Led::OneOrMany(left) => {
-    //             let clone = expr.clone();
-    //             let star = KleeneStar::new(clone);
-    //             let right_expr = Expr::Repetition(Box::new(star));
-    //             let concat = Concatenation::new(expr, right_expr);
-    //             let inner = Expr::Concatenation(Box::new(concat));
-    //             let group = Group::new(inner);
-    //             expr = Expr::Group(Box::new(group));
-
-    //             continue;
-    //         },
-    //     };
-    // }
-
-}
-
-fn parse_character_class(scanner: &mut impl Iterator<Item=char>) -> Expr {
-    todo!()
-}
-
-fn parse_quantifier(scanner: &mut impl Iterator<Item=char>) -> Expr {
-    todo!()
+    }
+
+    #[test]
+    fn groups_are_numbered_by_opening_paren() {
+        let Ok(Expr::Concatenation(concat)) = parse("((?:a)(?<name>b))(c)") else {
+            panic!("expected concatenation")
+        };
+        assert_eq!(group(concat.left()), (Some(1), None));
+        assert_eq!(group(concat.right()), (Some(3), None));
+        let Expr::Group(outer) = concat.left() else {
+            panic!("expected group")
+        };
+        let Expr::Concatenation(inner) = outer.expr() else {
+            panic!("expected concatenation")
+        };
+        assert_eq!(group(inner.left()), (None, None));
+        assert_eq!(group(inner.right()), (Some(2), Some("name")));
+    }
+
+    #[test]
+    fn group_errors() {
+        assert_eq!(error("a(?=b)"), (ErrorKind::InvalidGroup, 1));
+        assert_eq!(error("(?<>a)"), (ErrorKind::InvalidCaptureName, 0));
+        assert_eq!(error("(?<1x>a)"), (ErrorKind::InvalidCaptureName, 0));
+        assert_eq!(error("(?<x-y>a)"), (ErrorKind::InvalidCaptureName, 0));
+        assert_eq!(error("(?<x>a)(?<x>b)"), (ErrorKind::DuplicateCaptureName, 7));
+    }
+
+    #[test]
+    fn flag_errors() {
+        assert_eq!(error("a(?q)"), (ErrorKind::InvalidGroup, 1));
+        assert_eq!(error("(?iq)"), (ErrorKind::InvalidFlag, 0));
+        assert_eq!(error("(?i-)"), (ErrorKind::InvalidFlag, 0));
+        assert_eq!(error("(?i-m-s)"), (ErrorKind::InvalidFlag, 0));
+        assert_eq!(error("(?i"), (ErrorKind::UnbalancedParen, 0));
+    }
+
+    #[test]
+    fn case_insensitive_literal_becomes_class() {
+        let config = Config::new().case_insensitive(true);
+        assert_eq!(class_with("k", &config), CharClass::new([
+            ClassRange::new('K', 'K'),
+            ClassRange::new('k', 'k'),
+            ClassRange::new('\u{212A}', '\u{212A}')
+        ]));
+        let Ok(Expr::Literal(_)) = parse_with("1", &config) else {
+            panic!("expected literal")
+        };
+    }
+
+    #[test]
+    fn verbose_mode_skips_comments() {
+        let config = Config::new().ignore_whitespace(true);
+        let Ok(Expr::Concatenation(concat)) = parse_with(" a # first\n b # second", &config) else {
+            panic!("expected concatenation")
+        };
+        assert!(matches!(concat.left(), Expr::Literal(_)));
+        assert!(matches!(concat.right(), Expr::Literal(_)));
+        assert_eq!(error("(?x)a | # nothing\n"), (ErrorKind::EmptyAlternationBranch, 6));
+    }
+
+    #[test]
+    fn unbalanced_paren() {
+        assert_eq!(error("a(b|c"), (ErrorKind::UnbalancedParen, 1));
+        assert_eq!(error("ab)"), (ErrorKind::UnbalancedParen, 2));
+        assert_eq!(error(")"), (ErrorKind::UnbalancedParen, 0));
+    }
+
+    #[test]
+    fn dangling_quantifier() {
+        assert_eq!(error("*a"), (ErrorKind::DanglingQuantifier, 0));
+        assert_eq!(error("a|+"), (ErrorKind::DanglingQuantifier, 2));
+        assert_eq!(error("(*)"), (ErrorKind::DanglingQuantifier, 1));
+    }
+
+    #[test]
+    fn bad_escape() {
+        assert_eq!(error("a\\q"), (ErrorKind::BadEscape, 1));
+        assert_eq!(error("a\\"), (ErrorKind::BadEscape, 1));
+        assert_eq!(error("\\x4"), (ErrorKind::BadEscape, 0));
+        assert_eq!(error("\\u{}"), (ErrorKind::BadEscape, 0));
+        assert_eq!(error("\\u{D800}"), (ErrorKind::BadEscape, 0));
+        assert_eq!(error("\\u{1F600"), (ErrorKind::BadEscape, 0));
+        assert_eq!(error("[a-\\d]"), (ErrorKind::InvalidRange, 1));
+    }
+
+    fn literal(pattern: &str) -> char {
+        let Ok(Expr::Literal(literal)) = parse(pattern) else {
+            panic!("expected literal")
+        };
+        literal.literal()
+    }
+
+    #[test]
+    fn character_escapes() {
+        assert_eq!(literal("\\n"), '\n');
+        assert_eq!(literal("\\t"), '\t');
+        assert_eq!(literal("\\x41"), 'A');
+        assert_eq!(literal("\\u{1F600}"), '😀');
+        assert_eq!(class("[\\x41-\\x43\\n]"), CharClass::new([
+            ClassRange::new('\n', '\n'),
+            ClassRange::new('A', 'C')
+        ]));
+    }
+
+    #[test]
+    fn shorthand_classes() {
+        let Ok(Expr::Escape(escape)) = parse("\\d") else {
+            panic!("expected escape")
+        };
+        assert_eq!(escape.escape(), 'd');
+        assert_eq!(escape.class(), &CharClass::new([ClassRange::new('0', '9')]));
+        let Ok(Expr::Escape(escape)) = parse("\\S") else {
+            panic!("expected escape")
+        };
+        assert!(escape.class().contains('x'));
+        assert!(!escape.class().contains(' '));
+
+        // "-" after a shorthand class is not a range
+        let class = class("[\\d-_]");
+        assert!(class.contains('5') && class.contains('-') && class.contains('_'));
+    }
+
+    #[test]
+    fn dot() {
+        let Ok(Expr::MetaChar(meta)) = parse(".") else {
+            panic!("expected metacharacter")
+        };
+        assert_eq!(meta.meta(), '.');
+        assert!(meta.class().contains('ä'));
+        assert!(!meta.class().contains('\n'));
+
+        let config = Config::new().dot_matches_new_line(true);
+        let Ok(Expr::MetaChar(meta)) = parse_with(".", &config) else {
+            panic!("expected metacharacter")
+        };
+        assert!(meta.class().contains('\n'));
+    }
+
+    #[test]
+    fn empty_alternation_branch() {
+        assert_eq!(error("a|"), (ErrorKind::EmptyAlternationBranch, 1));
+        assert_eq!(error("|a"), (ErrorKind::EmptyAlternationBranch, 0));
+        assert_eq!(error("(a||b)"), (ErrorKind::EmptyAlternationBranch, 2));
+    }
+
+    #[test]
+    fn unescaped_meta_char() {
+        assert_eq!(error("a}"), (ErrorKind::UnescapedMetaChar('}'), 1));
+    }
+
+    fn extended(pattern: &str) -> Result<String, (ErrorKind, usize)> {
+        parse_with(pattern, &Config::new().extended(true))
+            .map(|expr| format!("{:?}", expr))
+            .map_err(|error| (error.kind(), error.offset()))
+    }
+
+    #[test]
+    fn set_operators_in_extended_syntax() {
+        assert_eq!(extended("a|b&cd"), Ok("(alt 'a' (and 'b' (concat 'c' 'd')))".to_string()));
+        assert_eq!(extended("a&b&c"), Ok("(and (and 'a' 'b') 'c')".to_string()));
+        assert_eq!(extended("~ab*"), Ok("(concat (not 'a') (star 'b'))".to_string()));
+        assert_eq!(extended("~~a+"), Ok("(not (not (plus 'a')))".to_string()));
+        assert_eq!(extended("a~(b|c)"), Ok("(concat 'a' (not (capture 1 (alt 'b' 'c'))))".to_string()));
+        assert_eq!(extended("\\&\\~"), Ok("(concat '&' '~')".to_string()));
+
+        // without extended they are plain characters
+        let Ok(expr) = parse("a&~b") else {
+            panic!("& and ~ are literals")
+        };
+        assert_eq!(format!("{:?}", expr), "(concat (concat (concat 'a' '&') '~') 'b')");
+    }
+
+    #[test]
+    fn set_operator_errors() {
+        assert_eq!(extended("a&"), Err((ErrorKind::MissingOperand, 1)));
+        assert_eq!(extended("&a"), Err((ErrorKind::MissingOperand, 0)));
+        assert_eq!(extended("a&&b"), Err((ErrorKind::MissingOperand, 1)));
+        assert_eq!(extended("(~)"), Err((ErrorKind::MissingOperand, 1)));
+        assert_eq!(extended("a|~"), Err((ErrorKind::MissingOperand, 2)));
+        assert_eq!(extended("~^a"), Err((ErrorKind::AssertionInSetOperation, 0)));
+        assert_eq!(extended("a&(b$)"), Err((ErrorKind::AssertionInSetOperation, 1)));
+        assert!(extended("^(a&b)$").is_ok());
+    }
 }
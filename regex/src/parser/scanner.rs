@@ -1,78 +1,53 @@
-use phf::{phf_map, phf_set, Set};
-
-const OP_PAREN: char = '(';
-const CL_PAREN: char = ')';
-// const OP_BRACK: char = '[';
-// const CL_BRACK: char = ']';
-
-// const CARET: char = '^';
-const BACKSLASH: char = '\\';
-
-const ALTERNATION: char = '|';
-const ASTERISK: char = '*';
-const PLUS: char = '+';
-const QUESTION_MARK: char = '?';
-
-// const DOLLAR: char = '$';
-
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub(super) enum Lexeme {
-    Literal(char),
-
-    OpParen, 
-    ClParen, 
-
-    Quantifier(char),
-    Alternation(char),
-    Concatenation(char),
-
-    Escape(char),
-
-    Epsilon,
-
-    EoS,
-    Error
-}
-pub(super) struct Scanner {
-    tokens: Vec<Lexeme>
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+// Character cursor over a pattern. Besides the characters themselves it keeps
+// track of byte offsets, so that errors can point into the pattern.
+pub(super) struct Scanner<'a> {
+    pattern: &'a str,
+    chars: Peekable<CharIndices<'a>>
 }
 
-impl Scanner {
-    pub(super) fn new(pattern: &str) -> Scanner {
-        let mut lexemes = pattern.chars().map(|c| c.into()).collect::<Vec<Lexeme>>();
-        lexemes.reverse();
+impl<'a> Scanner<'a> {
+    pub(super) fn new(pattern: &'a str) -> Scanner<'a> {
         Scanner {
-            tokens: lexemes
+            pattern,
+            chars: pattern.char_indices().peekable()
         }
     }
 
-    pub(super) fn next(&mut self) -> Lexeme {
-        self.tokens.pop().unwrap_or(Lexeme::EoS)
+    pub(super) fn next(&mut self) -> Option<char> {
+        self.chars.next().map(|(_, char)| char)
     }
 
-    pub(super) fn peek(&self) -> Lexeme {
-        self.tokens.last().copied().unwrap_or(Lexeme::EoS)
+    pub(super) fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, char)| *char)
     }
-}
 
-impl From<char> for Lexeme {
-    fn from(value: char) -> Self {
-        match value {
-            OP_PAREN => Lexeme::OpParen,
-            CL_PAREN => Lexeme::ClParen,
-            BACKSLASH => Lexeme::Escape('\\'),
-            ALTERNATION => Lexeme::Alternation('|'),
-            char @(ASTERISK|QUESTION_MARK|PLUS) => Lexeme::Quantifier(char),
-            char@_ => Lexeme::Literal(char)
-        }
+    // byte offset of the next character, or length of the pattern at the end
+    pub(super) fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.pattern.len(), |(offset, _)| *offset)
+    }
+
+    pub(super) fn pattern(&self) -> &'a str {
+        self.pattern
     }
 }
 
 #[cfg(test)]
 mod tests {
-
-    use std::{assert_eq, println};
-
-
-    
-}
\ No newline at end of file
+    use super::Scanner;
+
+    #[test]
+    fn offsets_are_in_bytes() {
+        let mut scanner = Scanner::new("äb");
+        assert_eq!(scanner.offset(), 0);
+        assert_eq!(scanner.next(), Some('ä'));
+        assert_eq!(scanner.offset(), 2);
+        assert_eq!(scanner.next(), Some('b'));
+        assert_eq!(scanner.offset(), 3);
+        assert_eq!(scanner.next(), None);
+    }
+}
@@ -0,0 +1,495 @@
+use std::fmt::{self, Debug, Display};
+use std::ops::Range;
+
+use crate::ast::simplify::simplify;
+use crate::error::RegexError;
+use crate::lazy::{Direction, GaveUp, LazyDFA};
+use crate::parser::{self, Config};
+use crate::shift_and::ShiftAnd;
+use crate::simulation::Simulation;
+use crate::thompson::NFA;
+
+// Compiled regular expression. Searches run in time linear in the length of
+// the haystack and report leftmost-first matches as byte offsets.
+//
+// Matches are found by lazy DFAs, a forward one finds where the match ends and
+// a reverse one where it starts. When they give up, the NFA is simulated instead.
+// Short patterns without assertions also get a bit-parallel engine, which answers
+// is_match on its own.
+pub struct Regex {
+    pattern: String,
+    forward: LazyDFA,
+    reverse: LazyDFA,
+    shift_and: Option<ShiftAnd>
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        if let Some(shift_and) = &self.shift_and {
+            return shift_and.is_match(haystack);
+        }
+        match self.forward.find_end(haystack, 0, true) {
+            Ok(end) => end.is_some(),
+            Err(GaveUp) => Simulation::new(self.nfa())
+                .find_at(haystack, 0, true)
+                .is_some()
+        }
+    }
+
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+            .map(|(start, end)| Match::new(haystack, start, end))
+    }
+
+    // leftmost-first match together with the spans of its capture groups
+    pub fn captures<'r, 'h>(&'r self, haystack: &'h str) -> Option<Captures<'r, 'h>> {
+        // only the NFA knows where groups are, but it does not have to look
+        // for the match in front of where the DFAs found it
+        let (start, _) = self.find_at(haystack, 0)?;
+        Simulation::with_captures(self.nfa())
+            .captures_at(haystack, start, false)
+            .map(|slots| Captures {
+                haystack,
+                slots,
+                names: self.nfa().capture_names()
+            })
+    }
+
+    // number of capture groups, group 0 that spans the whole match included
+    pub fn captures_len(&self) -> usize {
+        self.nfa().captures_len()
+    }
+
+    // all successive non-overlapping matches
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        Matches {
+            haystack,
+            regex: self,
+            at: 0,
+            last_end: None
+        }
+    }
+
+    // leftmost-first match that starts at or after start
+    pub(crate) fn find_at(&self, haystack: &str, start: usize) -> Option<(usize, usize)> {
+        match self.find_lazy(haystack, start) {
+            Ok(found) => found,
+            Err(GaveUp) => Simulation::new(self.nfa()).find_at(haystack, start, false)
+        }
+    }
+
+    fn find_lazy(&self, haystack: &str, start: usize) -> Result<Option<(usize, usize)>, GaveUp> {
+        let Some(end) = self.forward.find_end(haystack, start, false)? else {
+            return Ok(None);
+        };
+        let start = self.reverse.find_start(haystack, start, end)?;
+        Ok(start.map(|start| (start, end)))
+    }
+
+    fn nfa(&self) -> &NFA {
+        self.forward.nfa()
+    }
+}
+
+// Builds Regex with non-default flags, they are the same flags that can be
+// switched inline, e.g. case_insensitive(true) is like starting pattern with "(?i)".
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    config: Config
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            config: Config::new()
+        }
+    }
+
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> RegexBuilder {
+        self.config = self.config.case_insensitive(case_insensitive);
+        self
+    }
+
+    pub fn multi_line(mut self, multi_line: bool) -> RegexBuilder {
+        self.config = self.config.multi_line(multi_line);
+        self
+    }
+
+    pub fn dot_matches_new_line(mut self, dot_matches_new_line: bool) -> RegexBuilder {
+        self.config = self.config.dot_matches_new_line(dot_matches_new_line);
+        self
+    }
+
+    pub fn ignore_whitespace(mut self, ignore_whitespace: bool) -> RegexBuilder {
+        self.config = self.config.ignore_whitespace(ignore_whitespace);
+        self
+    }
+
+    // "&" and "~" operators, see Config
+    pub fn extended(mut self, extended: bool) -> RegexBuilder {
+        self.config = self.config.extended(extended);
+        self
+    }
+
+    pub fn size_limit(mut self, size_limit: usize) -> RegexBuilder {
+        self.config = self.config.size_limit(size_limit);
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, RegexError> {
+        let expr = parser::parse_with(&self.pattern, &self.config)?;
+        // simplified pattern matches the same, with the same captures, using fewer states
+        let expr = simplify(&expr);
+        let nfa = NFA::from_expr(&expr);
+        Ok(Regex {
+            pattern: self.pattern.clone(),
+            shift_and: ShiftAnd::new(&expr),
+            reverse: LazyDFA::new(nfa.reverse(), Direction::Reverse),
+            forward: LazyDFA::new(nfa, Direction::Forward)
+        })
+    }
+}
+
+impl Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+impl Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Regex").field(&self.pattern).finish()
+    }
+}
+
+// single match, start and end are byte offsets into the haystack
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize
+}
+
+impl<'h> Match<'h> {
+    pub(crate) fn new(haystack: &'h str, start: usize, end: usize) -> Match<'h> {
+        Match {
+            haystack,
+            start,
+            end
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.range()]
+    }
+}
+
+// Spans of the capture groups of a single match. Group 0 is the whole match,
+// groups that did not participate in it have no span.
+#[derive(Debug, Clone)]
+pub struct Captures<'r, 'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>,
+    names: &'r [Option<String>]
+}
+
+impl<'r, 'h> Captures<'r, 'h> {
+    pub fn get(&self, index: usize) -> Option<Match<'h>> {
+        let start = (*self.slots.get(2 * index)?)?;
+        let end = (*self.slots.get(2 * index + 1)?)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        let index = self.names
+            .iter()
+            .position(|group| group.as_deref() == Some(name))?;
+        self.get(index)
+    }
+
+    // number of groups, including the ones that did not match
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+// iterator returned by Regex::find_iter
+pub struct Matches<'r, 'h> {
+    haystack: &'h str,
+    regex: &'r Regex,
+    at: usize,
+    last_end: Option<usize>
+}
+
+impl<'r, 'h> Iterator for Matches<'r, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        loop {
+            if self.at > self.haystack.len() {
+                return None;
+            }
+            let (start, end) = self.regex.find_at(self.haystack, self.at)?;
+
+            if start == end {
+                // step over one character so that search makes progress after an empty match
+                self.at = end + self.haystack[end..].chars().next().map_or(1, char::len_utf8);
+                // empty match right where the previous one ended is not reported, "a*" on "ab"
+                // gives "a" and "" at 2, but not another "" at 1
+                if self.last_end == Some(end) {
+                    continue;
+                }
+            } else {
+                self.at = end;
+            }
+
+            self.last_end = Some(end);
+            return Some(Match::new(self.haystack, start, end));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::Simulation;
+
+    use super::{Regex, RegexBuilder};
+
+    fn regex(pattern: &str) -> Regex {
+        match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(error) => panic!("{}", error)
+        }
+    }
+
+    fn spans(pattern: &str, haystack: &str) -> Vec<(usize, usize)> {
+        regex(pattern)
+            .find_iter(haystack)
+            .map(|found| (found.start(), found.end()))
+            .collect()
+    }
+
+    #[test]
+    fn is_match() {
+        let identifier = regex("[a-zA-Z_][a-zA-Z0-9_]*");
+        assert!(identifier.is_match("int x1 = 0;"));
+        assert!(!identifier.is_match("123 + 456"));
+        assert!(regex("").is_match(""));
+    }
+
+    #[test]
+    fn find_returns_span() {
+        let number = regex("[0-9]+");
+        let found = number.find("x = 1024;").unwrap();
+        assert_eq!(found.range(), 4..8);
+        assert_eq!(found.as_str(), "1024");
+    }
+
+    #[test]
+    fn find_uses_byte_offsets() {
+        let found = regex("ö+").find("größe").unwrap();
+        assert_eq!(found.range(), 2..4);
+        assert_eq!(found.as_str(), "ö");
+    }
+
+    #[test]
+    fn find_iter_non_overlapping() {
+        assert_eq!(spans("[0-9]+", "1 22 333"), vec![(0, 1), (2, 4), (5, 8)]);
+        assert_eq!(spans("aa", "aaaaa"), vec![(0, 2), (2, 4)]);
+        assert!(spans("x", "abc").is_empty());
+    }
+
+    #[test]
+    fn find_iter_empty_matches() {
+        assert_eq!(spans("a*", "ab"), vec![(0, 1), (2, 2)]);
+        assert_eq!(spans("", "äb"), vec![(0, 0), (2, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn assertions() {
+        assert_eq!(spans("\\b[a-z]+\\b", "one, two"), vec![(0, 3), (5, 8)]);
+        assert_eq!(spans("^a", "aaa"), vec![(0, 1)]);
+        assert_eq!(spans("$", "ab"), vec![(2, 2)]);
+        assert!(!regex("^b").is_match("ab"));
+    }
+
+    #[test]
+    fn shorthand_classes_and_dot() {
+        assert_eq!(spans("\\d+", "a1b22"), vec![(1, 2), (3, 5)]);
+        assert_eq!(spans("\\s+", "a \t\nb"), vec![(1, 4)]);
+        assert_eq!(spans("[\\w$]+", "int $x1;"), vec![(0, 3), (4, 7)]);
+        assert_eq!(spans("a.c", "abc a\nc aäc"), vec![(0, 3), (8, 12)]);
+        assert_eq!(spans("\\u{1F600}+", "x😀😀"), vec![(1, 9)]);
+    }
+
+    #[test]
+    fn captures() {
+        let number = regex("(?<mantissa>([0-9]+)(?:\\.([0-9]+))?)(?:e(?<exponent>-?[0-9]+))?");
+        assert_eq!(number.captures_len(), 5);
+
+        let caps = number.captures("x = 6.02e23;").unwrap();
+        assert_eq!(caps.len(), 5);
+        assert_eq!(caps.get(0).unwrap().as_str(), "6.02e23");
+        assert_eq!(caps.name("mantissa").unwrap().as_str(), "6.02");
+        assert_eq!(caps.get(2).unwrap().as_str(), "6");
+        assert_eq!(caps.get(3).unwrap().as_str(), "02");
+        assert_eq!(caps.name("exponent").unwrap().range(), 9..11);
+
+        let caps = number.captures("42").unwrap();
+        assert!(caps.get(3).is_none());
+        assert!(caps.name("exponent").is_none());
+        assert!(caps.name("missing").is_none());
+        assert!(number.captures("x").is_none());
+    }
+
+    #[test]
+    fn captures_are_leftmost_first() {
+        let groups = |pattern: &str, haystack: &str| {
+            let regex = regex(pattern);
+            let caps = regex.captures(haystack).unwrap();
+            (0..caps.len())
+                .map(|index| caps.get(index).map(|group| (group.start(), group.end())))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(groups("(a|ab)(c|bcd)(d*)", "abcd"), vec![Some((0, 4)), Some((0, 1)), Some((1, 4)), Some((4, 4))]);
+        // repeated group reports its last iteration
+        assert_eq!(groups("(?:(a)|b)+", "abab"), vec![Some((0, 4)), Some((2, 3))]);
+        assert_eq!(groups("(a){2}", "aa"), vec![Some((0, 2)), Some((1, 2))]);
+    }
+
+    #[test]
+    fn builder_flags() {
+        let keyword = RegexBuilder::new("select|from").case_insensitive(true).build().unwrap();
+        assert_eq!(keyword.find("SeLeCt *").map(|found| found.range()), Some(0..6));
+
+        let lines = RegexBuilder::new("^[a-z]+$").multi_line(true).build().unwrap();
+        let found = lines.find_iter("ab\n12\ncd").map(|found| found.as_str()).collect::<Vec<_>>();
+        assert_eq!(found, vec!["ab", "cd"]);
+
+        let dot = RegexBuilder::new("a.b").dot_matches_new_line(true).build().unwrap();
+        assert!(dot.is_match("a\nb"));
+        assert!(!regex("a.b").is_match("a\nb"));
+    }
+
+    #[test]
+    fn extended_syntax() {
+        // words of at least 3 letters except the ones that contain "ab"
+        let words = RegexBuilder::new("[a-z]{3,}&~(?:.*ab.*)").extended(true).build().unwrap();
+        assert_eq!(words.find("ab cabin cdef").map(|found| found.as_str()), Some("bin"));
+        let found = words.find_iter("xy abc cdef").map(|found| found.as_str()).collect::<Vec<_>>();
+        assert_eq!(found, vec!["cdef"]);
+        assert!(RegexBuilder::new("a&b").build().unwrap().is_match("a&b"));
+    }
+
+    #[test]
+    fn nested_set_operations_compile_once() {
+        // compiling every operand again at each level would take 2^30 times as long
+        let complements = "~(".repeat(30) + "a" + &")".repeat(30);
+        let even = RegexBuilder::new(&complements).extended(true).build().unwrap();
+        assert!(even.is_match("a"));
+
+        let intersections = "(?:".repeat(30) + "[ab]*" + &")&[ab]*".repeat(30);
+        let words = RegexBuilder::new(&intersections).extended(true).build().unwrap();
+        assert_eq!(words.find("xabbx").map(|found| found.as_str()), Some(""));
+        assert!(words.is_match("abba"));
+    }
+
+    #[test]
+    fn inline_flags() {
+        assert_eq!(spans("a(?i)b", "aB AB ab"), vec![(0, 2), (6, 8)]);
+        assert_eq!(spans("(?i:a)b", "Ab AB"), vec![(0, 2)]);
+        assert_eq!(spans("((?i)a)b", "AB Ab"), vec![(3, 5)]);
+        assert_eq!(spans("(?i)[^x]", "xXy"), vec![(2, 3)]);
+        assert_eq!(spans("(?is)a.", "A\n"), vec![(0, 2)]);
+        assert_eq!(spans("(?m)^a(?-m)$", "a\na"), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn case_folding_is_symmetric() {
+        for (a, b) in [("k", "\u{212A}"), ("K", "\u{212A}"), ("s", "ſ"), ("S", "ſ")] {
+            assert!(regex(&format!("(?i){}", a)).is_match(b), "(?i){} matches {}", a, b);
+            assert!(regex(&format!("(?i){}", b)).is_match(a), "(?i){} matches {}", b, a);
+        }
+    }
+
+    #[test]
+    fn verbose_mode() {
+        let float = regex("(?x)
+            [0-9]+        # integer part
+            (?: \\. [0-9]+ )? # fraction
+            (?: e [0-9]+ )?
+        ");
+        assert_eq!(float.find("x = 1.5e3;").map(|found| found.as_str()), Some("1.5e3"));
+        assert!(regex("(?x)a\\ b").is_match("a b"));
+        assert!(regex("(?x)a[ ]b").is_match("a b"));
+        assert!(!regex("(?x)a b").is_match("a b"));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let pattern = "(a|b";
+        let Err(error) = Regex::new(pattern) else {
+            panic!("pattern is invalid")
+        };
+        assert_eq!(error.offset(), 0);
+    }
+
+    #[test]
+    fn lazy_dfa_agrees_with_simulation() {
+        let patterns = ["a|ab", "(a|b)*abb", "\\bx*\\b", "(?m)^[a-c]+$", "[0-9]*\\.?[0-9]+", "a*?b|c?"];
+        let haystacks = ["", "ab", "aabbabb x xx", "ab\ncab\nd", "3.14 .5 7.", "cbaab"];
+        for pattern in patterns {
+            let regex = regex(pattern);
+            for haystack in haystacks {
+                for start in 0..=haystack.len() {
+                    let expected = Simulation::new(regex.nfa()).find_at(haystack, start, false);
+                    assert_eq!(regex.find_at(haystack, start), expected, "{} on {:?}", pattern, haystack);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn short_patterns_use_shift_and() {
+        let keywords = regex("if|else|while|for");
+        assert!(keywords.shift_and.is_some());
+        assert!(keywords.is_match("} else {"));
+        assert!(!keywords.is_match("x = y;"));
+        assert!(regex("^if").shift_and.is_none());
+        assert!(regex("[a-z]{200}").shift_and.is_none());
+    }
+}
@@ -0,0 +1,215 @@
+use std::ops::{BitAnd, BitOr};
+
+use crate::alphabet::Alphabet;
+use crate::ast::Expr;
+use crate::glushkov::Glushkov;
+
+// positions of the largest pattern the engine takes
+pub(crate) const MAX_POSITIONS: usize = 128;
+
+// bits of a mask that share one follow table
+const CHUNK_BITS: usize = 8;
+
+// Bit-parallel simulation of the Glushkov automaton of a pattern, the Shift-And
+// algorithm generalized from strings to regular expressions. Every position is a bit,
+// so the set of active positions fits into a single u64 or u128 and a step is a few
+// word operations instead of a walk over NFA states:
+//
+//     active = (follow(active) | first) & mask(char)
+//
+// mask(char) has the bits of the positions whose class contains char. For a string
+// follow(active) is active << 1, which gives Shift-And its name. In general
+// follow(active) is the union of the follow sets of the active positions, it is looked
+// up a byte of the mask at a time in tables that hold the union for every byte value.
+//
+// The engine has no notion of priority between alternatives, so it only answers
+// whether something matches and where the earliest match ends.
+pub(crate) enum ShiftAnd {
+    Narrow(Engine<u64>),
+    Wide(Engine<u128>)
+}
+
+impl ShiftAnd {
+    // None when expr has assertions or more than MAX_POSITIONS positions
+    pub(crate) fn new(expr: &Expr) -> Option<ShiftAnd> {
+        let glushkov = Glushkov::from_expr(expr)?;
+        match glushkov.positions() {
+            0..=64 => Some(ShiftAnd::Narrow(Engine::new(&glushkov))),
+            65..=MAX_POSITIONS => Some(ShiftAnd::Wide(Engine::new(&glushkov))),
+            _ => None
+        }
+    }
+
+    pub(crate) fn is_match(&self, haystack: &str) -> bool {
+        self.find_end(haystack, 0).is_some()
+    }
+
+    // end of the match that ends first among the ones starting at or after start
+    pub(crate) fn find_end(&self, haystack: &str, start: usize) -> Option<usize> {
+        match self {
+            ShiftAnd::Narrow(engine) => engine.find_end(haystack, start),
+            ShiftAnd::Wide(engine) => engine.find_end(haystack, start)
+        }
+    }
+}
+
+// set of positions, bit i is position i
+pub(crate) trait Mask: Copy + Eq + BitAnd<Output = Self> + BitOr<Output = Self> {
+    const ZERO: Self;
+
+    fn bit(position: usize) -> Self;
+    // bits index * CHUNK_BITS.. of the mask
+    fn chunk(self, index: usize) -> usize;
+}
+
+impl Mask for u64 {
+    const ZERO: u64 = 0;
+
+    fn bit(position: usize) -> u64 {
+        1 << position
+    }
+
+    fn chunk(self, index: usize) -> usize {
+        (self >> (index * CHUNK_BITS)) as usize & 0xff
+    }
+}
+
+impl Mask for u128 {
+    const ZERO: u128 = 0;
+
+    fn bit(position: usize) -> u128 {
+        1 << position
+    }
+
+    fn chunk(self, index: usize) -> usize {
+        (self >> (index * CHUNK_BITS)) as usize & 0xff
+    }
+}
+
+pub(crate) struct Engine<M> {
+    alphabet: Alphabet,
+    // positions of every class of alphabet
+    masks: Vec<M>,
+    // follow[chunk][byte] is the union of the follow sets of the positions in byte
+    follow: Vec<[M; 256]>,
+    first: M,
+    last: M,
+    nullable: bool
+}
+
+impl<M: Mask> Engine<M> {
+    fn new(glushkov: &Glushkov) -> Engine<M> {
+        let positions = glushkov.positions();
+        let set = |positions: &[usize]| positions
+            .iter()
+            .fold(M::ZERO, |mask, position| mask | M::bit(*position));
+
+        let alphabet = Alphabet::from_classes((0..positions).map(|position| glushkov.class(position)), false);
+        let masks = (0..alphabet.len())
+            .map(|class| {
+                let char = alphabet.representative(class);
+                (0..positions)
+                    .filter(|position| glushkov.class(*position).contains(char))
+                    .fold(M::ZERO, |mask, position| mask | M::bit(position))
+            })
+            .collect();
+
+        let follow = (0..positions.div_ceil(CHUNK_BITS))
+            .map(|chunk| {
+                let mut table = [M::ZERO; 256];
+                for (byte, union) in table.iter_mut().enumerate() {
+                    *union = (0..CHUNK_BITS)
+                        .filter(|bit| byte & (1 << bit) != 0)
+                        .map(|bit| chunk * CHUNK_BITS + bit)
+                        .filter(|position| *position < positions)
+                        .fold(M::ZERO, |union, position| union | set(glushkov.follow(position)));
+                }
+                table
+            })
+            .collect();
+
+        Engine {
+            alphabet,
+            masks,
+            follow,
+            first: set(glushkov.first()),
+            last: set(glushkov.last()),
+            nullable: glushkov.nullable()
+        }
+    }
+
+    fn find_end(&self, haystack: &str, start: usize) -> Option<usize> {
+        if self.nullable {
+            return Some(start);
+        }
+        let mut active = M::ZERO;
+        for (offset, char) in haystack[start..].char_indices() {
+            let mask = match self.alphabet.class_of(char) {
+                Some(class) => self.masks[class],
+                None => M::ZERO
+            };
+            // a new match may start at every character
+            active = (self.follow(active) | self.first) & mask;
+            if active & self.last != M::ZERO {
+                return Some(start + offset + char.len_utf8());
+            }
+        }
+        None
+    }
+
+    fn follow(&self, active: M) -> M {
+        self.follow
+            .iter()
+            .enumerate()
+            .fold(M::ZERO, |union, (chunk, table)| union | table[active.chunk(chunk)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+    use crate::simulation::Simulation;
+    use crate::thompson::NFA;
+
+    use super::ShiftAnd;
+
+    fn shift_and(pattern: &str) -> Option<ShiftAnd> {
+        let Ok(expr) = parse(pattern) else {
+            panic!("failed to parse {}", pattern)
+        };
+        ShiftAnd::new(&expr)
+    }
+
+    #[test]
+    fn agrees_with_simulation() {
+        let patterns = ["abc", "(a|b)*abb", "a{2,3}b*|[ab]{3}", "\\d+\\.\\d*", "x?y?", "if|else|while", "é+ü"];
+        let haystacks = ["", "abc", "xxabcxx", "aabbabb", "ababab", "1.5", "12", "while(x)", "elsé", "ééü", "y"];
+        for pattern in patterns {
+            let Some(engine) = shift_and(pattern) else {
+                panic!("{} fits", pattern)
+            };
+            let Ok(nfa) = NFA::from_pattern(pattern) else {
+                panic!("failed to compile {}", pattern)
+            };
+            for haystack in haystacks {
+                let expected = Simulation::new(&nfa).find_at(haystack, 0, true).map(|(_, end)| end);
+                assert_eq!(engine.find_end(haystack, 0), expected, "{} on {}", pattern, haystack);
+            }
+        }
+    }
+
+    #[test]
+    fn picks_mask_width_by_positions() {
+        assert!(matches!(shift_and("a{64}"), Some(ShiftAnd::Narrow(_))));
+        assert!(matches!(shift_and("a{65}"), Some(ShiftAnd::Wide(_))));
+        assert!(shift_and("a{129}").is_none());
+        assert!(shift_and("^abc").is_none());
+
+        let Some(wide) = shift_and("(?:ab){50}c") else {
+            panic!("101 positions fit")
+        };
+        let haystack = "ab".repeat(60) + "c";
+        assert_eq!(wide.find_end(&haystack, 0), Some(haystack.len()));
+        assert!(!wide.is_match(&"ab".repeat(60)));
+    }
+}
@@ -0,0 +1,271 @@
+use std::iter;
+use std::mem;
+
+use crate::look::Context;
+use crate::thompson::{StateId, NFA};
+use crate::utils::sparse_set::SparseSet;
+
+// Pike VM, i.e. Thompson's simulation of an NFA whose threads carry capture
+// slots. All threads advance in lockstep over the input, so it is scanned
+// exactly once and every step costs at most O(states), no backtracking is involved.
+//
+// Threads are kept in priority order, a thread that reaches the accept state
+// kills every thread behind it. This gives leftmost-first semantics, i.e. the
+// match and the groups that a backtracking engine would report.
+pub(crate) struct Simulation<'n> {
+    nfa: &'n NFA,
+    current: Threads,
+    next: Threads,
+    stack: Vec<Frame>,
+    // slots of the thread that is being added
+    scratch: Vec<Option<usize>>
+}
+
+// active states and the slots of the thread in each of them, slot 0 and 1
+// hold start and end of the match, the other ones start and end of the groups
+struct Threads {
+    states: SparseSet,
+    slots: Vec<Option<usize>>,
+    stride: usize
+}
+
+impl Threads {
+    fn new(capacity: usize, stride: usize) -> Threads {
+        Threads {
+            states: SparseSet::new(capacity),
+            slots: vec![None; capacity * stride],
+            stride
+        }
+    }
+
+    fn slots(&self, state: StateId) -> &[Option<usize>] {
+        &self.slots[state * self.stride..(state + 1) * self.stride]
+    }
+
+    fn slots_mut(&mut self, state: StateId) -> &mut [Option<usize>] {
+        &mut self.slots[state * self.stride..(state + 1) * self.stride]
+    }
+}
+
+enum Frame {
+    Explore(StateId),
+    // sets a slot of the scratch thread, the old value is set back the same way
+    // once everything behind a capture edge has been explored
+    SetSlot(usize, Option<usize>)
+}
+
+impl<'n> Simulation<'n> {
+    // simulation that only tracks where matches start and end
+    pub(crate) fn new(nfa: &'n NFA) -> Simulation<'n> {
+        Simulation::with_slots(nfa, 2)
+    }
+
+    // simulation that tracks every capture group as well
+    pub(crate) fn with_captures(nfa: &'n NFA) -> Simulation<'n> {
+        Simulation::with_slots(nfa, 2 * nfa.captures_len())
+    }
+
+    fn with_slots(nfa: &'n NFA, stride: usize) -> Simulation<'n> {
+        Simulation {
+            nfa,
+            current: Threads::new(nfa.len(), stride),
+            next: Threads::new(nfa.len(), stride),
+            stack: Vec::new(),
+            scratch: vec![None; stride]
+        }
+    }
+
+    // finds leftmost-first match that starts at or after start. With earliest set
+    // it stops at the first accept state it sees, which is enough to answer is_match.
+    pub(crate) fn find_at(&mut self, haystack: &str, start: usize, earliest: bool) -> Option<(usize, usize)> {
+        let slots = self.captures_at(haystack, start, earliest)?;
+        Some((slots[0]?, slots[1]?))
+    }
+
+    // slots of the leftmost-first match that starts at or after start,
+    // groups that did not take part in the match are None
+    pub(crate) fn captures_at(&mut self, haystack: &str, start: usize, earliest: bool) -> Option<Vec<Option<usize>>> {
+        self.current.states.clear();
+        let mut matched = None;
+
+        let mut chars = haystack[start..]
+            .char_indices()
+            .map(|(offset, char)| (start + offset, Some(char)))
+            .chain(iter::once((haystack.len(), None)))
+            .peekable();
+        // assertions look at the characters on both sides of a position
+        let mut prev = Context::of(haystack[..start].chars().next_back());
+
+        while let Some((at, char)) = chars.next() {
+            let next = Context::of(char);
+            // once there is a match no thread that starts later can be preferred
+            if matched.is_none() {
+                self.scratch.fill(None);
+                self.scratch[0] = Some(at);
+                let context = (prev, next);
+                add_thread(self.nfa, &mut self.current, &mut self.stack, &mut self.scratch, self.nfa.start(), at, context);
+            }
+            if self.current.states.is_empty() {
+                break;
+            }
+
+            self.next.states.clear();
+            let after = Context::of(chars.peek().and_then(|(_, char)| *char));
+            for state in self.current.states.iter() {
+                if *state == self.nfa.accept() {
+                    let mut slots = self.current.slots(*state).to_vec();
+                    slots[1] = Some(at);
+                    matched = Some(slots);
+                    if earliest {
+                        return matched;
+                    }
+                    // threads with lower priority can not win anymore
+                    break;
+                }
+                let Some(char) = char else {
+                    continue;
+                };
+                for transition in self.nfa.transitions(*state) {
+                    if transition.symbol().matches(char) {
+                        self.scratch.copy_from_slice(self.current.slots(*state));
+                        let (at, context) = (at + char.len_utf8(), (next, after));
+                        add_thread(self.nfa, &mut self.next, &mut self.stack, &mut self.scratch, transition.next(), at, context);
+                    }
+                }
+            }
+            mem::swap(&mut self.current, &mut self.next);
+            prev = next;
+        }
+
+        matched
+    }
+}
+
+// Adds state and everything reachable from it through epsilon transitions and
+// look transitions that hold between the (previous, next) context. States are
+// added depth first so the order of epsilon transitions is their priority.
+// Every added state gets a copy of slots, with capture edges on the way to it
+// recording position at.
+fn add_thread(
+    nfa: &NFA,
+    threads: &mut Threads,
+    stack: &mut Vec<Frame>,
+    slots: &mut [Option<usize>],
+    state: StateId,
+    at: usize,
+    (prev, next): (Context, Context)
+) {
+    stack.push(Frame::Explore(state));
+    while let Some(frame) = stack.pop() {
+        let state = match frame {
+            Frame::Explore(state) => state,
+            Frame::SetSlot(slot, value) => {
+                slots[slot] = value;
+                continue;
+            }
+        };
+        if !threads.states.insert(state) {
+            continue;
+        }
+        threads.slots_mut(state).copy_from_slice(slots);
+
+        for transition in nfa.transitions(state).rev() {
+            let symbol = transition.symbol();
+            match symbol.slot() {
+                Some(slot) if slot < slots.len() => {
+                    stack.push(Frame::SetSlot(slot, slots[slot]));
+                    stack.push(Frame::Explore(transition.next()));
+                    stack.push(Frame::SetSlot(slot, Some(at)));
+                },
+                _ if symbol.is_epsilon() || symbol.look().is_some_and(|look| look.holds(prev, next)) => {
+                    stack.push(Frame::Explore(transition.next()));
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_with, Config};
+    use crate::thompson::NFA;
+
+    use super::Simulation;
+
+    fn find(pattern: &str, haystack: &str) -> Option<(usize, usize)> {
+        let Ok(nfa) = NFA::from_pattern(pattern) else {
+            panic!("failed to compile {}", pattern)
+        };
+        Simulation::new(&nfa).find_at(haystack, 0, false)
+    }
+
+    #[test]
+    fn leftmost_match_wins() {
+        assert_eq!(find("b+", "abbcbbb"), Some((1, 3)));
+        assert_eq!(find("x", "abc"), None);
+    }
+
+    #[test]
+    fn alternation_prefers_left_branch() {
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("ab|a", "ab"), Some((0, 2)));
+    }
+
+    #[test]
+    fn quantifiers_are_greedy() {
+        assert_eq!(find("a*", "aaab"), Some((0, 3)));
+        assert_eq!(find("a?a", "aa"), Some((0, 2)));
+        assert_eq!(find("(a|b){1,2}", "bab"), Some((0, 2)));
+    }
+
+    #[test]
+    fn empty_match() {
+        assert_eq!(find("a*", "bbb"), Some((0, 0)));
+        assert_eq!(find("", ""), Some((0, 0)));
+    }
+
+    #[test]
+    fn linear_on_pathological_pattern() {
+        // a backtracking engine needs 2^n steps for this
+        let pattern = format!("{}{}", "a?".repeat(30), "a".repeat(30));
+        let haystack = "a".repeat(30);
+        assert_eq!(find(&pattern, &haystack), Some((0, 30)));
+    }
+
+    #[test]
+    fn text_anchors() {
+        assert_eq!(find("^a", "aa"), Some((0, 1)));
+        assert_eq!(find("^b", "ab"), None);
+        assert_eq!(find("a$", "aa"), Some((1, 2)));
+        assert_eq!(find("^$", ""), Some((0, 0)));
+        assert_eq!(find("a|^b", "cb"), None);
+    }
+
+    #[test]
+    fn line_anchors() {
+        let config = Config::new().multi_line(true);
+        let Ok(expr) = parse_with("^b$", &config) else {
+            panic!("failed to parse")
+        };
+        let nfa = NFA::from_expr(&expr);
+        assert_eq!(Simulation::new(&nfa).find_at("a\nb\nc", 0, false), Some((2, 3)));
+        assert_eq!(find("^b$", "a\nb\nc"), None);
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(find("\\bcat\\b", "concat cat"), Some((7, 10)));
+        assert_eq!(find("\\Bcat", "cat concat"), Some((7, 10)));
+        assert_eq!(find("\\b", "  x"), Some((2, 2)));
+    }
+
+    #[test]
+    fn start_depends_on_previous_char() {
+        let Ok(nfa) = NFA::from_pattern("\\bb") else {
+            panic!("failed to compile")
+        };
+        let mut simulation = Simulation::new(&nfa);
+        assert_eq!(simulation.find_at("ab b", 1, false), Some((3, 4)));
+    }
+}
@@ -1,122 +1,760 @@
-use std::ops::Add;
+use std::fmt::{self, Display};
 
+use crate::ast::{Expr, ThompsonVisitor};
+use crate::alphabet::ClassId;
+use crate::class::{escape_invisible, CharClass, ClassRange};
+use crate::dfa::{self, DFA};
+use crate::error::RegexError;
+use crate::look::Look;
+use crate::parser::parse;
 use crate::utils;
+use utils::graphviz;
 use utils::immutable_graph::Graph;
 
-const INITIAL_CAPACITY: usize = 5; 
-const MULTIPLIER: usize = 2;
+pub type StateId = usize;
 
-type StateId = usize; 
-type Nfa = (usize, usize);
+// Thompson fragment, i.e. piece of NFA with a single entry and a single exit state.
+// Exit state never has outgoing transitions until the fragment is linked into a bigger one.
+pub(crate) type Nfa = (StateId, StateId);
 
-#[derive(Debug, Clone)]
-struct NfaState {
-    id: StateId, 
-    transitions: Vec<Transition>
+// outgoing edge of a state as stored in the graph of the NFA
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Transition<'n> {
+    symbol: &'n Symbol,
+    next: StateId
 }
 
-#[derive(Debug, Clone)]
-struct Transition {
-    symbol: Option<char>,
-    next: StateId
+// label of an NFA edge. Edges that consume input carry a class, i.e. sorted and
+// non-overlapping ranges, so a large class is still a single edge and a literal
+// is a class with one character. Look edges are conditional epsilon edges, they
+// do not consume input and can only be taken where the assertion holds.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Symbol {
+    Epsilon,
+    Class(CharClass),
+    Look(Look),
+    // epsilon edge that records the current position in a capture slot,
+    // group i starts in slot 2i and ends in slot 2i + 1
+    Capture(usize)
+}
+
+impl<'n> Transition<'n> {
+    pub fn symbol(&self) -> &'n Symbol {
+        self.symbol
+    }
+
+    pub fn next(&self) -> StateId {
+        self.next
+    }
 }
 
-impl NfaState {
-    fn new(id: StateId) -> NfaState {
-        NfaState {
-            id,
-            transitions: Vec::with_capacity(INITIAL_CAPACITY)
+impl Symbol {
+    // true if the edge can be taken on char, epsilon edges never consume input
+    pub fn matches(&self, char: char) -> bool {
+        match self {
+            Symbol::Epsilon | Symbol::Look(_) | Symbol::Capture(_) => false,
+            Symbol::Class(class) => class.contains(char)
+        }
+    }
+
+    // edges that are always taken without consuming input, captures only matter
+    // to engines that report groups, for the others they are plain epsilon edges
+    pub fn is_epsilon(&self) -> bool {
+        matches!(self, Symbol::Epsilon | Symbol::Capture(_))
+    }
+
+    pub fn slot(&self) -> Option<usize> {
+        match self {
+            Symbol::Capture(slot) => Some(*slot),
+            _ => None
+        }
+    }
+
+    pub fn look(&self) -> Option<Look> {
+        match self {
+            Symbol::Look(look) => Some(*look),
+            _ => None
+        }
+    }
+
+    // characters the edge can be taken on
+    pub fn ranges(&self) -> &[ClassRange] {
+        match self {
+            Symbol::Epsilon | Symbol::Look(_) | Symbol::Capture(_) => &[],
+            Symbol::Class(class) => class.ranges()
+        }
+    }
+
+    pub fn class(&self) -> Option<&CharClass> {
+        match self {
+            Symbol::Class(class) => Some(class),
+            _ => None
+        }
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbol::Epsilon => write!(f, "ε"),
+            Symbol::Class(class) => match class.ranges() {
+                [range] if range.start() == range.end() => write!(f, "{}", escape_invisible(range.start())),
+                _ => write!(f, "{}", class)
+            },
+            Symbol::Look(look) => write!(f, "{}", look),
+            Symbol::Capture(slot) if slot % 2 == 0 => write!(f, "({}", slot / 2),
+            Symbol::Capture(slot) => write!(f, "{})", slot / 2)
         }
     }
 }
 
+// Thompson NFA, states are numbered in the order they were created.
+// It always has exactly one start and one accept state.
 pub struct NFA {
-    states: Graph<NfaState>
+    states: Graph<(), Symbol>,
+    start: StateId,
+    accept: StateId,
+    // names of capture groups by their index, group 0 is the whole match
+    captures: Vec<Option<String>>
 }
 
 impl NFA {
     pub fn new() -> NFA {
-        NFA { 
-            states: Graph::new()
+        let mut nfa = NFA {
+            states: Graph::new(),
+            start: 0,
+            accept: 0,
+            captures: vec![None]
+        };
+        // NFA for the empty expression, start state is also accepting
+        nfa.add_state();
+        nfa
+    }
+
+    pub fn from_expr(expr: &Expr) -> NFA {
+        let mut thompson = ThompsonVisitor::new();
+        thompson.visit(expr);
+        thompson.into_nfa()
+    }
+
+    pub fn from_pattern(pattern: &str) -> Result<NFA, RegexError> {
+        parse(pattern).map(|expr| NFA::from_expr(&expr))
+    }
+
+    pub fn start(&self) -> StateId {
+        self.start
+    }
+
+    pub fn accept(&self) -> StateId {
+        self.accept
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.length()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // true if some edge is conditioned on an assertion
+    pub fn has_looks(&self) -> bool {
+        (0..self.len()).any(|state| {
+            self.transitions(state)
+                .any(|transition| transition.symbol().look().is_some())
+        })
+    }
+
+    pub fn transitions(&self, state: StateId) -> impl DoubleEndedIterator<Item = Transition<'_>> {
+        self.states
+            .edges(state)
+            .map(|(next, symbol)| Transition { symbol, next })
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.states = Graph::new();
+        self.captures = vec![None];
+    }
+
+    // number of capture groups including the implicit group 0
+    pub fn captures_len(&self) -> usize {
+        self.captures.len()
+    }
+
+    pub fn capture_names(&self) -> &[Option<String>] {
+        &self.captures
+    }
+
+    pub(crate) fn set_fragment(&mut self, nfa: Nfa) {
+        self.start = nfa.0;
+        self.accept = nfa.1;
+    }
+
+    pub fn add_state(&mut self) -> StateId {
+        self.states.add_node(())
+    }
+
+    pub fn add_transition(&mut self, from: StateId, to: StateId, symbol: Symbol) {
+        self.states.add_edge(from, to, symbol);
+    }
+
+    fn epsilon(&mut self, from: StateId, to: StateId) {
+        self.add_transition(from, to, Symbol::Epsilon);
+    }
+
+    // Fragment constructions, each of them takes fragments built so far and
+    // links them together with epsilon transitions.
+
+    // fragment matching the empty string
+    pub(crate) fn empty(&mut self) -> Nfa {
+        let state = self.add_state();
+        (state, state)
+    }
+
+    // fragment matching a single character out of symbol
+    pub(crate) fn symbol(&mut self, symbol: Symbol) -> Nfa {
+        let from = self.add_state();
+        let to = self.add_state();
+        self.add_transition(from, to, symbol);
+        (from, to)
+    }
+
+    pub(crate) fn concatenation(&mut self, left_nfa: Nfa, right_nfa: Nfa) -> Nfa {
+        self.epsilon(left_nfa.1, right_nfa.0);
+        (left_nfa.0, right_nfa.1)
+    }
+
+    // alternatives are tried in the order of nfas
+    pub(crate) fn alternation(&mut self, nfas: &[Nfa]) -> Nfa {
+        let start = self.add_state();
+        for nfa in nfas {
+            self.epsilon(start, nfa.0);
+        }
+
+        let end = self.add_state();
+        for nfa in nfas {
+            self.epsilon(nfa.1, end);
         }
+
+        (start, end)
     }
 
-    pub fn add_state(&mut self) {
-        let state_id = self.states.length();
-        let state = NfaState::new(state_id);
-        self.states.add_node(state);
+    // e*
+    pub(crate) fn repetition(&mut self, nfa: Nfa) -> Nfa {
+        let start = self.add_state();
+        let end = self.add_state();
+        self.epsilon(start, nfa.0);
+        self.epsilon(start, end);
+        self.epsilon(nfa.1, nfa.0);
+        self.epsilon(nfa.1, end);
+
+        (start, end)
     }
 
-    pub fn add_transition(&mut self, from: StateId, to: StateId, symbol: Option<char>) {
-        if let Some(state) = self.states.get_mut(from) {
-            state.transitions.push(Transition { symbol, next: to })
-        } 
+    // e+
+    pub(crate) fn one_or_many(&mut self, nfa: Nfa) -> Nfa {
+        let start = self.add_state();
+        let end = self.add_state();
+        self.epsilon(start, nfa.0);
+        self.epsilon(nfa.1, nfa.0);
+        self.epsilon(nfa.1, end);
+
+        (start, end)
     }
-    // // add espilon transition 
 
+    // group with index, its boundaries are recorded in slots 2 * index and 2 * index + 1
+    pub(crate) fn capture(&mut self, nfa: Nfa, index: usize, name: Option<String>) -> Nfa {
+        if self.captures.len() <= index {
+            self.captures.resize(index + 1, None);
+        }
+        self.captures[index] = name;
 
-    // // add character transition  
-    // pub fn character_transition(&mut self, char: char) -> Nfa {
-    //     let from = self.nfa_states.add_node(StateId::Character(char));
-    //     let to = self.nfa_states.add_node(StateId::Accept);
-    //     self.nfa_states.add_edge(from, to);
-    //     (from, to)
-    // }
+        let start = self.add_state();
+        let end = self.add_state();
+        self.add_transition(start, nfa.0, Symbol::Capture(2 * index));
+        self.add_transition(nfa.1, end, Symbol::Capture(2 * index + 1));
 
-    // // add concatentation
-    // pub fn concatenation(&mut self, left_nfa: Nfa, right_nfa: Nfa) -> Nfa {
-    //     if let Some(state_id@StateId::Accept) = self.nfa_states.get_mut(left_nfa.1) {
-    //         *state_id = StateId::Epsilon;
-    //         self.nfa_states.add_edge(left_nfa.1, right_nfa.0);
-    //     }
-    //     (left_nfa.0, right_nfa.1)
-    // }
+        (start, end)
+    }
 
-    // // 
-    // pub fn alternation(&mut self, top_nfa: Nfa, bottom_nfa: Nfa) -> Nfa {
-    //     let start = self.nfa_states.add_node(StateId::DoubleEpsilon);
-    //     self.nfa_states.add_edge(start, top_nfa.0);
-    //     self.nfa_states.add_edge(start, bottom_nfa.0);
+    // e?
+    pub(crate) fn zero_or_one(&mut self, nfa: Nfa) -> Nfa {
+        let start = self.add_state();
+        let end = self.add_state();
+        self.epsilon(start, nfa.0);
+        self.epsilon(start, end);
+        self.epsilon(nfa.1, end);
 
-    //     let end = self.nfa_states.add_node(StateId::Accept);
+        (start, end)
+    }
 
-    //     if let Some(state_id@StateId::Accept) = self.nfa_states.get_mut(top_nfa.1) {
-    //         *state_id = StateId::Epsilon;
-    //         self.nfa_states.add_edge(top_nfa.1, end);
-    //     }
+    // e{min}, e{min,} and e{min,max}. Fragment of e occupies states first..self.len(),
+    // because fragments are built bottom up, so the copies of e are made by duplicating that range.
+    // Mandatory copies are concatenated and optional ones are nested,
+    // e{2,4} becomes ee(e(e)?)?, while e{2,} becomes ee+.
+    pub(crate) fn bounded(&mut self, nfa: Nfa, first: StateId, min: u32, max: Option<u32>) -> Nfa {
+        let copies = max.unwrap_or(min).max(1) as usize;
+        if max == Some(0) {
+            return self.empty();
+        }
 
-    //     if let Some(state_id@StateId::Accept) = self.nfa_states.get_mut(bottom_nfa.1) {
-    //         *state_id = StateId::Epsilon;
-    //         self.nfa_states.add_edge(bottom_nfa.1, end);
-    //     }
+        let last = self.len();
+        let mut fragments = Vec::with_capacity(copies);
+        fragments.push(nfa);
+        for _ in 1..copies {
+            let offset = self.copy_states(first, last);
+            fragments.push((nfa.0 + offset, nfa.1 + offset));
+        }
 
-    //     (start, end)
-    // }
+        let min = min as usize;
+        let Some(_) = max else {
+            // the last mandatory copy (or the only one for e{0,}) is repeated
+            let looped = fragments.pop().unwrap();
+            let looped = if min == 0 {
+                self.repetition(looped)
+            } else {
+                self.one_or_many(looped)
+            };
+            return fragments
+                .into_iter()
+                .rev()
+                .fold(looped, |right, left| self.concatenation(left, right));
+        };
 
-    // pub fn repetition(&mut self, nfa: Nfa) -> Nfa {
-    //     if let Some(state_id@StateId::Accept) = self.nfa_states.get_mut(nfa.1) {
-    //         *state_id = StateId::DoubleEpsilon;
-    //         self.nfa_states.add_edge(nfa.1, nfa.0);
-    //     }
+        let mut optional = fragments.split_off(min);
+        let mut tail = None;
+        while let Some(fragment) = optional.pop() {
+            let fragment = match tail {
+                Some(tail) => self.concatenation(fragment, tail),
+                None => fragment
+            };
+            tail = Some(self.zero_or_one(fragment));
+        }
 
-    //     let start = self.nfa_states.add_node(StateId::DoubleEpsilon);
-    //     self.nfa_states.add_edge(start, nfa.0);
-    //     let end = self.nfa_states.add_node(StateId::Accept);
-    //     self.nfa_states.add_edge(start, end);
+        let mandatory = fragments
+            .into_iter()
+            .reduce(|left, right| self.concatenation(left, right));
+        match (mandatory, tail) {
+            (Some(mandatory), Some(tail)) => self.concatenation(mandatory, tail),
+            (Some(fragment), None) | (None, Some(fragment)) => fragment,
+            (None, None) => self.empty()
+        }
+    }
 
-    //     (start, end)
-    // }
+    // Fragment with a state for every state of dfa, which is how operands of & and ~ get
+    // into the NFA once they are combined as DFAs. A DFA state can only move on one class
+    // per character, so the edges to the same state are merged into one. Accepting states
+    // lead to the end of the fragment after their other edges, like e* they prefer
+    // to go on matching.
+    pub(crate) fn dfa(&mut self, dfa: &DFA<dfa::StateId, ClassId>) -> Nfa {
+        let first = self.len();
+        for _ in 0..dfa.number_of_states() {
+            self.add_state();
+        }
+        let end = self.add_state();
+        for state in 0..dfa.number_of_states() {
+            let mut targets: Vec<(dfa::StateId, CharClass)> = Vec::new();
+            for class in dfa.alphabet() {
+                let Some(to) = dfa.transition_from_on(&state, *class) else {
+                    continue;
+                };
+                let chars = dfa.classes().class(*class);
+                match targets.iter_mut().find(|(target, _)| target == to) {
+                    Some((_, union)) => union.union(chars),
+                    None => targets.push((*to, chars.clone()))
+                }
+            }
+            for (to, class) in targets {
+                self.add_transition(first + state, first + to, Symbol::Class(class));
+            }
+            if dfa.is_accepting(&state) {
+                self.epsilon(first + state, end);
+            }
+        }
+        (first + dfa.start(), end)
+    }
+
+    // standalone NFA of fragment nfa, e.g. to turn it into a DFA. Only the states
+    // reachable from its entry are copied, renumbered in breadth first order.
+    pub(crate) fn fragment(&self, nfa: Nfa) -> NFA {
+        let mut ids = vec![None; self.len()];
+        let mut fragment = NFA {
+            states: Graph::new(),
+            start: 0,
+            accept: 0,
+            captures: vec![None]
+        };
+        for state in self.states.bfs(nfa.0).chain([nfa.1]) {
+            if ids[state].is_none() {
+                ids[state] = Some(fragment.add_state());
+            }
+        }
+        for (state, copy) in ids.iter().enumerate() {
+            if let Some(copy) = *copy {
+                for transition in self.transitions(state) {
+                    if let Some(next) = ids[transition.next] {
+                        fragment.add_transition(copy, next, transition.symbol.clone());
+                    }
+                }
+            }
+        }
+        fragment.start = ids[nfa.0].unwrap_or_default();
+        fragment.accept = ids[nfa.1].unwrap_or_default();
+        fragment
+    }
+
+    // drops states from len on, the ones of fragments that are not needed any more
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.states.truncate(len);
+    }
+
+    // duplicates states first..last at the end of the NFA, returns offset between original and copy
+    fn copy_states(&mut self, first: StateId, last: StateId) -> usize {
+        let offset = self.len() - first;
+        for _ in first..last {
+            self.add_state();
+        }
+        for state in first..last {
+            let transitions = self.transitions(state)
+                .map(|transition| (transition.next + offset, transition.symbol.clone()))
+                .collect::<Vec<_>>();
+            for (next, symbol) in transitions {
+                self.add_transition(state + offset, next, symbol);
+            }
+        }
+        offset
+    }
+
+    // NFA running all of nfas side by side, its start state has an epsilon edge to each of
+    // them in order, so earlier NFAs have priority. Accept states of the parts stay separate,
+    // accepts[i] is the one of nfas[i]. The accept state of the union itself has no incoming
+    // edges and is never reached.
+    pub(crate) fn tagged_union(nfas: &[NFA]) -> (NFA, Vec<StateId>) {
+        let mut union = NFA::new();
+        let start = union.start();
+        union.accept = union.add_state();
+
+        let mut accepts = Vec::with_capacity(nfas.len());
+        for nfa in nfas {
+            let offset = union.len();
+            for _ in 0..nfa.len() {
+                union.add_state();
+            }
+            for from in 0..nfa.len() {
+                for transition in nfa.transitions(from) {
+                    union.add_transition(from + offset, transition.next() + offset, transition.symbol().clone());
+                }
+            }
+            union.epsilon(start, nfa.start() + offset);
+            accepts.push(nfa.accept() + offset);
+        }
+        (union, accepts)
+    }
+
+    // NFA for the reversed language, every edge points the other way and start and
+    // accept are swapped. Captures become plain epsilon edges.
+    pub fn reverse(&self) -> NFA {
+        let mut reversed = NFA::new();
+        reversed.clear();
+        for _ in 0..self.len() {
+            reversed.add_state();
+        }
+        for from in 0..self.len() {
+            for transition in self.transitions(from) {
+                let symbol = match transition.symbol() {
+                    Symbol::Capture(_) => Symbol::Epsilon,
+                    Symbol::Look(look) => Symbol::Look(look.reversed()),
+                    symbol => symbol.clone()
+                };
+                reversed.add_transition(transition.next(), from, symbol);
+            }
+        }
+        reversed.start = self.accept;
+        reversed.accept = self.start;
+        reversed
+    }
 
+    // DOT description of the NFA, accept state is drawn with a double circle
     pub fn to_graphviz_string(&self) -> String {
         let mut graph = String::new();
-        graph.push_str("digraph finite_state_machine {\n");
-        graph.push_str("fontname=\"Helvetica,Arial,sans-serif\"\n");
-        graph.push_str("node [fontname=\"Helvetica,Arial,sans-serif\"]\n");
-        graph.push_str("edge [fontname=\"Helvetica,Arial,sans-serif\"]\n");
+        graphviz::header(&mut graph, "finite_state_machine");
         graph.push_str("rankdir=LR;\n");
-        graph.push_str("node [shape = doublecircle]; 0 3 4 8;\n");
+        graph.push_str(&format!("node [shape = doublecircle]; {};\n", self.accept));
         graph.push_str("node [shape = circle];\n");
+        graph.push_str("__start [shape = point];\n");
+        graph.push_str(&format!("__start -> {};\n", self.start));
+
+        for state in 0..self.len() {
+            for transition in self.transitions(state) {
+                let label = graphviz::escape(&transition.symbol.to_string());
+                graph.push_str(&format!("{} -> {} [label = \"{}\"];\n", state, transition.next, label));
+            }
+        }
+        graph.push_str("}\n");
+        graph
+    }
+}
+
+impl Default for NFA {
+    fn default() -> Self {
+        NFA::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::look::Look;
+
+    use crate::class::CharClass;
+
+    use super::{Symbol, NFA};
+
+    fn char(char: char) -> Symbol {
+        Symbol::Class(CharClass::single(char))
+    }
+
+    fn nfa(pattern: &str) -> NFA {
+        let Ok(nfa) = NFA::from_pattern(pattern) else {
+            panic!("failed to compile {}", pattern)
+        };
+        nfa
+    }
+
+    // all transitions as (from, symbol, to) in order of states
+    fn edges(nfa: &NFA) -> Vec<(usize, Symbol, usize)> {
+        (0..nfa.len())
+            .flat_map(|state| {
+                nfa.transitions(state)
+                    .map(move |transition| (state, transition.symbol().clone(), transition.next()))
+            })
+            .collect()
+    }
+
+    // checks whether NFA accepts the whole input by tracking the set of active states
+    fn accepts(nfa: &NFA, input: &str) -> bool {
+        let closure = |mut states: Vec<usize>| {
+            let mut closed = Vec::new();
+            while let Some(state) = states.pop() {
+                if closed.contains(&state) {
+                    continue;
+                }
+                closed.push(state);
+                nfa.transitions(state)
+                    .filter(|transition| transition.symbol().is_epsilon())
+                    .for_each(|transition| states.push(transition.next()));
+            }
+            closed
+        };
+
+        let mut states = closure(vec![nfa.start()]);
+        for char in input.chars() {
+            let next = states
+                .iter()
+                .flat_map(|state| nfa.transitions(*state))
+                .filter(|transition| transition.symbol().matches(char))
+                .map(|transition| transition.next())
+                .collect();
+            states = closure(next);
+        }
+        states.contains(&nfa.accept())
+    }
+
+    #[test]
+    fn empty_pattern() {
+        let nfa = nfa("");
+        assert_eq!(nfa.len(), 1);
+        assert_eq!(nfa.start(), nfa.accept());
+    }
+
+    #[test]
+    fn literal() {
+        let nfa = nfa("a");
+        assert_eq!(edges(&nfa), vec![(0, char('a'), 1)]);
+        assert_eq!((nfa.start(), nfa.accept()), (0, 1));
+    }
+
+    #[test]
+    fn concatenation() {
+        let nfa = nfa("ab");
+        assert_eq!(edges(&nfa), vec![
+            (0, char('a'), 1),
+            (1, Symbol::Epsilon, 2),
+            (2, char('b'), 3)
+        ]);
+        assert_eq!((nfa.start(), nfa.accept()), (0, 3));
+    }
+
+    #[test]
+    fn alternation() {
+        let nfa = nfa("a|b");
+        assert_eq!(edges(&nfa), vec![
+            (0, char('a'), 1),
+            (1, Symbol::Epsilon, 5),
+            (2, char('b'), 3),
+            (3, Symbol::Epsilon, 5),
+            (4, Symbol::Epsilon, 0),
+            (4, Symbol::Epsilon, 2),
+        ]);
+        assert_eq!((nfa.start(), nfa.accept()), (4, 5));
+    }
+
+    #[test]
+    fn star() {
+        let nfa = nfa("a*");
+        assert_eq!(edges(&nfa), vec![
+            (0, char('a'), 1),
+            (1, Symbol::Epsilon, 0),
+            (1, Symbol::Epsilon, 3),
+            (2, Symbol::Epsilon, 0),
+            (2, Symbol::Epsilon, 3),
+        ]);
+        assert_eq!((nfa.start(), nfa.accept()), (2, 3));
+    }
+
+    #[test]
+    fn plus_and_optional() {
+        let plus = nfa("a+");
+        assert_eq!(edges(&plus), vec![
+            (0, char('a'), 1),
+            (1, Symbol::Epsilon, 0),
+            (1, Symbol::Epsilon, 3),
+            (2, Symbol::Epsilon, 0),
+        ]);
+        let optional = nfa("a?");
+        assert_eq!(edges(&optional), vec![
+            (0, char('a'), 1),
+            (1, Symbol::Epsilon, 3),
+            (2, Symbol::Epsilon, 0),
+            (2, Symbol::Epsilon, 3),
+        ]);
+    }
+
+    #[test]
+    fn non_capturing_group_adds_no_states() {
+        assert_eq!(edges(&nfa("(?:a)")), edges(&nfa("a")));
+        assert_eq!(edges(&nfa("(?:(?:a)b)")), edges(&nfa("ab")));
+    }
+
+    #[test]
+    fn capture_group() {
+        let nfa = nfa("(a)(?<b>b)");
+        assert_eq!(edges(&nfa), vec![
+            (0, char('a'), 1),
+            (1, Symbol::Capture(3), 3),
+            (2, Symbol::Capture(2), 0),
+            (3, Symbol::Epsilon, 6),
+            (4, char('b'), 5),
+            (5, Symbol::Capture(5), 7),
+            (6, Symbol::Capture(4), 4)
+        ]);
+        assert_eq!(nfa.capture_names(), &[None, None, Some("b".to_string())]);
+    }
+
+    #[test]
+    fn class_is_a_single_transition() {
+        let nfa = nfa("[a-z]");
+        assert_eq!(nfa.len(), 2);
+        assert!(nfa.transitions(0).all(|transition| transition.symbol().matches('q')));
+        assert_eq!(nfa.transitions(0).count(), 1);
+    }
+
+    #[test]
+    fn languages() {
+        let nfa = nfa("(a|b)*abb");
+        assert!(accepts(&nfa, "abb"));
+        assert!(accepts(&nfa, "babaabb"));
+        assert!(!accepts(&nfa, "ab"));
+        assert!(!accepts(&nfa, "abba"));
+
+        let nfa = self::nfa("x(yz)+w?");
+        assert!(accepts(&nfa, "xyz"));
+        assert!(accepts(&nfa, "xyzyzw"));
+        assert!(!accepts(&nfa, "x"));
+        assert!(!accepts(&nfa, "xyzww"));
+    }
+
+    #[test]
+    fn graphviz() {
+        let expected = "\
+digraph finite_state_machine {
+fontname=\"Helvetica,Arial,sans-serif\"
+node [fontname=\"Helvetica,Arial,sans-serif\"]
+edge [fontname=\"Helvetica,Arial,sans-serif\"]
+rankdir=LR;
+node [shape = doublecircle]; 3;
+node [shape = circle];
+__start [shape = point];
+__start -> 0;
+0 -> 1 [label = \"\\\"\"];
+1 -> 2 [label = \"ε\"];
+2 -> 3 [label = \"[\\\\-\\\\]a-z]\"];
+}
+";
+        assert_eq!(nfa("\"[a-z\\]\\-]").to_graphviz_string(), expected);
+    }
+
+    #[test]
+    fn counted_repetition() {
+        let exact = nfa("(ab){3}");
+        assert!(accepts(&exact, "ababab"));
+        assert!(!accepts(&exact, "abab"));
+        assert!(!accepts(&exact, "abababab"));
+
+        let bounded = nfa("a{2,4}");
+        assert!(!accepts(&bounded, "a"));
+        assert!(accepts(&bounded, "aa"));
+        assert!(accepts(&bounded, "aaaa"));
+        assert!(!accepts(&bounded, "aaaaa"));
+
+        let unbounded = nfa("a{2,}b");
+        assert!(!accepts(&unbounded, "ab"));
+        assert!(accepts(&unbounded, "aab"));
+        assert!(accepts(&unbounded, "aaaaaab"));
+
+        let optional = nfa("xa{0,2}");
+        assert!(accepts(&optional, "x"));
+        assert!(accepts(&optional, "xaa"));
+        assert!(!accepts(&optional, "xaaa"));
+
+        let star = nfa("a{0,}");
+        assert!(accepts(&star, ""));
+        assert!(accepts(&star, "aaa"));
+
+        assert!(accepts(&nfa("xa{0}y"), "xy"));
+    }
+
+    #[test]
+    fn copies_of_nested_repetition() {
+        let nfa = nfa("(a|b{2}){2}c");
+        assert!(accepts(&nfa, "abbc"));
+        assert!(accepts(&nfa, "bbbbc"));
+        assert!(!accepts(&nfa, "abc"));
+    }
+
+    #[test]
+    fn reverse() {
+        let reversed = nfa("ab*(c|^d)").reverse();
+        assert!(accepts(&reversed, "cbba"));
+        assert!(accepts(&reversed, "ca"));
+        assert!(!accepts(&reversed, "abc"));
+        let looks = edges(&reversed)
+            .into_iter()
+            .filter_map(|(_, symbol, _)| symbol.look())
+            .collect::<Vec<_>>();
+        assert_eq!(looks, vec![Look::EndText]);
+    }
 
-        todo!()
+    #[test]
+    fn assertions_are_look_edges() {
+        let nfa = nfa("^a\\b");
+        assert_eq!(edges(&nfa), vec![
+            (0, Symbol::Look(Look::StartText), 1),
+            (1, Symbol::Epsilon, 2),
+            (2, char('a'), 3),
+            (3, Symbol::Epsilon, 4),
+            (4, Symbol::Look(Look::WordBoundary), 5)
+        ]);
+        assert!(nfa.has_looks());
+        assert!(!super::NFA::from_pattern("ab").is_ok_and(|nfa| nfa.has_looks()));
     }
 }
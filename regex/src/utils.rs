@@ -1 +1,3 @@
-pub(crate) mod immutable_graph;
\ No newline at end of file
+pub(crate) mod graphviz;
+pub(crate) mod immutable_graph;
+pub(crate) mod sparse_set;
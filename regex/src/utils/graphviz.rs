@@ -0,0 +1,38 @@
+// Helpers shared by everything that can be drawn with graphviz.
+
+const FONT: &str = "Helvetica,Arial,sans-serif";
+
+// opens a digraph, it has to be closed with "}" by the caller
+pub(crate) fn header(graph: &mut String, name: &str) {
+    graph.push_str(&format!("digraph {} {{\n", name));
+    graph.push_str(&format!("fontname=\"{}\"\n", FONT));
+    graph.push_str(&format!("node [fontname=\"{}\"]\n", FONT));
+    graph.push_str(&format!("edge [fontname=\"{}\"]\n", FONT));
+}
+
+// escapes label so that it can be placed between double quotes
+pub(crate) fn escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for char in label.chars() {
+        match char {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(char);
+            },
+            char => escaped.push(char)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape;
+
+    #[test]
+    fn quotes_and_backslashes_are_escaped() {
+        assert_eq!(escape("a"), "a");
+        assert_eq!(escape("\""), "\\\"");
+        assert_eq!(escape("[\\]]"), "[\\\\]]");
+    }
+}
@@ -1,67 +1,147 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::VecDeque;
 
-const INITIAL_CAPACITY: usize = 5; 
-const MULTIPLIER: usize = 2;
+pub type NodeId = usize;
 
-
-// This is simple graph backed up by Vector of nodes 
-// somewhat akin to adjacency list 
-pub struct Graph<T> {
+// Directed graph with data on its nodes and labels on its edges, backed by
+// vectors of nodes and of the outgoing edges of every node, akin to an adjacency
+// list. Nodes are numbered in the order they were added and edges keep the order
+// they were added in, parallel edges with different labels are allowed. The NFA
+// keeps its states as nodes and its transitions as edges labelled with symbols.
+#[derive(Debug, Clone)]
+pub struct Graph<T, E = ()> {
     nodes: Vec<T>,
-    edges: HashMap<NodeId, HashSet<NodeId>>
+    // targets and labels of the outgoing edges of every node
+    targets: Vec<Vec<NodeId>>,
+    labels: Vec<Vec<E>>
 }
 
-type NodeId = usize;
-
-impl<T> Graph<T> {
+impl<T, E> Graph<T, E> {
     pub fn new() -> Self {
         Graph {
-            nodes: Vec::with_capacity(INITIAL_CAPACITY),
-            edges: HashMap::with_capacity(INITIAL_CAPACITY)
-            
+            nodes: Vec::new(),
+            targets: Vec::new(),
+            labels: Vec::new()
         }
     }
 
     pub fn length(&self) -> usize {
-        self.length()
+        self.nodes.len()
+    }
+
+    pub fn add_node(&mut self, data: T) -> NodeId {
+        self.nodes.push(data);
+        self.targets.push(Vec::new());
+        self.labels.push(Vec::new());
+        self.nodes.len() - 1
+    }
+
+    // inserts edge only when both nodes are defined
+    pub fn add_edge(&mut self, from: NodeId, to: NodeId, label: E) {
+        if from < self.length() && to < self.length() {
+            self.targets[from].push(to);
+            self.labels[from].push(label);
+        }
     }
 
-    pub fn add_node(&mut self, data: T) {
-        if self.is_full() {
-            self.extend();
+    // drops the nodes from length on together with all edges into them
+    pub fn truncate(&mut self, length: usize) {
+        self.nodes.truncate(length);
+        self.targets.truncate(length);
+        self.labels.truncate(length);
+        for (targets, labels) in self.targets.iter_mut().zip(&mut self.labels) {
+            let mut kept = targets.iter().map(|&target| target < length);
+            labels.retain(|_| kept.next().unwrap_or(false));
+            targets.retain(|&target| target < length);
         }
+    }
 
-        self.nodes.push(data);
+    // targets of the outgoing edges of from together with their labels
+    pub fn edges(&self, from: NodeId) -> impl DoubleEndedIterator<Item = (NodeId, &E)> {
+        let targets = self.targets.get(from).map_or(&[][..], Vec::as_slice);
+        let labels = self.labels.get(from).map_or(&[][..], Vec::as_slice);
+        targets.iter().copied().zip(labels)
     }
 
-    // inserts edge only when two nodes are defined are defined. 
-    pub fn add_edge(&mut self, from: NodeId, to: NodeId) {
-        match (self.nodes.get(from), self.nodes.get(to)) {
-            (Some(_),Some(_)) => {
-                self.edges
-                    .entry(from)
-                    .or_insert(HashSet::with_capacity(INITIAL_CAPACITY))
-                    .insert(to);
-            },
-            _ => {}
+    // nodes reachable from start in breadth first order
+    pub fn bfs(&self, start: NodeId) -> Bfs<'_, T, E> {
+        let mut visited = vec![false; self.length()];
+        let mut queue = VecDeque::new();
+        if start < visited.len() {
+            visited[start] = true;
+            queue.push_back(start);
         }
+        Bfs { graph: self, visited, queue }
+    }
+}
+
+impl<T, E> Default for Graph<T, E> {
+    fn default() -> Self {
+        Graph::new()
     }
+}
+
+pub struct Bfs<'g, T, E> {
+    graph: &'g Graph<T, E>,
+    visited: Vec<bool>,
+    queue: VecDeque<NodeId>
+}
+
+impl<T, E> Iterator for Bfs<'_, T, E> {
+    type Item = NodeId;
 
-    pub fn get(&mut self, index: NodeId) -> Option<&T> {
-        self.nodes.get(index)
+    fn next(&mut self) -> Option<NodeId> {
+        let node = self.queue.pop_front()?;
+        for next in self.graph.targets[node].iter().copied() {
+            if !self.visited[next] {
+                self.visited[next] = true;
+                self.queue.push_back(next);
+            }
+        }
+        Some(node)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Graph;
 
-    pub fn get_mut(&mut self, index: NodeId) -> Option<&mut T> {
-        self.nodes.get_mut(index)
+    // 0 -> 1 -> 2 -> 0, 2 -> 3 -> 4, 3 -> 5
+    fn graph() -> Graph<char, char> {
+        let mut graph = Graph::new();
+        for node in "abcdef".chars() {
+            graph.add_node(node);
+        }
+        for (from, to, label) in [(0, 1, 'x'), (1, 2, 'y'), (2, 0, 'z'), (2, 3, 'x'), (3, 4, 'x'), (3, 5, 'y')] {
+            graph.add_edge(from, to, label);
+        }
+        graph
     }
 
-    fn is_full(&self) -> bool {
-        self.nodes.len() >= self.nodes.capacity() 
+    #[test]
+    fn nodes_and_labelled_edges() {
+        let mut graph = graph();
+        assert_eq!(graph.length(), 6);
+        assert_eq!(graph.add_node('g'), 6);
+        graph.add_edge(6, 42, 'x');
+        assert_eq!(graph.edges(6).count(), 0);
+        assert_eq!(graph.edges(3).collect::<Vec<_>>(), vec![(4, &'x'), (5, &'y')]);
+        assert_eq!(graph.edges(3).next_back(), Some((5, &'y')));
     }
 
-    fn extend(&mut self) {
-        let new_capacity = MULTIPLIER*self.nodes.capacity();
-        self.nodes.reserve_exact(new_capacity);
+    #[test]
+    fn truncate_drops_edges_into_dropped_nodes() {
+        let mut graph = graph();
+        graph.truncate(3);
+        assert_eq!(graph.length(), 3);
+        assert_eq!(graph.edges(2).collect::<Vec<_>>(), vec![(0, &'z')]);
+        assert_eq!(graph.bfs(0).collect::<Vec<_>>(), vec![0, 1, 2]);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn breadth_first() {
+        let graph = graph();
+        assert_eq!(graph.bfs(0).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(graph.bfs(3).collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(graph.bfs(42).count(), 0);
+    }
+}
@@ -0,0 +1,65 @@
+// Set of integers below a fixed capacity that remembers insertion order and
+// can be cleared in constant time (Briggs and Torczon). Simulations use it to
+// keep the list of active NFA states in priority order.
+#[derive(Debug, Clone)]
+pub struct SparseSet {
+    dense: Vec<usize>,
+    sparse: Vec<usize>
+}
+
+impl SparseSet {
+    pub fn new(capacity: usize) -> SparseSet {
+        SparseSet {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity]
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.sparse
+            .get(value)
+            .is_some_and(|index| self.dense.get(*index) == Some(&value))
+    }
+
+    // returns false when value was already in the set
+    pub fn insert(&mut self, value: usize) -> bool {
+        if self.contains(value) {
+            return false;
+        }
+        self.sparse[value] = self.dense.len();
+        self.dense.push(value);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &usize> {
+        self.dense.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SparseSet;
+
+    #[test]
+    fn keeps_insertion_order() {
+        let mut set = SparseSet::new(10);
+        assert!(set.insert(7));
+        assert!(set.insert(2));
+        assert!(!set.insert(7));
+        assert!(set.insert(9));
+        assert_eq!(set.iter().copied().collect::<Vec<_>>(), vec![7, 2, 9]);
+
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.contains(2));
+        assert!(set.insert(2));
+    }
+}